@@ -0,0 +1,9 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS student_parents_parent_id_idx;
+
+DELETE FROM refresh_tokens WHERE parent_id IS NOT NULL;
+ALTER TABLE refresh_tokens DROP CONSTRAINT IF EXISTS refresh_tokens_subject_check;
+ALTER TABLE refresh_tokens DROP COLUMN IF EXISTS parent_id;
+ALTER TABLE refresh_tokens ALTER COLUMN user_id SET NOT NULL;
+
+DROP TABLE IF EXISTS parent_login_tokens;
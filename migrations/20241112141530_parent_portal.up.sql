@@ -0,0 +1,17 @@
+-- Parents sign in to the portal with single-use links sent to their email.
+CREATE TABLE IF NOT EXISTS parent_login_tokens (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    parent_id UUID NOT NULL REFERENCES parents(id) ON DELETE CASCADE,
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Sessions now belong either to a staff user or to a parent.
+ALTER TABLE refresh_tokens ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE refresh_tokens ADD COLUMN IF NOT EXISTS parent_id UUID REFERENCES parents(id) ON DELETE CASCADE;
+ALTER TABLE refresh_tokens ADD CONSTRAINT refresh_tokens_subject_check
+    CHECK ((user_id IS NULL) <> (parent_id IS NULL));
+
+CREATE INDEX IF NOT EXISTS student_parents_parent_id_idx ON student_parents (parent_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS password_reset_tokens;
+DROP TABLE IF EXISTS user_invitations;
+
+DROP INDEX IF EXISTS users_email_lower_idx;
+ALTER TABLE users DROP COLUMN IF EXISTS email;
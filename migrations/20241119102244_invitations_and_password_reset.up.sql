@@ -0,0 +1,25 @@
+-- Staff accounts get an email address so they can be invited and reset their password.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_idx ON users (lower(email));
+
+-- Invitations sent by an admin. The account is only created when the invitee accepts.
+CREATE TABLE IF NOT EXISTS user_invitations (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    email TEXT NOT NULL,
+    role VARCHAR(50) CHECK (role IN ('admin', 'coach')) NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    accepted_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Single-use password reset tokens. Only the SHA-256 of each token is stored.
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
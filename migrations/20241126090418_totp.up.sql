@@ -0,0 +1,20 @@
+-- RFC 6238 second factor. The secret is kept while enrollment is pending and only
+-- enforced once totp_enabled_at is set; totp_last_step stops a code from being replayed.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+
+-- Single-use recovery codes for a lost authenticator. Only the SHA-256 of each code is stored.
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS user_recovery_codes_user_id_idx ON user_recovery_codes (user_id);
+
+-- Admins must enroll before their next session, so sessions opened without it end here.
+UPDATE refresh_tokens SET revoked_at = NOW()
+WHERE revoked_at IS NULL
+  AND user_id IN (SELECT id FROM users WHERE role = 'admin');
@@ -0,0 +1,3 @@
+-- Consecutive failed logins; reaching the configured maximum locks the account until locked_until.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMP WITH TIME ZONE;
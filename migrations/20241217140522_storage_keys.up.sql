@@ -0,0 +1,7 @@
+-- Where the file lives in storage, generated by the server (e.g. 'photos/ab/cd/<uuid>.jpg').
+-- `filename` keeps the name sent by the client, as metadata only. Rows created before
+-- this migration have no key and are still served from the old directories by filename.
+ALTER TABLE photos ADD COLUMN IF NOT EXISTS storage_key TEXT UNIQUE;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS storage_key TEXT UNIQUE;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS storage_key TEXT UNIQUE;
+ALTER TABLE file_metadata ADD COLUMN IF NOT EXISTS storage_key TEXT UNIQUE;
@@ -0,0 +1,17 @@
+-- Resumable (tus) video uploads. The bytes received so far are kept in a temporary file
+-- named after the id. `owner_id` is the user or API key that created the upload; only it
+-- may continue it. `video_id` is set when the upload completes and the video row exists.
+CREATE TABLE IF NOT EXISTS resumable_uploads (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    owner_id UUID NOT NULL,
+    student_id UUID NOT NULL REFERENCES students(id) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    description TEXT,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    video_id UUID REFERENCES videos(id) ON DELETE SET NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS resumable_uploads_expires_at_idx ON resumable_uploads (expires_at);
@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_student_sha256_idx;
+DROP INDEX IF EXISTS videos_student_sha256_idx;
+DROP INDEX IF EXISTS photos_student_sha256_idx;
+ALTER TABLE file_metadata DROP COLUMN IF EXISTS sha256;
+ALTER TABLE documents DROP COLUMN IF EXISTS sha256;
+ALTER TABLE videos DROP COLUMN IF EXISTS sha256;
+ALTER TABLE photos DROP COLUMN IF EXISTS sha256;
@@ -0,0 +1,11 @@
+-- Hex SHA-256 of the stored content, computed while the upload streams in. Rows stored
+-- before this migration are filled in by the integrity check. The same content is kept
+-- only once per student.
+ALTER TABLE photos ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE file_metadata ADD COLUMN IF NOT EXISTS sha256 TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS photos_student_sha256_idx ON photos (student_id, sha256);
+CREATE UNIQUE INDEX IF NOT EXISTS videos_student_sha256_idx ON videos (student_id, sha256);
+CREATE UNIQUE INDEX IF NOT EXISTS documents_student_sha256_idx ON documents (student_id, sha256);
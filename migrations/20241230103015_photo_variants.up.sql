@@ -0,0 +1,13 @@
+-- Resized copies of each photo for the gallery, one per size and format. The objects
+-- live next to the original, under `{original key without extension}/{size}.{ext}`.
+CREATE TABLE IF NOT EXISTS photo_variants (
+    photo_id UUID NOT NULL REFERENCES photos(id) ON DELETE CASCADE,
+    size TEXT NOT NULL,
+    format TEXT NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    bytes BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (photo_id, size, format)
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS photos_student_taken_at_idx;
+ALTER TABLE photos DROP COLUMN IF EXISTS orientation;
+ALTER TABLE photos DROP COLUMN IF EXISTS taken_at;
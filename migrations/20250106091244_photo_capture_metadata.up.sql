@@ -0,0 +1,6 @@
+-- Read from the EXIF data of each photo before it is removed. `taken_at` is the camera's
+-- local clock (EXIF has no time zone); `orientation` is the EXIF value, 1 to 8.
+ALTER TABLE photos ADD COLUMN IF NOT EXISTS taken_at TIMESTAMP;
+ALTER TABLE photos ADD COLUMN IF NOT EXISTS orientation SMALLINT;
+
+CREATE INDEX IF NOT EXISTS photos_student_taken_at_idx ON photos (student_id, taken_at);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS videos_duration_seconds_idx;
+ALTER TABLE videos DROP COLUMN IF EXISTS recorded_at;
+ALTER TABLE videos DROP COLUMN IF EXISTS bitrate;
+ALTER TABLE videos DROP COLUMN IF EXISTS audio_codec;
+ALTER TABLE videos DROP COLUMN IF EXISTS video_codec;
+ALTER TABLE videos DROP COLUMN IF EXISTS frame_rate;
+ALTER TABLE videos DROP COLUMN IF EXISTS height;
+ALTER TABLE videos DROP COLUMN IF EXISTS width;
+ALTER TABLE videos DROP COLUMN IF EXISTS duration_seconds;
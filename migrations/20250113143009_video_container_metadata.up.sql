@@ -0,0 +1,12 @@
+-- Read from the MP4/QuickTime container when the video is uploaded. WebM files and
+-- videos stored before this migration leave them empty.
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS duration_seconds DOUBLE PRECISION;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS width INTEGER;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS height INTEGER;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS frame_rate DOUBLE PRECISION;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS video_codec TEXT;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS audio_codec TEXT;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS bitrate BIGINT;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS recorded_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX IF NOT EXISTS videos_duration_seconds_idx ON videos (duration_seconds);
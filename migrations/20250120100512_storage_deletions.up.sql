@@ -0,0 +1,12 @@
+-- Objects whose records were deleted. Keys are queued in the same transaction as the
+-- delete, removed from storage right after the commit, and retried with backoff until
+-- the backend confirms the removal.
+CREATE TABLE IF NOT EXISTS storage_deletions (
+    storage_key TEXT PRIMARY KEY NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS storage_deletions_next_attempt_at_idx ON storage_deletions (next_attempt_at);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE file_metadata DROP COLUMN IF EXISTS bytes;
+ALTER TABLE documents DROP COLUMN IF EXISTS bytes;
+ALTER TABLE videos DROP COLUMN IF EXISTS bytes;
+ALTER TABLE photos DROP COLUMN IF EXISTS bytes;
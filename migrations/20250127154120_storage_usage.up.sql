@@ -0,0 +1,6 @@
+-- Size of each stored file, summed for storage usage and quotas. Files stored before
+-- this migration get theirs from the next integrity check.
+ALTER TABLE photos ADD COLUMN IF NOT EXISTS bytes BIGINT;
+ALTER TABLE videos ADD COLUMN IF NOT EXISTS bytes BIGINT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS bytes BIGINT;
+ALTER TABLE file_metadata ADD COLUMN IF NOT EXISTS bytes BIGINT;
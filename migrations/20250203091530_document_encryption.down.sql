@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE documents DROP COLUMN IF EXISTS encrypted_data_key;
+ALTER TABLE documents DROP COLUMN IF EXISTS encryption_key_id;
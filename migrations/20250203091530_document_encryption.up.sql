@@ -0,0 +1,4 @@
+-- Envelope encryption of documents: the data key that encrypted the file, wrapped by the
+-- master key named in encryption_key_id. Documents without one are stored in plaintext.
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS encryption_key_id TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS encrypted_data_key BYTEA;
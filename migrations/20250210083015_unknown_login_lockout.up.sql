@@ -0,0 +1,7 @@
+-- Failed logins for usernames that have no account, counted like users.failed_login_attempts
+-- so unknown and existing usernames lock out the same way.
+CREATE TABLE IF NOT EXISTS unknown_login_attempts (
+    username TEXT PRIMARY KEY,
+    failed_login_attempts INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS checksum_duplicates;
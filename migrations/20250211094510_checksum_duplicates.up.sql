@@ -0,0 +1,11 @@
+-- Records stored before checksums whose content turned out to repeat another record of the
+-- same student, so (student_id, sha256) cannot hold both. The integrity check reports them
+-- instead of trying to record their checksum again on every run.
+CREATE TABLE IF NOT EXISTS checksum_duplicates (
+    table_name TEXT NOT NULL,
+    record_id UUID NOT NULL,
+    duplicate_of UUID NOT NULL,
+    sha256 TEXT NOT NULL,
+    found_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (table_name, record_id)
+);
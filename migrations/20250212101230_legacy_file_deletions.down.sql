@@ -0,0 +1,2 @@
+DELETE FROM storage_deletions WHERE legacy_dir IS NOT NULL;
+ALTER TABLE storage_deletions DROP COLUMN IF EXISTS legacy_dir;
@@ -0,0 +1,4 @@
+-- Files saved before storage keys live in ./static or ./uploads, outside the storage
+-- backend. They are queued with the path in storage_key and the legacy directory here;
+-- NULL for objects in storage.
+ALTER TABLE storage_deletions ADD COLUMN IF NOT EXISTS legacy_dir TEXT;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS standalone_uploads;
@@ -0,0 +1,7 @@
+-- Files stored through /upload before any record points at them. Their bytes count towards
+-- the installation quota until a record takes the key over; the row goes with the object.
+CREATE TABLE IF NOT EXISTS standalone_uploads (
+    storage_key TEXT PRIMARY KEY NOT NULL,
+    bytes BIGINT NOT NULL,
+    uploaded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
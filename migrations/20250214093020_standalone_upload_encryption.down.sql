@@ -0,0 +1,2 @@
+ALTER TABLE standalone_uploads DROP COLUMN IF EXISTS encrypted_data_key;
+ALTER TABLE standalone_uploads DROP COLUMN IF EXISTS encryption_key_id;
@@ -0,0 +1,4 @@
+-- Documents stored through /upload are encrypted like the others; their envelope stays with
+-- the upload until a record takes the key over.
+ALTER TABLE standalone_uploads ADD COLUMN IF NOT EXISTS encryption_key_id TEXT;
+ALTER TABLE standalone_uploads ADD COLUMN IF NOT EXISTS encrypted_data_key BYTEA;
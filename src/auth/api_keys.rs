@@ -0,0 +1,60 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{permissions::Permission, tokens};
+
+/// Header integrations send the key in.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+const KEY_PREFIX: &str = "ak_";
+// Caracteres mostrados na listagem para reconhecer a chave sem expor o segredo
+const DISPLAY_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// New key in `ak_<64 hex>` form. Only its hash is stored; the key is shown once.
+pub fn generate_api_key() -> String {
+    format!("{}{}", KEY_PREFIX, tokens::generate_token())
+}
+
+pub fn display_prefix(key: &str) -> String {
+    key.chars().take(DISPLAY_PREFIX_LEN).collect()
+}
+
+/// Scopes an admin can grant. Keys never manage users, which also covers managing keys.
+pub fn is_grantable(permission: Permission) -> bool {
+    permission != Permission::UsersManage
+}
+
+pub struct ApiKeyIdentity {
+    pub id: Uuid,
+    pub scopes: Vec<Permission>,
+}
+
+/// Id of an active key, without recording its use.
+pub async fn key_id(db: &PgPool, key: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT id FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())",
+    )
+    .bind(tokens::hash_token(key))
+    .fetch_optional(db)
+    .await
+}
+
+/// Looks up an active key and records its use.
+pub async fn authenticate(db: &PgPool, key: &str) -> Result<Option<ApiKeyIdentity>, sqlx::Error> {
+    let row: Option<(Uuid, Vec<String>)> = sqlx::query_as(
+        r#"
+        UPDATE api_keys SET last_used_at = NOW()
+        WHERE key_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+        RETURNING id, scopes
+        "#,
+    )
+    .bind(tokens::hash_token(key))
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(|(id, scopes)| ApiKeyIdentity {
+        id,
+        // Escopos que deixaram de existir são ignorados
+        scopes: scopes.iter().filter_map(|scope| scope.parse().ok()).collect(),
+    }))
+}
@@ -0,0 +1,110 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Config;
+
+/// What a token can be used for. Only access tokens open the API; the other kinds
+/// carry a login that is still waiting for its second factor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    #[default]
+    Access,
+    /// Password checked, TOTP code still required (`POST /api/auth/login/totp`).
+    MfaChallenge,
+    /// Admin without TOTP: only the enrollment routes accept it.
+    TotpEnrollment,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub role: String,
+    /// Session the token belongs to; revoking the session's refresh tokens invalidates it.
+    pub sid: Uuid,
+    #[serde(default)]
+    pub typ: TokenKind,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Signing keys and lifetimes for the tokens issued by the API.
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    pub access_token_ttl: Duration,
+    pub refresh_token_ttl: Duration,
+    pub mfa_token_ttl: Duration,
+}
+
+impl JwtKeys {
+    pub fn new(config: &Config) -> JwtKeys {
+        JwtKeys {
+            encoding: EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+            decoding: DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+            access_token_ttl: config.access_token_ttl,
+            refresh_token_ttl: config.refresh_token_ttl,
+            mfa_token_ttl: config.mfa_token_ttl,
+        }
+    }
+
+    pub fn encode_access_token(
+        &self,
+        user_id: Uuid,
+        role: &str,
+        session_id: Uuid,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        self.encode_token(user_id, role, session_id, TokenKind::Access, self.access_token_ttl)
+    }
+
+    /// Short-lived token for a login that still needs its second factor. It has no session.
+    pub fn encode_mfa_token(
+        &self,
+        user_id: Uuid,
+        role: &str,
+        kind: TokenKind,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        self.encode_token(user_id, role, Uuid::nil(), kind, self.mfa_token_ttl)
+    }
+
+    fn encode_token(
+        &self,
+        user_id: Uuid,
+        role: &str,
+        session_id: Uuid,
+        kind: TokenKind,
+        ttl: Duration,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: user_id,
+            role: role.to_string(),
+            sid: session_id,
+            typ: kind,
+            iat: now.timestamp(),
+            exp: (now + ttl).timestamp(),
+        };
+        encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+    }
+
+    /// Decodes any token signed by the API; callers must check [`Claims::typ`].
+    pub fn decode_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        decode::<Claims>(token, &self.decoding, &Validation::new(Algorithm::HS256))
+            .map(|data| data.claims)
+    }
+
+    /// Decodes a token and requires it to be of the given kind.
+    pub fn decode_token_of_kind(
+        &self,
+        token: &str,
+        kind: TokenKind,
+    ) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let claims = self.decode_token(token)?;
+        if claims.typ != kind {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+        }
+        Ok(claims)
+    }
+}
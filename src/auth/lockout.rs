@@ -0,0 +1,83 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::model::UserModel;
+
+/// Time left before a locked account accepts logins again.
+pub fn remaining(user: &UserModel) -> Option<Duration> {
+    let locked_until = user.locked_until?;
+    let remaining = locked_until - Utc::now();
+    (remaining > Duration::zero()).then_some(remaining)
+}
+
+/// Counts a failed password or second-factor attempt. Once `max_attempts` is reached the
+/// account is locked for `lockout` and the counter starts over; returns the new lock, if any.
+pub async fn record_failure(
+    db: &PgPool,
+    user_id: Uuid,
+    max_attempts: i32,
+    lockout: Duration,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE users SET
+            failed_login_attempts = CASE WHEN failed_login_attempts + 1 >= $2 THEN 0 ELSE failed_login_attempts + 1 END,
+            locked_until = CASE WHEN failed_login_attempts + 1 >= $2 THEN NOW() + $3 ELSE locked_until END
+        WHERE id = $1
+        RETURNING CASE WHEN failed_login_attempts = 0 THEN locked_until END
+        "#,
+    )
+    .bind(user_id)
+    .bind(max_attempts)
+    .bind(lockout)
+    .fetch_optional(db)
+    .await
+    .map(Option::flatten)
+}
+
+/// [`remaining`] for a username with no account.
+pub async fn remaining_unknown(db: &PgPool, username: &str) -> Result<Option<Duration>, sqlx::Error> {
+    let locked_until: Option<DateTime<Utc>> =
+        sqlx::query_scalar("SELECT locked_until FROM unknown_login_attempts WHERE username = $1")
+            .bind(username)
+            .fetch_optional(db)
+            .await?
+            .flatten();
+    Ok(locked_until
+        .map(|locked_until| locked_until - Utc::now())
+        .filter(|remaining| *remaining > Duration::zero()))
+}
+
+/// [`record_failure`] for a username with no account. Unknown usernames lock out after the
+/// same number of attempts, so the lockout does not tell which usernames exist.
+pub async fn record_unknown_failure(
+    db: &PgPool,
+    username: &str,
+    max_attempts: i32,
+    lockout: Duration,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO unknown_login_attempts AS attempts (username, failed_login_attempts, locked_until)
+        VALUES ($1, CASE WHEN 1 >= $2 THEN 0 ELSE 1 END, CASE WHEN 1 >= $2 THEN NOW() + $3 END)
+        ON CONFLICT (username) DO UPDATE SET
+            failed_login_attempts = CASE WHEN attempts.failed_login_attempts + 1 >= $2 THEN 0 ELSE attempts.failed_login_attempts + 1 END,
+            locked_until = CASE WHEN attempts.failed_login_attempts + 1 >= $2 THEN NOW() + $3 ELSE attempts.locked_until END
+        RETURNING CASE WHEN failed_login_attempts = 0 THEN locked_until END
+        "#,
+    )
+    .bind(username)
+    .bind(max_attempts)
+    .bind(lockout)
+    .fetch_one(db)
+    .await
+}
+
+pub async fn reset(db: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET failed_login_attempts = 0, locked_until = NULL WHERE id = $1 AND (failed_login_attempts > 0 OR locked_until IS NOT NULL)")
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
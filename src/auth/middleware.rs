@@ -0,0 +1,169 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::AUTHORIZATION,
+    web::Data,
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+
+use super::{
+    api_keys::{self, API_KEY_HEADER},
+    jwt::TokenKind,
+    permissions::Role,
+    tokens, AuthError, AuthenticatedUser, Credential, TotpCaller,
+};
+use crate::AppState;
+
+// Rotas do escopo /api que não exigem token
+const PUBLIC_PATHS: &[&str] = &[
+    "/api/healthchecker",
+    "/api/auth/login",
+    "/api/auth/login/totp",
+    "/api/auth/refresh",
+    "/api/auth/parents/magic-link",
+    "/api/auth/parents/verify",
+    "/api/auth/invitations/accept",
+    "/api/auth/password-reset",
+    "/api/auth/password-reset/confirm",
+];
+
+// Prefixos públicos: links assinados são verificados pelo próprio handler
+const PUBLIC_PREFIXES: &[&str] = &["/api/files/"];
+
+// Únicas rotas liberadas para o token de cadastro do TOTP (admins sem segundo fator)
+const TOTP_ENROLLMENT_PATHS: &[&str] = &[
+    "/api/auth/totp/setup",
+    "/api/auth/totp/activate",
+];
+
+fn is_public(path: &str) -> bool {
+    PUBLIC_PATHS.contains(&path.trim_end_matches('/'))
+        || PUBLIC_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+}
+
+fn is_totp_enrollment(path: &str) -> bool {
+    TOTP_ENROLLMENT_PATHS.contains(&path.trim_end_matches('/'))
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+fn api_key(req: &ServiceRequest) -> Option<String> {
+    let key = req.headers().get(API_KEY_HEADER)?.to_str().ok()?.trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+/// Rejects requests without a valid access token or API key and exposes the caller
+/// to handlers through the [`AuthenticatedUser`] extractor.
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            if is_public(req.path()) {
+                return service.call(req).await;
+            }
+
+            let state = req
+                .app_data::<Data<AppState>>()
+                .cloned()
+                .ok_or_else(|| AuthError::Internal("Application state is missing".to_string()))?;
+
+            // Integrações mandam a chave no cabeçalho X-API-Key em vez de um bearer token
+            if let (None, Some(key)) = (bearer_token(&req), api_key(&req)) {
+                let identity = match api_keys::authenticate(&state.db, &key).await {
+                    Ok(Some(identity)) => identity,
+                    Ok(None) => return Err(AuthError::InvalidToken.into()),
+                    Err(error) => {
+                        return Err(AuthError::Internal(format!("Failed to check API key: {:?}", error)).into())
+                    }
+                };
+
+                req.extensions_mut().insert(AuthenticatedUser {
+                    id: identity.id,
+                    role: Role::Integration,
+                    credential: Credential::ApiKey { scopes: identity.scopes },
+                });
+                return service.call(req).await;
+            }
+
+            let token = bearer_token(&req).ok_or(AuthError::MissingToken)?;
+
+            let claims = state
+                .jwt
+                .decode_token(&token)
+                .map_err(|_| AuthError::InvalidToken)?;
+
+            let role: Role = claims.role.parse().map_err(|_| AuthError::InvalidToken)?;
+
+            match claims.typ {
+                TokenKind::Access => {}
+                TokenKind::TotpEnrollment if is_totp_enrollment(req.path()) => {
+                    req.extensions_mut().insert(TotpCaller {
+                        id: claims.sub,
+                        role,
+                        session_id: None,
+                    });
+                    return service.call(req).await;
+                }
+                TokenKind::TotpEnrollment => return Err(AuthError::TotpEnrollmentRequired.into()),
+                TokenKind::MfaChallenge => return Err(AuthError::InvalidToken.into()),
+            }
+
+            match tokens::is_session_active(&state.db, claims.sid).await {
+                Ok(true) => {}
+                Ok(false) => return Err(AuthError::SessionRevoked.into()),
+                Err(error) => {
+                    return Err(AuthError::Internal(format!("Failed to check session: {:?}", error)).into())
+                }
+            }
+
+            req.extensions_mut().insert(AuthenticatedUser {
+                id: claims.sub,
+                role,
+                credential: Credential::Session(claims.sid),
+            });
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,157 @@
+pub mod api_keys;
+pub mod jwt;
+pub mod lockout;
+pub mod middleware;
+pub mod password;
+pub mod permissions;
+pub mod tokens;
+pub mod totp;
+
+use std::fmt;
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload, http::StatusCode, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    ResponseError,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use permissions::{Permission, Role};
+
+/// How the caller proved who they are.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// Access token of a user or parent session.
+    Session(Uuid),
+    /// API key; it carries its own scopes instead of a role's permissions.
+    ApiKey { scopes: Vec<Permission> },
+}
+
+/// The caller identified by the access token or API key, placed in the request
+/// extensions by [`middleware::Authentication`]. For API keys `id` is the key's id.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub id: Uuid,
+    pub role: Role,
+    pub credential: Credential,
+}
+
+impl AuthenticatedUser {
+    pub fn can(&self, permission: Permission) -> bool {
+        match &self.credential {
+            Credential::Session(_) => self.role.allows(permission),
+            Credential::ApiKey { scopes } => scopes.contains(&permission),
+        }
+    }
+
+    pub fn session_id(&self) -> Option<Uuid> {
+        match self.credential {
+            Credential::Session(session_id) => Some(session_id),
+            Credential::ApiKey { .. } => None,
+        }
+    }
+
+    /// Set when the caller is a parent; queries use it to restrict rows to the
+    /// students linked to that parent in `student_parents`.
+    pub fn parent_id(&self) -> Option<Uuid> {
+        (self.role == Role::Parent).then_some(self.id)
+    }
+}
+
+/// Caller of the TOTP enrollment routes: a signed-in user, or an admin who logged in
+/// with a password but holds only the enrollment token until TOTP is set up.
+#[derive(Debug, Clone)]
+pub struct TotpCaller {
+    pub id: Uuid,
+    pub role: Role,
+    /// `None` while enrolling from the enrollment token; activation then opens the session.
+    pub session_id: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    SessionRevoked,
+    TotpEnrollmentRequired,
+    SessionRequired,
+    Forbidden(Permission),
+    Internal(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "Missing bearer token or API key"),
+            AuthError::InvalidToken => write!(f, "Invalid or expired token"),
+            AuthError::SessionRevoked => write!(f, "Session has been revoked"),
+            AuthError::TotpEnrollmentRequired => {
+                write!(f, "Two-factor authentication must be set up before using the API")
+            }
+            AuthError::SessionRequired => write!(f, "This route requires a signed-in user, not an API key"),
+            AuthError::Forbidden(permission) => {
+                write!(f, "Forbidden: requires the '{}' permission", permission)
+            }
+            AuthError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Forbidden(_) | AuthError::TotpEnrollmentRequired | AuthError::SessionRequired => {
+                StatusCode::FORBIDDEN
+            }
+            AuthError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({
+            "status": "error",
+            "message": self.to_string()
+        }))
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(AuthError::MissingToken),
+        )
+    }
+}
+
+impl FromRequest for TotpCaller {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        if let Some(caller) = extensions.get::<TotpCaller>() {
+            return ready(Ok(caller.clone()));
+        }
+
+        let caller = match extensions.get::<AuthenticatedUser>() {
+            Some(user) => match user.session_id() {
+                Some(session_id) => Ok(TotpCaller {
+                    id: user.id,
+                    role: user.role,
+                    session_id: Some(session_id),
+                }),
+                None => Err(AuthError::SessionRequired),
+            },
+            None => Err(AuthError::MissingToken),
+        };
+        ready(caller)
+    }
+}
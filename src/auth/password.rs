@@ -0,0 +1,45 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+// Hash usado quando o usuário não existe, para que o tempo de resposta do login
+// não revele quais usernames estão cadastrados.
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$Vfv0mMmEuT1cE9bWmqxvZ2UeBhw2r3tHSBUPA8Xo6CA";
+
+/// Hashes a plaintext password with Argon2id and a random salt (PHC string format).
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Checks a plaintext password against a stored PHC hash.
+/// Malformed hashes are treated as a mismatch.
+pub fn verify_password(password: &str, hashed_password: &str) -> bool {
+    match PasswordHash::new(hashed_password) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Burns the same amount of work as a real verification. Used when the account does not exist.
+pub fn verify_dummy_password(password: &str) {
+    let _ = verify_password(password, DUMMY_HASH);
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+
+use super::{AuthError, AuthenticatedUser};
+
+/// Staff roles allowed by the `users.role` CHECK constraint, parents signed in
+/// through the parent portal, and integrations calling with an API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Coach,
+    Parent,
+    Integration,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Coach => "coach",
+            Role::Parent => "parent",
+            Role::Integration => "integration",
+        }
+    }
+
+    pub fn is_staff(&self) -> bool {
+        matches!(self, Role::Admin | Role::Coach)
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Coach => !matches!(
+                permission,
+                Permission::UsersManage | Permission::StudentsDelete | Permission::LogsDelete | Permission::StorageManage
+            ),
+            // Parents only read, and handlers narrow results to their own children
+            Role::Parent => matches!(permission, Permission::StudentsRead | Permission::MediaRead),
+            // API keys get exactly the scopes they were issued with, see AuthenticatedUser::can
+            Role::Integration => false,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "admin" => Ok(Role::Admin),
+            "coach" => Ok(Role::Coach),
+            "parent" => Ok(Role::Parent),
+            "integration" => Ok(Role::Integration),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a route requires from the caller. Declared per route with
+/// `wrap = "RequirePermission(Permission::...)"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    UsersManage,
+    StudentsRead,
+    StudentsWrite,
+    StudentsDelete,
+    ParentsRead,
+    ParentsWrite,
+    MediaRead,
+    MediaWrite,
+    FilesRead,
+    FilesWrite,
+    LogsRead,
+    LogsWrite,
+    LogsDelete,
+    TasksManage,
+    StorageManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 15] = [
+        Permission::UsersManage,
+        Permission::StudentsRead,
+        Permission::StudentsWrite,
+        Permission::StudentsDelete,
+        Permission::ParentsRead,
+        Permission::ParentsWrite,
+        Permission::MediaRead,
+        Permission::MediaWrite,
+        Permission::FilesRead,
+        Permission::FilesWrite,
+        Permission::LogsRead,
+        Permission::LogsWrite,
+        Permission::LogsDelete,
+        Permission::TasksManage,
+        Permission::StorageManage,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::UsersManage => "users:manage",
+            Permission::StudentsRead => "students:read",
+            Permission::StudentsWrite => "students:write",
+            Permission::StudentsDelete => "students:delete",
+            Permission::ParentsRead => "parents:read",
+            Permission::ParentsWrite => "parents:write",
+            Permission::MediaRead => "media:read",
+            Permission::MediaWrite => "media:write",
+            Permission::FilesRead => "files:read",
+            Permission::FilesWrite => "files:write",
+            Permission::LogsRead => "logs:read",
+            Permission::LogsWrite => "logs:write",
+            Permission::LogsDelete => "logs:delete",
+            Permission::TasksManage => "tasks:manage",
+            Permission::StorageManage => "storage:manage",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == value)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Route middleware that answers 403 unless the authenticated caller holds the permission.
+/// Relies on [`super::middleware::Authentication`] having run on the enclosing scope.
+pub struct RequirePermission(pub Permission);
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequirePermissionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service: Rc::new(service),
+            permission: self.0,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: Rc<S>,
+    permission: Permission,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed = req
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .map(|user| user.can(self.permission));
+
+        match allowed {
+            Some(true) => {
+                let service = Rc::clone(&self.service);
+                Box::pin(async move { service.call(req).await })
+            }
+            Some(false) => {
+                let error = AuthError::Forbidden(self.permission);
+                Box::pin(async move { Err(error.into()) })
+            }
+            None => Box::pin(async move { Err(AuthError::MissingToken.into()) }),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::{jwt::JwtKeys, permissions::Role};
+
+/// Random opaque token handed to the client. Only its hash is stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Who a session belongs to: staff users and parents live in different tables.
+#[derive(Debug, Clone, Copy)]
+pub enum Subject {
+    User(Uuid),
+    Parent(Uuid),
+}
+
+impl Subject {
+    pub fn id(&self) -> Uuid {
+        match self {
+            Subject::User(id) | Subject::Parent(id) => *id,
+        }
+    }
+
+    fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Subject::User(id) => Some(*id),
+            Subject::Parent(_) => None,
+        }
+    }
+
+    fn parent_id(&self) -> Option<Uuid> {
+        match self {
+            Subject::User(_) => None,
+            Subject::Parent(id) => Some(*id),
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct RefreshTokenRow {
+    id: Uuid,
+    user_id: Option<Uuid>,
+    parent_id: Option<Uuid>,
+    session_id: Uuid,
+    expires_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Invalid,
+    Database(sqlx::Error),
+    Jwt(jsonwebtoken::errors::Error),
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Invalid => write!(f, "invalid or expired token"),
+            TokenError::Database(error) => write!(f, "database error: {}", error),
+            TokenError::Jwt(error) => write!(f, "token encoding error: {}", error),
+        }
+    }
+}
+
+impl From<sqlx::Error> for TokenError {
+    fn from(error: sqlx::Error) -> Self {
+        TokenError::Database(error)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(error)
+    }
+}
+
+async fn insert_refresh_token<'e, E>(
+    executor: E,
+    keys: &JwtKeys,
+    subject: Subject,
+    session_id: Uuid,
+) -> Result<(Uuid, String), sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let token = generate_token();
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO refresh_tokens (user_id, parent_id, session_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+    )
+    .bind(subject.user_id())
+    .bind(subject.parent_id())
+    .bind(session_id)
+    .bind(hash_token(&token))
+    .bind(Utc::now() + keys.refresh_token_ttl)
+    .fetch_one(executor)
+    .await?;
+    Ok((id, token))
+}
+
+/// Starts a new session for the subject and returns its first token pair.
+pub async fn issue_session(
+    db: &PgPool,
+    keys: &JwtKeys,
+    subject: Subject,
+    role: &str,
+) -> Result<TokenPair, TokenError> {
+    let session_id = Uuid::new_v4();
+    let (_, refresh_token) = insert_refresh_token(db, keys, subject, session_id).await?;
+    Ok(TokenPair {
+        access_token: keys.encode_access_token(subject.id(), role, session_id)?,
+        refresh_token,
+        expires_in: keys.access_token_ttl.num_seconds(),
+    })
+}
+
+/// Exchanges a refresh token for a new pair. Each refresh token works once; presenting
+/// one that was already rotated revokes the whole session, since it means it leaked.
+pub async fn rotate_refresh_token(
+    db: &PgPool,
+    keys: &JwtKeys,
+    refresh_token: &str,
+) -> Result<TokenPair, TokenError> {
+    let mut tx = db.begin().await?;
+
+    let current = sqlx::query_as::<_, RefreshTokenRow>(
+        r#"
+        SELECT id, user_id, parent_id, session_id, expires_at, revoked_at
+        FROM refresh_tokens
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(hash_token(refresh_token))
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(TokenError::Invalid)?;
+
+    if current.revoked_at.is_some() {
+        revoke_session_tokens(&mut tx, current.session_id).await?;
+        tx.commit().await?;
+        return Err(TokenError::Invalid);
+    }
+    if current.expires_at <= Utc::now() {
+        return Err(TokenError::Invalid);
+    }
+
+    // O papel é relido a cada rotação para refletir mudanças feitas por um admin
+    let (subject, role) = match (current.user_id, current.parent_id) {
+        (Some(user_id), _) => {
+            let (role, totp_enabled_at): (String, Option<DateTime<Utc>>) =
+                sqlx::query_as("SELECT role, totp_enabled_at FROM users WHERE id = $1")
+                    .bind(user_id)
+                    .fetch_optional(&mut tx)
+                    .await?
+                    .ok_or(TokenError::Invalid)?;
+            // Admin sem TOTP (promovido ou com o TOTP zerado depois do login) não renova a
+            // sessão: volta ao login, que exige o cadastro do segundo fator
+            if role == Role::Admin.as_str() && totp_enabled_at.is_none() {
+                revoke_session_tokens(&mut tx, current.session_id).await?;
+                tx.commit().await?;
+                return Err(TokenError::Invalid);
+            }
+            (Subject::User(user_id), role)
+        }
+        (None, Some(parent_id)) => (Subject::Parent(parent_id), Role::Parent.as_str().to_string()),
+        (None, None) => return Err(TokenError::Invalid),
+    };
+
+    let (new_id, new_token) =
+        insert_refresh_token(&mut tx, keys, subject, current.session_id).await?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW(), replaced_by = $1 WHERE id = $2")
+        .bind(new_id)
+        .bind(current.id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(TokenPair {
+        access_token: keys.encode_access_token(subject.id(), &role, current.session_id)?,
+        refresh_token: new_token,
+        expires_in: keys.access_token_ttl.num_seconds(),
+    })
+}
+
+async fn revoke_session_tokens<'e, E>(executor: E, session_id: Uuid) -> Result<u64, sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let result = sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE session_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(session_id)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn revoke_session(db: &PgPool, session_id: Uuid) -> Result<u64, sqlx::Error> {
+    revoke_session_tokens(db, session_id).await
+}
+
+/// Ends every session of a staff user, e.g. after a password change.
+pub async fn revoke_all_sessions(db: &PgPool, user_id: Uuid) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// A session stays valid while it has an unrevoked, unexpired refresh token.
+pub async fn is_session_active(db: &PgPool, session_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM refresh_tokens
+            WHERE session_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        )
+        "#,
+    )
+    .bind(session_id)
+    .fetch_one(db)
+    .await
+}
@@ -0,0 +1,173 @@
+use base32::Alphabet;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::tokens::hash_token;
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// Aceita o código anterior e o seguinte para tolerar relógios um pouco fora de hora
+const ALLOWED_SKEW_STEPS: i64 = 1;
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+const BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// New random shared secret, base32-encoded as authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32::encode(BASE32, &bytes)
+}
+
+/// `otpauth://` URI that authenticator apps import, usually through a QR code.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// HOTP value (RFC 4226) for the given counter; TOTP uses the 30-second time step as the counter.
+pub fn code_at(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Time step matched by `code` at `unix_time`, if any. Callers must record the step
+/// (see [`claim_step`]) so the same code cannot be used twice.
+pub fn matching_step(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let secret = base32::decode(BASE32, secret)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = unix_time / STEP_SECONDS;
+    (current - ALLOWED_SKEW_STEPS..=current + ALLOWED_SKEW_STEPS)
+        .filter(|step| *step >= 0)
+        .find(|step| code_at(&secret, *step as u64) == code)
+}
+
+/// Marks `step` as used for the user. Returns false when that step, or a later one,
+/// was already accepted, which is how replayed codes are rejected.
+pub async fn claim_step<'e, E>(executor: E, user_id: Uuid, step: i64) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let result = sqlx::query(
+        "UPDATE users SET totp_last_step = $1 WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+    )
+    .bind(step)
+    .bind(user_id)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Fresh set of recovery codes in `xxxxx-xxxxx` form. Shown to the user once.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Replaces every recovery code of the user with `codes`.
+pub async fn store_recovery_codes(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    codes: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code in codes {
+        sqlx::query("INSERT INTO user_recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(hash_token(&normalize_recovery_code(code)))
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Consumes a recovery code. Each code works once.
+pub async fn use_recovery_code<'e, E>(executor: E, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let result = sqlx::query(
+        "UPDATE user_recovery_codes SET used_at = NOW() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(hash_token(&normalize_recovery_code(code)))
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Checks a code against the user's secret and records its step, so it is accepted once.
+pub async fn verify_code(db: &PgPool, user_id: Uuid, secret: &str, code: &str) -> Result<bool, sqlx::Error> {
+    match matching_step(secret, code, Utc::now().timestamp()) {
+        Some(step) => claim_step(db, user_id, step).await,
+        None => Ok(false),
+    }
+}
+
+/// Removes the secret and the recovery codes; the user is back to password-only login.
+pub async fn clear(db: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await
+}
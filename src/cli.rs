@@ -0,0 +1,117 @@
+use chrono::Duration;
+
+use crate::{
+    reconcile::{self, Options, DEFAULT_MIN_AGE_HOURS},
+    storage,
+    AppState,
+};
+
+const USAGE: &str = "Usage: backend [reconcile [--apply] [--min-age-hours N] | rotate-keys | encrypt-documents]";
+
+/// Runs a maintenance command given as `backend <command> [options]` instead of the
+/// server. `Err` carries the message to print before exiting with a failure status.
+pub async fn run(data: &AppState, args: &[String]) -> Result<(), String> {
+    match args.split_first() {
+        Some((command, options)) if command == "reconcile" => reconcile_command(data, options).await,
+        Some((command, [])) if command == "rotate-keys" => rotate_keys_command(data).await,
+        Some((command, [])) if command == "encrypt-documents" => encrypt_documents_command(data).await,
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+// Igual ao endpoint: sem --apply só relata
+async fn reconcile_command(data: &AppState, args: &[String]) -> Result<(), String> {
+    let mut options = Options {
+        dry_run: true,
+        min_age: Duration::hours(DEFAULT_MIN_AGE_HOURS),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--apply" => options.dry_run = false,
+            "--min-age-hours" => {
+                options.min_age = args
+                    .next()
+                    .and_then(|hours| hours.parse().ok())
+                    .filter(|&hours: &i64| hours >= 0)
+                    .and_then(Duration::try_hours)
+                    .ok_or("--min-age-hours expects a non-negative number of hours")?;
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let report = reconcile::run(data, options).await.map_err(|error| error.to_string())?;
+    for file in &report.orphaned_files {
+        println!("orphaned file  {} ({} bytes)", file.key, file.size);
+    }
+    for record in &report.missing_files {
+        let file = record.storage_key.as_deref().or(record.filename.as_deref()).unwrap_or_default();
+        println!("missing file   {} {} ({})", record.table, record.id, file);
+    }
+    for record in &report.unchecked_files {
+        println!("unchecked file {} {} ({})", record.table, record.id, record.filename.as_deref().unwrap_or_default());
+    }
+    for error in &report.errors {
+        eprintln!("{}", error);
+    }
+
+    if report.dry_run {
+        println!(
+            "Dry run: {} orphaned files and {} records with missing files found; run with --apply to delete them",
+            report.orphaned_files.len(),
+            report.missing_files.len()
+        );
+    } else {
+        println!("Deleted {} orphaned files and {} records with missing files", report.deleted_files, report.deleted_records);
+    }
+    if !report.unchecked_files.is_empty() {
+        println!("{} records have a legacy filename that cannot be looked up; check them by hand", report.unchecked_files.len());
+    }
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} errors during reconciliation", report.errors.len()))
+    }
+}
+
+// Passa as chaves dos documentos para a primeira chave mestra de DOCUMENT_ENCRYPTION_KEYS
+async fn rotate_keys_command(data: &AppState) -> Result<(), String> {
+    let keys = &data.config.storage.document_keys;
+    let report = storage::rotate_keys(&data.db, keys).await.map_err(|error| error.to_string())?;
+    for error in &report.errors {
+        eprintln!("{}", error);
+    }
+
+    let current = keys.first().map(|key| key.id.as_str()).unwrap_or_default();
+    println!("Re-wrapped {} document keys with master key '{}'", report.rewrapped, current);
+    if report.plaintext > 0 {
+        println!("{} documents are stored in plaintext and have no key to rotate; run encrypt-documents", report.plaintext);
+    }
+    if report.errors.is_empty() {
+        if keys.len() > 1 {
+            println!("No document needs the other master keys any more; they can be removed");
+        }
+        Ok(())
+    } else {
+        Err(format!("{} document keys could not be re-wrapped; keep their master keys configured", report.errors.len()))
+    }
+}
+
+// Cifra os documentos gravados antes de DOCUMENT_ENCRYPTION_KEYS existir
+async fn encrypt_documents_command(data: &AppState) -> Result<(), String> {
+    let report = storage::encrypt_documents(&data.db, data.storage.as_ref(), &data.config.storage)
+        .await
+        .map_err(|error| error.to_string())?;
+    for error in &report.errors {
+        eprintln!("{}", error);
+    }
+
+    let current = data.config.storage.document_keys.first().map(|key| key.id.as_str()).unwrap_or_default();
+    println!("Encrypted {} documents with master key '{}'", report.encrypted, current);
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} documents could not be encrypted; they stay in plaintext", report.errors.len()))
+    }
+}
@@ -0,0 +1,385 @@
+use std::env;
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Duration;
+
+// Configuração lida das variáveis de ambiente (.env em desenvolvimento)
+pub struct Config {
+    pub environment: Environment,
+    pub database_url: String,
+    pub jwt_secret: String,
+    pub signed_url_secret: String,
+    pub signed_url_ttl: Duration,
+    pub storage: StorageConfig,
+    pub access_token_ttl: Duration,
+    pub refresh_token_ttl: Duration,
+    pub mfa_token_ttl: Duration,
+    pub totp_issuer: String,
+    pub portal_url: String,
+    pub parent_login_token_ttl: Duration,
+    pub invitation_ttl: Duration,
+    pub password_reset_ttl: Duration,
+    pub max_failed_logins: i32,
+    pub login_lockout: Duration,
+    pub rate_limit: RateLimitConfig,
+    pub cors: CorsConfig,
+    pub mailer: MailerConfig,
+}
+
+/// Deployment profile (`APP_ENV`). Picks defaults that are convenient locally and strict in production.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+impl Environment {
+    fn from_env() -> Environment {
+        match env_or("APP_ENV", "production".to_string()).as_str() {
+            "development" => Environment::Development,
+            "production" => Environment::Production,
+            other => panic!("APP_ENV must be 'development' or 'production', got: {}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// Exact origins (`scheme://host[:port]`); `*` allows any origin and is only accepted in development.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub exposed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    pub max_age_seconds: usize,
+}
+
+impl CorsConfig {
+    fn from_env(environment: Environment, portal_url: &str) -> CorsConfig {
+        let portal_origin = portal_url.trim_end_matches('/').to_string();
+        let default_origins = match environment {
+            Environment::Development => vec![
+                portal_origin,
+                "http://localhost:3000".to_string(),
+                "http://127.0.0.1:3000".to_string(),
+                "http://localhost:5173".to_string(),
+            ],
+            Environment::Production => vec![portal_origin],
+        };
+
+        let config = CorsConfig {
+            allowed_origins: env_list("CORS_ALLOWED_ORIGINS", default_origins),
+            allowed_methods: env_list(
+                "CORS_ALLOWED_METHODS",
+                ["GET", "HEAD", "POST", "PATCH", "PUT", "DELETE"].map(String::from).to_vec(),
+            ),
+            allowed_headers: env_list(
+                "CORS_ALLOWED_HEADERS",
+                [
+                    "authorization",
+                    "content-type",
+                    "accept",
+                    "x-api-key",
+                    // Protocolo tus (uploads retomáveis)
+                    "tus-resumable",
+                    "upload-length",
+                    "upload-offset",
+                    "upload-metadata",
+                    // Streaming de vídeo por intervalos
+                    "range",
+                    "if-range",
+                ]
+                .map(String::from)
+                .to_vec(),
+            ),
+            exposed_headers: env_list(
+                "CORS_EXPOSED_HEADERS",
+                [
+                    "retry-after",
+                    "location",
+                    "tus-resumable",
+                    "tus-version",
+                    "tus-extension",
+                    "tus-max-size",
+                    "upload-offset",
+                    "upload-length",
+                    "upload-expires",
+                    "x-video-id",
+                    "accept-ranges",
+                    "content-range",
+                    "content-length",
+                    "etag",
+                ]
+                .map(String::from)
+                .to_vec(),
+            ),
+            allow_credentials: env_or("CORS_ALLOW_CREDENTIALS", false),
+            max_age_seconds: env_or(
+                "CORS_MAX_AGE_SECONDS",
+                match environment {
+                    Environment::Development => 60,
+                    Environment::Production => 3600,
+                },
+            ),
+        };
+
+        let any_origin = config.allowed_origins.iter().any(|origin| origin == "*");
+        if any_origin && environment == Environment::Production {
+            panic!("CORS_ALLOWED_ORIGINS cannot be '*' when APP_ENV is production");
+        }
+        if any_origin && config.allow_credentials {
+            panic!("CORS_ALLOWED_ORIGINS cannot be '*' when CORS_ALLOW_CREDENTIALS is true");
+        }
+        config
+    }
+}
+
+/// Token bucket: `burst` requests at once, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl BucketConfig {
+    fn from_env(prefix: &str, per_minute: u32, burst: u32) -> BucketConfig {
+        BucketConfig {
+            per_minute: env_or(&format!("{}_PER_MINUTE", prefix), per_minute),
+            burst: env_or(&format!("{}_BURST", prefix), burst),
+        }
+    }
+}
+
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Use the client address from `Forwarded`/`X-Forwarded-For`; only safe behind a proxy that sets it.
+    pub trust_proxy: bool,
+    /// Anonymous requests, per client IP.
+    pub ip: BucketConfig,
+    /// Requests with a valid access token or API key, per user or key.
+    pub user: BucketConfig,
+    /// `/api/auth/*`, per client IP.
+    pub auth: BucketConfig,
+    /// Multipart uploads, per user or key (or IP when anonymous).
+    pub upload: BucketConfig,
+}
+
+impl RateLimitConfig {
+    fn from_env() -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: env_or("RATE_LIMIT_ENABLED", true),
+            trust_proxy: env_or("RATE_LIMIT_TRUST_PROXY", false),
+            ip: BucketConfig::from_env("RATE_LIMIT_IP", 120, 60),
+            user: BucketConfig::from_env("RATE_LIMIT_USER", 300, 100),
+            auth: BucketConfig::from_env("RATE_LIMIT_AUTH", 10, 5),
+            upload: BucketConfig::from_env("RATE_LIMIT_UPLOAD", 10, 5),
+        }
+    }
+}
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Local,
+    S3,
+}
+
+pub struct StorageConfig {
+    pub backend: StorageKind,
+    pub dir: PathBuf,
+    pub tmp_dir: PathBuf,
+    pub s3_endpoint: String,
+    pub s3_region: String,
+    pub s3_bucket: String,
+    pub s3_access_key: String,
+    pub s3_secret_key: String,
+    pub s3_path_style: bool,
+    /// Upload size limits by type, checked while the file streams in.
+    pub max_image_bytes: u64,
+    pub max_video_bytes: u64,
+    pub max_document_bytes: u64,
+    /// Most one student's files may take together; zero means no limit.
+    pub student_quota_bytes: u64,
+    /// Most every stored file may take together; zero means no limit.
+    pub total_quota_bytes: u64,
+    /// Resumable uploads not finished within this time are discarded.
+    pub resumable_upload_ttl: Duration,
+    /// How often stored files are re-hashed against their checksums; zero disables it.
+    pub integrity_check_interval: Duration,
+    /// Master keys that wrap the data keys of encrypted documents. The first one wraps new
+    /// keys; the others are only kept to read documents not yet rotated to it.
+    pub document_keys: Vec<MasterKey>,
+}
+
+/// A 256-bit key named by an id that is stored next to whatever it wrapped.
+pub struct MasterKey {
+    pub id: String,
+    pub key: [u8; 32],
+}
+
+// DOCUMENT_ENCRYPTION_KEYS=id:chave em base64,...; a primeira é a atual
+fn document_keys(environment: Environment) -> Vec<MasterKey> {
+    let mut keys: Vec<MasterKey> = Vec::new();
+    for entry in env_list("DOCUMENT_ENCRYPTION_KEYS", Vec::new()) {
+        let (id, encoded) = entry
+            .split_once(':')
+            .unwrap_or_else(|| panic!("DOCUMENT_ENCRYPTION_KEYS entries must look like id:base64-key, got: {}", entry));
+        let key = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .unwrap_or_else(|| panic!("DOCUMENT_ENCRYPTION_KEYS key '{}' must be 32 bytes in base64", id));
+        let id = id.trim().to_string();
+        if id.is_empty() || keys.iter().any(|existing| existing.id == id) {
+            panic!("DOCUMENT_ENCRYPTION_KEYS ids must be unique and not empty, got: '{}'", id);
+        }
+        keys.push(MasterKey { id, key });
+    }
+
+    if keys.is_empty() && environment == Environment::Production {
+        panic!("DOCUMENT_ENCRYPTION_KEYS must be set when APP_ENV is production");
+    }
+    keys
+}
+
+impl StorageConfig {
+    fn from_env(environment: Environment) -> StorageConfig {
+        let backend = match env_or("STORAGE_BACKEND", "local".to_string()).as_str() {
+            "local" => StorageKind::Local,
+            "s3" => StorageKind::S3,
+            other => panic!("STORAGE_BACKEND must be 'local' or 's3', got: {}", other),
+        };
+        // Credenciais só são obrigatórias quando o S3 está em uso
+        let s3_credential = |name: &str| match backend {
+            StorageKind::S3 => env::var(name).unwrap_or_else(|_| panic!("{} must be set when STORAGE_BACKEND=s3", name)),
+            StorageKind::Local => env::var(name).unwrap_or_default(),
+        };
+
+        StorageConfig {
+            backend,
+            dir: PathBuf::from(env_or("STORAGE_DIR", "./uploads".to_string())),
+            tmp_dir: env::var("STORAGE_TMP_DIR").map(PathBuf::from).unwrap_or_else(|_| env::temp_dir()),
+            s3_endpoint: env_or("S3_ENDPOINT", "http://localhost:9000".to_string()),
+            s3_region: env_or("S3_REGION", "us-east-1".to_string()),
+            s3_bucket: env_or("S3_BUCKET", "media".to_string()),
+            s3_access_key: s3_credential("S3_ACCESS_KEY_ID"),
+            s3_secret_key: s3_credential("S3_SECRET_ACCESS_KEY"),
+            s3_path_style: env_or("S3_PATH_STYLE", true),
+            max_image_bytes: env_or("UPLOAD_MAX_IMAGE_MB", 20u64) * MEGABYTE,
+            max_video_bytes: env_or("UPLOAD_MAX_VIDEO_MB", 1024u64) * MEGABYTE,
+            max_document_bytes: env_or("UPLOAD_MAX_DOCUMENT_MB", 20u64) * MEGABYTE,
+            student_quota_bytes: env_or("STORAGE_QUOTA_STUDENT_MB", 0u64) * MEGABYTE,
+            total_quota_bytes: env_or("STORAGE_QUOTA_TOTAL_MB", 0u64) * MEGABYTE,
+            resumable_upload_ttl: Duration::hours(env_or("RESUMABLE_UPLOAD_HOURS", 24)),
+            integrity_check_interval: Duration::hours(env_or("INTEGRITY_CHECK_HOURS", 24)),
+            document_keys: document_keys(environment),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailTransport {
+    Smtp,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    Tls,
+    StartTls,
+    None,
+}
+
+pub struct MailerConfig {
+    pub transport: MailTransport,
+    pub from: String,
+    pub maildir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: SmtpTls,
+}
+
+impl MailerConfig {
+    fn from_env() -> MailerConfig {
+        let transport = match env_or("MAILER", "file".to_string()).as_str() {
+            "smtp" => MailTransport::Smtp,
+            "file" => MailTransport::File,
+            other => panic!("MAILER must be 'smtp' or 'file', got: {}", other),
+        };
+        let smtp_tls = match env_or("SMTP_TLS", "starttls".to_string()).as_str() {
+            "tls" => SmtpTls::Tls,
+            "starttls" => SmtpTls::StartTls,
+            "none" => SmtpTls::None,
+            other => panic!("SMTP_TLS must be 'tls', 'starttls' or 'none', got: {}", other),
+        };
+
+        MailerConfig {
+            transport,
+            from: env_or("MAIL_FROM", "no-reply@localhost".to_string()),
+            maildir: env_or("MAIL_DIR", "./maildir".to_string()),
+            smtp_host: env_or("SMTP_HOST", "localhost".to_string()),
+            smtp_port: env_or("SMTP_PORT", 587),
+            smtp_username: env::var("SMTP_USERNAME").ok(),
+            smtp_password: env::var("SMTP_PASSWORD").ok(),
+            smtp_tls,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let environment = Environment::from_env();
+        let portal_url = env_or("PORTAL_URL", "http://localhost:3000".to_string());
+        let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+
+        Config {
+            environment,
+            database_url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
+            // Sem SIGNED_URL_SECRET próprio, reaproveita o segredo do JWT (as mensagens assinadas não se confundem)
+            signed_url_secret: env::var("SIGNED_URL_SECRET").unwrap_or_else(|_| jwt_secret.clone()),
+            signed_url_ttl: Duration::minutes(env_or("SIGNED_URL_MINUTES", 15)),
+            storage: StorageConfig::from_env(environment),
+            jwt_secret,
+            access_token_ttl: Duration::minutes(env_or("JWT_ACCESS_TOKEN_MINUTES", 15)),
+            refresh_token_ttl: Duration::days(env_or("JWT_REFRESH_TOKEN_DAYS", 30)),
+            mfa_token_ttl: Duration::minutes(env_or("MFA_TOKEN_MINUTES", 5)),
+            totp_issuer: env_or("TOTP_ISSUER", "4 Linhas".to_string()),
+            cors: CorsConfig::from_env(environment, &portal_url),
+            portal_url,
+            parent_login_token_ttl: Duration::minutes(env_or("PARENT_LOGIN_TOKEN_MINUTES", 15)),
+            invitation_ttl: Duration::hours(env_or("INVITATION_TOKEN_HOURS", 72)),
+            password_reset_ttl: Duration::minutes(env_or("PASSWORD_RESET_TOKEN_MINUTES", 60)),
+            max_failed_logins: env_or("LOGIN_MAX_FAILED_ATTEMPTS", 5),
+            login_lockout: Duration::minutes(env_or("LOGIN_LOCKOUT_MINUTES", 15)),
+            rate_limit: RateLimitConfig::from_env(),
+            mailer: MailerConfig::from_env(),
+        }
+    }
+}
+
+/// Comma-separated list; empty entries are dropped.
+pub fn env_list(name: &str, default: Vec<String>) -> Vec<String> {
+    match env::var(name) {
+        Ok(value) => value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+        Err(_) => default,
+    }
+}
+
+/// Reads and parses an optional variable, panicking on malformed values
+/// so that a typo in the environment is caught at startup.
+pub fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", name, value)),
+        Err(_) => default,
+    }
+}
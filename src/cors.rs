@@ -0,0 +1,87 @@
+use actix_cors::Cors;
+use actix_web::{
+    dev::RequestHead,
+    http::{
+        header::{HeaderValue, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD},
+        Method,
+    },
+};
+
+use crate::config::CorsConfig;
+
+/// Builds the CORS middleware from configuration. Requests from origins that are not
+/// allowed, and preflights asking for a method or header outside the policy, are
+/// answered with 400 by actix-cors and logged here.
+pub fn from_config(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(config.allowed_headers.iter().map(String::as_str))
+        .max_age(config.max_age_seconds);
+
+    if !config.exposed_headers.is_empty() {
+        cors = cors.expose_headers(config.exposed_headers.iter().map(String::as_str));
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+
+    let policy = config.clone();
+    cors.allowed_origin_fn(move |origin, head| match rejection_reason(&policy, origin, head) {
+        None => true,
+        Some(reason) => {
+            let kind = if is_preflight(head) { "preflight" } else { "request" };
+            eprintln!(
+                "CORS: rejected {} {} {} from origin {:?}: {}",
+                kind, head.method, head.uri, origin, reason
+            );
+            false
+        }
+    })
+}
+
+fn is_preflight(head: &RequestHead) -> bool {
+    head.method == Method::OPTIONS && head.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Why a request does not fit the policy, or `None` when it does.
+pub fn rejection_reason(config: &CorsConfig, origin: &HeaderValue, head: &RequestHead) -> Option<String> {
+    let origin = origin.to_str().unwrap_or_default();
+    if !config.allowed_origins.iter().any(|allowed| allowed == origin) {
+        return Some("origin not allowed".to_string());
+    }
+
+    if !is_preflight(head) {
+        return None;
+    }
+
+    // actix-cors também recusa esses casos, mas sem dizer o motivo
+    let method = head
+        .headers()
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !config.allowed_methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method)) {
+        return Some(format!("method {} not allowed", method));
+    }
+
+    let requested_headers = head
+        .headers()
+        .get(ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let denied: Vec<&str> = requested_headers
+        .split(',')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .filter(|header| !config.allowed_headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)))
+        .collect();
+    if !denied.is_empty() {
+        return Some(format!("headers not allowed: {}", denied.join(", ")));
+    }
+
+    None
+}
@@ -0,0 +1,172 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageError, ImageReader, Rgb, RgbImage,
+};
+
+use crate::storage::MediaType;
+
+// Qualidade dos JPEGs gerados; o WebP do encoder em Rust puro é sempre sem perdas
+const JPEG_QUALITY: u8 = 82;
+
+/// Sizes generated for every photo, by the longest side of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantSize {
+    Thumbnail,
+    Medium,
+    Large,
+}
+
+/// Encodings each variant is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantFormat {
+    Webp,
+    Jpeg,
+}
+
+impl VariantSize {
+    // Do maior para o menor: cada tamanho é reduzido a partir do anterior
+    pub const ALL: [VariantSize; 3] = [VariantSize::Large, VariantSize::Medium, VariantSize::Thumbnail];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VariantSize::Thumbnail => "thumbnail",
+            VariantSize::Medium => "medium",
+            VariantSize::Large => "large",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<VariantSize> {
+        VariantSize::ALL.into_iter().find(|size| size.name() == name)
+    }
+
+    /// Longest side, in pixels. Smaller photos keep their own size.
+    pub fn max_dimension(self) -> u32 {
+        match self {
+            VariantSize::Thumbnail => 320,
+            VariantSize::Medium => 1024,
+            VariantSize::Large => 2048,
+        }
+    }
+}
+
+impl VariantFormat {
+    pub const ALL: [VariantFormat; 2] = [VariantFormat::Webp, VariantFormat::Jpeg];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VariantFormat::Webp => "webp",
+            VariantFormat::Jpeg => "jpeg",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<VariantFormat> {
+        match name {
+            "webp" => Some(VariantFormat::Webp),
+            "jpeg" | "jpg" => Some(VariantFormat::Jpeg),
+            _ => None,
+        }
+    }
+
+    pub fn media_type(self) -> MediaType {
+        match self {
+            VariantFormat::Webp => MediaType::Webp,
+            VariantFormat::Jpeg => MediaType::Jpeg,
+        }
+    }
+}
+
+/// One encoded variant, ready to be stored.
+pub struct Variant {
+    pub size: VariantSize,
+    pub format: VariantFormat,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Key of a variant, next to the original: `photos/ab/cd/{uuid}.jpg` gives
+/// `photos/ab/cd/{uuid}/thumbnail.webp`.
+pub fn variant_key(storage_key: &str, size: VariantSize, format: VariantFormat) -> String {
+    let base = storage_key.rsplit_once('.').map_or(storage_key, |(base, _)| base);
+    format!("{}/{}.{}", base, size.name(), format.media_type().extension())
+}
+
+/// Dimensions that fit `width` x `height` inside a `max` x `max` box, keeping the aspect
+/// ratio. Images that already fit are never enlarged.
+pub fn fit_within(width: u32, height: u32, max: u32) -> (u32, u32) {
+    if width <= max && height <= max {
+        return (width, height);
+    }
+    let scale = |side: u32, longest: u32| ((side as u64 * max as u64 + longest as u64 / 2) / longest as u64).max(1) as u32;
+    if width >= height {
+        (max, scale(height, width))
+    } else {
+        (scale(width, height), max)
+    }
+}
+
+fn encode(image: &DynamicImage, format: VariantFormat) -> Result<Vec<u8>, ImageError> {
+    let mut bytes = Vec::new();
+    match format {
+        VariantFormat::Webp => {
+            let encoder = WebPEncoder::new_lossless(&mut bytes);
+            if image.color().has_alpha() {
+                image.to_rgba8().write_with_encoder(encoder)?;
+            } else {
+                image.to_rgb8().write_with_encoder(encoder)?;
+            }
+        }
+        VariantFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY);
+            flatten(image).write_with_encoder(encoder)?;
+        }
+    }
+    Ok(bytes)
+}
+
+// JPEG não tem transparência: pixels transparentes viram fundo branco, não preto
+fn flatten(image: &DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |channel: u8| ((channel as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// Decodes a photo, turns it upright according to its EXIF `orientation` and produces
+/// every size in every format. The pure-Rust WebP encoder only writes lossless WebP, so
+/// for photos the WebP variants are usually larger than the JPEG ones, most of all at the
+/// medium and large sizes. CPU-bound: call it off the async executor.
+pub fn render_variants(original: &[u8], orientation: Option<u8>) -> Result<Vec<Variant>, ImageError> {
+    let mut image = ImageReader::new(Cursor::new(original)).with_guessed_format()?.decode()?;
+    // As variantes saem sem EXIF, então a rotação tem de estar nos próprios pixels
+    if let Some(orientation) = orientation.and_then(Orientation::from_exif) {
+        image.apply_orientation(orientation);
+    }
+
+    let mut variants = Vec::new();
+    for size in VariantSize::ALL {
+        let (width, height) = fit_within(image.width(), image.height(), size.max_dimension());
+        if (width, height) != (image.width(), image.height()) {
+            image = image.resize_exact(width, height, FilterType::Lanczos3);
+        }
+        for format in VariantFormat::ALL {
+            variants.push(Variant {
+                size,
+                format,
+                width,
+                height,
+                bytes: encode(&image, format)?,
+            });
+        }
+    }
+    Ok(variants)
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use actix_web::{rt, web::Data};
+use uuid::Uuid;
+
+use crate::{
+    storage::{self, Envelope, StorageBackend, StorageError},
+    AppState,
+};
+
+// Tabelas cujos registros apontam para arquivos no storage
+const TABLES: [&str; 4] = ["photos", "videos", "documents", "file_metadata"];
+
+/// Outcome of re-reading one stored object.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Intact,
+    Missing,
+    Corrupted { actual: String },
+    /// The record had no checksum yet (stored before checksums existed).
+    Unrecorded { actual: String },
+}
+
+/// Re-hashes the object under `key` and compares it with the checksum on record.
+pub async fn check_object(
+    storage: &dyn StorageBackend,
+    key: &str,
+    expected: Option<&str>,
+) -> Result<Verdict, StorageError> {
+    verdict(storage::hash_object(storage, key).await, expected)
+}
+
+fn verdict(hashed: Result<String, StorageError>, expected: Option<&str>) -> Result<Verdict, StorageError> {
+    let actual = match hashed {
+        Ok(actual) => actual,
+        Err(StorageError::NotFound(_)) => return Ok(Verdict::Missing),
+        Err(error) => return Err(error),
+    };
+
+    Ok(match expected {
+        None => Verdict::Unrecorded { actual },
+        Some(expected) if expected == actual => Verdict::Intact,
+        Some(_) => Verdict::Corrupted { actual },
+    })
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    checked: usize,
+    backfilled: usize,
+    sized: usize,
+    missing: usize,
+    corrupted: usize,
+    duplicates: usize,
+    failed: usize,
+}
+
+// id, storage_key, sha256, bytes e o envelope da chave, quando o arquivo é cifrado
+type Row = (Uuid, String, Option<String>, Option<i64>, Option<String>, Option<Vec<u8>>);
+
+async fn check_table(data: &AppState, table: &str, report: &mut Report) -> Result<(), sqlx::Error> {
+    // Só documentos podem estar cifrados; o checksum é do conteúdo em claro
+    let envelope = if table == "documents" { "encryption_key_id, encrypted_data_key" } else { "NULL::text, NULL::bytea" };
+    // `table` vem sempre de TABLES, nunca da requisição
+    let rows: Vec<Row> = sqlx::query_as(&format!(
+        r#"
+        SELECT id, storage_key, sha256, bytes, {envelope} FROM {table} t
+        WHERE storage_key IS NOT NULL
+          AND NOT EXISTS (SELECT 1 FROM checksum_duplicates d WHERE d.table_name = '{table}' AND d.record_id = t.id)
+        ORDER BY id
+        "#
+    ))
+    .fetch_all(&data.db)
+    .await?;
+    // Duplicatas já encontradas ficam fora da conferência, mas continuam no relatório
+    let duplicates: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM checksum_duplicates d JOIN {table} t ON t.id = d.record_id WHERE d.table_name = '{table}'"
+    ))
+    .fetch_one(&data.db)
+    .await?;
+    report.duplicates += duplicates as usize;
+
+    for (id, key, expected, bytes, key_id, wrapped_key) in rows {
+        report.checked += 1;
+        if bytes.is_none() {
+            record_size(data, table, id, &key, report).await;
+        }
+        let checked = match Envelope::from_record(key_id, wrapped_key) {
+            Some(envelope) => {
+                let keys = &data.config.storage.document_keys;
+                let hashed = storage::hash_document(data.storage.as_ref(), keys, &key, Some(&envelope)).await;
+                verdict(hashed, expected.as_deref())
+            }
+            None => check_object(data.storage.as_ref(), &key, expected.as_deref()).await,
+        };
+        match checked {
+            Ok(Verdict::Intact) => {}
+            Ok(Verdict::Missing) => {
+                report.missing += 1;
+                eprintln!("Integrity: {} {} is missing its file {}", table, id, key);
+            }
+            Ok(Verdict::Corrupted { actual }) => {
+                report.corrupted += 1;
+                eprintln!(
+                    "Integrity: {} {} has a corrupted file {} (expected sha256 {}, found {})",
+                    table,
+                    id,
+                    key,
+                    expected.unwrap_or_default(),
+                    actual
+                );
+            }
+            Ok(Verdict::Unrecorded { actual }) => {
+                // Registros anteriores aos checksums: grava o hash atual
+                let updated = sqlx::query(&format!("UPDATE {} SET sha256 = $1 WHERE id = $2 AND sha256 IS NULL", table))
+                    .bind(&actual)
+                    .bind(id)
+                    .execute(&data.db)
+                    .await;
+                match updated {
+                    Ok(_) => report.backfilled += 1,
+                    // Mesmo conteúdo que outro registro do aluno: o índice único não deixa
+                    // gravar o hash, então o registro é anotado como duplicata uma vez só
+                    Err(error) if storage::is_key_taken(&error) => match record_duplicate(data, table, id, &actual).await {
+                        Ok(original) => {
+                            report.duplicates += 1;
+                            eprintln!("Integrity: {} {} has the same content as {} {}", table, id, table, original);
+                        }
+                        Err(error) => eprintln!("Integrity: could not record {} {} as a duplicate: {:?}", table, id, error),
+                    },
+                    Err(error) => eprintln!("Integrity: could not record the sha256 of {} {}: {:?}", table, id, error),
+                }
+            }
+            Err(error) => {
+                report.failed += 1;
+                eprintln!("Integrity: could not read {} {} ({}): {}", table, id, key, error);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Anota um registro anterior aos checksums cujo conteúdo repete outro do mesmo aluno e
+// devolve o id desse outro
+async fn record_duplicate(data: &AppState, table: &str, id: Uuid, sha256: &str) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        r#"
+        INSERT INTO checksum_duplicates (table_name, record_id, duplicate_of, sha256)
+        SELECT '{table}', $1, original.id, $2
+        FROM {table} t JOIN {table} original ON original.student_id = t.student_id AND original.sha256 = $2
+        WHERE t.id = $1
+        ON CONFLICT (table_name, record_id) DO UPDATE SET duplicate_of = EXCLUDED.duplicate_of
+        RETURNING duplicate_of
+        "#
+    ))
+    .bind(id)
+    .bind(sha256)
+    .fetch_one(&data.db)
+    .await
+}
+
+// Registros anteriores à contagem de uso: grava o tamanho do objeto
+async fn record_size(data: &AppState, table: &str, id: Uuid, key: &str, report: &mut Report) {
+    let Ok(Some(object)) = data.storage.stat(key).await else {
+        return;
+    };
+    let updated = sqlx::query(&format!("UPDATE {} SET bytes = $1 WHERE id = $2 AND bytes IS NULL", table))
+        .bind(object.size as i64)
+        .bind(id)
+        .execute(&data.db)
+        .await;
+    match updated {
+        Ok(_) => report.sized += 1,
+        Err(error) => eprintln!("Integrity: could not record the size of {} {}: {:?}", table, id, error),
+    }
+}
+
+/// Re-hashes every stored file and compares it with its recorded checksum. Missing and
+/// corrupted files are logged; records stored before checksums or sizes existed get them,
+/// except those repeating another record of the same student, which are reported as
+/// duplicates and left out of later runs.
+pub async fn run_check(data: &AppState) {
+    let mut report = Report::default();
+    for table in TABLES {
+        if let Err(error) = check_table(data, table, &mut report).await {
+            eprintln!("Integrity: failed to list {}: {:?}", table, error);
+        }
+    }
+
+    println!(
+        "Integrity check: {} files checked, {} missing, {} corrupted, {} unreadable, {} duplicates, {} checksums and {} sizes recorded",
+        report.checked, report.missing, report.corrupted, report.failed, report.duplicates, report.backfilled, report.sized
+    );
+}
+
+/// Runs [`run_check`] every `INTEGRITY_CHECK_HOURS`, the first time one interval after
+/// startup. Does nothing when the interval is zero.
+pub fn spawn(data: Data<AppState>) {
+    let Ok(period) = data.config.storage.integrity_check_interval.to_std() else {
+        return;
+    };
+    if period == Duration::ZERO {
+        return;
+    }
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval_at(rt::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            run_check(&data).await;
+        }
+    });
+}
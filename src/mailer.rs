@@ -0,0 +1,150 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use uuid::Uuid;
+
+use crate::config::{MailTransport, MailerConfig, SmtpTls};
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    InvalidAddress(String),
+    Build(String),
+    Transport(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::InvalidAddress(address) => write!(f, "invalid email address: {}", address),
+            MailError::Build(message) => write!(f, "failed to build email: {}", message),
+            MailError::Transport(message) => write!(f, "failed to send email: {}", message),
+            MailError::Io(error) => write!(f, "failed to write email: {}", error),
+        }
+    }
+}
+
+impl From<std::io::Error> for MailError {
+    fn from(error: std::io::Error) -> Self {
+        MailError::Io(error)
+    }
+}
+
+/// Outgoing email. Handlers only see this trait; the implementation is picked in configuration.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+fn build_message(from: &Mailbox, email: Email) -> Result<Message, MailError> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|_| MailError::InvalidAddress(email.to.clone()))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body)
+        .map_err(|error| MailError::Build(error.to_string()))
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &MailerConfig, from: Mailbox) -> Result<SmtpMailer, MailError> {
+        let builder = match config.smtp_tls {
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)),
+        }
+        .map_err(|error| MailError::Transport(error.to_string()))?;
+
+        let mut builder = builder.port(config.smtp_port);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let message = build_message(&self.from, email)?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|error| MailError::Transport(error.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes each message as an RFC 5322 file into a maildir (`tmp/`, `new/`, `cur/`),
+/// so development and tests can read sent mail without a mail server.
+pub struct FileMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>, from: Mailbox) -> FileMailer {
+        FileMailer {
+            dir: dir.into(),
+            from,
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let message = build_message(&self.from, email)?;
+
+        for sub in ["tmp", "new", "cur"] {
+            tokio::fs::create_dir_all(self.dir.join(sub)).await?;
+        }
+
+        // Grava em tmp/ e depois move para new/, como manda o formato maildir
+        let name = format!("{}.{}.eml", Utc::now().timestamp_micros(), Uuid::new_v4());
+        let tmp_path = self.dir.join("tmp").join(&name);
+        tokio::fs::write(&tmp_path, message.formatted()).await?;
+        tokio::fs::rename(&tmp_path, self.dir.join("new").join(&name)).await?;
+        Ok(())
+    }
+}
+
+pub fn from_config(config: &MailerConfig) -> Result<Arc<dyn Mailer>, MailError> {
+    let from: Mailbox = config
+        .from
+        .parse()
+        .map_err(|_| MailError::InvalidAddress(config.from.clone()))?;
+
+    match config.transport {
+        MailTransport::Smtp => Ok(Arc::new(SmtpMailer::new(config, from)?)),
+        MailTransport::File => Ok(Arc::new(FileMailer::new(&config.maildir, from))),
+    }
+}
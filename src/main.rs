@@ -1,16 +1,42 @@
+mod auth;
+mod cli;
+mod config;
+mod cors;
+mod images;
+mod integrity;
+mod mailer;
+mod rate_limit;
 mod services;
 mod model;
+mod reconcile;
 mod schema;
+mod signed_urls;
+mod storage;
+mod zip;
+
+#[cfg(test)]
+mod tests;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
-use actix_cors::Cors;
 use dotenv::dotenv;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::env;
-use actix_files::Files;
+use std::sync::Arc;
+
+use auth::jwt::JwtKeys;
+use config::Config;
+use mailer::Mailer;
+use rate_limit::{RateLimit, RateLimiter};
+use signed_urls::UrlSigner;
+use storage::StorageBackend;
 
 pub struct AppState {
     db: Pool<Postgres>,
+    jwt: JwtKeys,
+    mailer: Arc<dyn Mailer>,
+    storage: Arc<dyn StorageBackend>,
+    url_signer: UrlSigner,
+    rate_limiter: RateLimiter,
+    config: Config,
 }
 
 #[actix_web::main]
@@ -24,11 +50,12 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init();
 
-    // Retrieve database URL from environment variables
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // Read settings from environment variables
+    let config = Config::from_env();
+    println!("Using the {:?} profile", config.environment);
 
     // Create the database connection pool
-    let pool = match PgPoolOptions::new().max_connections(10).connect(&database_url).await {
+    let pool = match PgPoolOptions::new().max_connections(10).connect(&config.database_url).await {
         Ok(pool) => {
             println!("Connection to DB established");
             pool
@@ -39,19 +66,55 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    let mailer = match mailer::from_config(&config.mailer) {
+        Ok(mailer) => mailer,
+        Err(error) => {
+            eprintln!("Failed to configure the mailer: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let storage = match storage::from_config(&config.storage) {
+        Ok(storage) => storage,
+        Err(error) => {
+            eprintln!("Failed to configure the storage backend: {}", error);
+            std::process::exit(1);
+        }
+    };
+    println!("Storing uploads with the {:?} backend", config.storage.backend);
+
+    let state = web::Data::new(AppState {
+        db: pool,
+        jwt: JwtKeys::new(&config),
+        mailer,
+        storage,
+        url_signer: UrlSigner::new(&config.signed_url_secret, config.signed_url_ttl),
+        rate_limiter: RateLimiter::new(),
+        config,
+    });
+
+    // `backend <command>` runs a maintenance task and exits instead of serving
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(message) = cli::run(&state, &args).await {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    services::video_uploads::spawn_expiry(state.clone()); // Discard resumable uploads left unfinished
+    integrity::spawn(state.clone()); // Re-hash stored files and report missing or corrupted ones
+    storage::spawn_deletion_retries(state.db.clone(), state.storage.clone()); // Retry files of deleted records that could not be removed
+
     // Start the HTTP server
     HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(AppState { db: pool.clone() })) // Share the database pool across handlers
+            .app_data(state.clone()) // Share the database pool and signing keys across handlers
             .configure(services::config) // Register routes and services
+            .wrap(RateLimit) // 429 when a client or user goes over its token bucket
             .wrap(Logger::default()) // Enable request logging
-            .wrap(Cors::default()
-                .allow_any_origin() // Allow requests from any origin
-                .allow_any_method() // Allow any HTTP method
-                .allow_any_header() // Allow any headers
-            )
-            .service(Files::new("/uploads", "./uploads").show_files_listing()) // Servir arquivos estáticos do diretório de uploads
-            .service(Files::new("/static", "./static").show_files_listing()) // Servir arquivos estáticos
+            .wrap(cors::from_config(&state.config.cors)) // Origins, methods and headers from CORS_* settings
     })
     .bind("127.0.0.1:8080")? // Bind the server to port 8080
     .run()
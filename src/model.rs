@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
@@ -17,7 +17,14 @@ pub struct DocumentModel {
     pub student_id: Uuid,  // Altere aqui de user_id para student_id
     pub doc_type: String,
     pub filename: String,
+    pub storage_key: Option<String>,
+    pub sha256: Option<String>,
+    pub bytes: Option<i64>,
     pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub encryption_key_id: Option<String>,
+    #[serde(skip_serializing)] // A chave do arquivo, cifrada pela chave mestra
+    pub encrypted_data_key: Option<Vec<u8>>,
 }
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
@@ -38,17 +45,36 @@ pub struct ParentModel {
 }
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
-pub struct StudentParentModel {
+pub struct VideoModel {
+    pub id: Uuid,
     pub student_id: Uuid,
-    pub parent_id: Uuid,
+    pub filename: String,
+    pub storage_key: Option<String>,
+    pub sha256: Option<String>,
+    pub bytes: Option<i64>,
+    pub description: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub frame_rate: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub bitrate: Option<i64>,
+    pub recorded_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
-pub struct VideoModel {
+pub struct ResumableUploadModel {
     pub id: Uuid,
+    pub owner_id: Uuid,
     pub student_id: Uuid,
     pub filename: String,
     pub description: Option<String>,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub video_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
     pub created_at: Option<DateTime<Utc>>,
 }
 
@@ -57,7 +83,24 @@ pub struct PhotoModel {
     pub id: Uuid,
     pub student_id: Uuid,
     pub filename: String,
+    pub storage_key: Option<String>,
+    pub sha256: Option<String>,
+    pub bytes: Option<i64>,
     pub description: Option<String>,
+    pub taken_at: Option<NaiveDateTime>,
+    pub orientation: Option<i16>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct PhotoVariantModel {
+    pub photo_id: Uuid,
+    pub size: String,
+    pub format: String,
+    pub storage_key: String,
+    pub width: i32,
+    pub height: i32,
+    pub bytes: i64,
     pub created_at: Option<DateTime<Utc>>,
 }
 
@@ -65,9 +108,43 @@ pub struct PhotoModel {
 pub struct UserModel {
     pub id: Uuid,
     pub username: String,
+    pub email: Option<String>,
+    #[serde(skip_serializing)] // Nunca devolver o hash nas respostas da API
     pub hashed_password: String,
     pub role: String, // Consider using an enum for role if it's limited to specific values
     pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    #[allow(dead_code)] // Lido e gravado só no SQL de auth::totp::claim_step
+    pub totp_last_step: Option<i64>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct UserInvitationModel {
+    pub id: Uuid,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct ApiKeyModel {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
@@ -76,6 +153,9 @@ pub struct FileMetadataModel {
     pub user_id: Option<Uuid>,
     pub file_type: String,
     pub filename: String,
+    pub storage_key: Option<String>,
+    pub sha256: Option<String>,
+    pub bytes: Option<i64>,
     pub description: Option<String>,
     pub uploaded_at: Option<DateTime<Utc>>,
 }
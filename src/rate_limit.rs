@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        Method, StatusCode,
+    },
+    web::Data,
+    Error, HttpResponse, ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+use serde_json::json;
+
+use crate::{
+    auth::{
+        api_keys::{self, API_KEY_HEADER},
+        jwt::TokenKind,
+    },
+    config::{BucketConfig, RateLimitConfig},
+    AppState,
+};
+
+// Buckets parados há mais tempo que isso já estão cheios de novo e podem ser descartados
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(10 * 60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Rotas multipart com limite próprio, mais baixo
+const UPLOAD_PATHS: &[&str] = &[
+    "/api/upload",
+    "/api/photos",
+    "/api/videos",
+    "/api/videos/uploads",
+    "/api/documents",
+    "/api/file_metadatas/upload",
+];
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+struct Buckets {
+    entries: HashMap<String, Bucket>,
+    pruned_at: Instant,
+}
+
+/// In-memory token buckets, shared by every worker through [`AppState`].
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            buckets: Mutex::new(Buckets {
+                entries: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes one token from the bucket for `key`. When it is empty, returns how long
+    /// until the next token is available.
+    pub fn check(&self, key: &str, limit: BucketConfig) -> Result<(), Duration> {
+        self.check_at(key, limit, Instant::now())
+    }
+
+    pub fn check_at(&self, key: &str, limit: BucketConfig, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if now.saturating_duration_since(buckets.pruned_at) >= PRUNE_INTERVAL {
+            buckets
+                .entries
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated_at) < IDLE_BUCKET_TTL);
+            buckets.pruned_at = now;
+        }
+
+        let capacity = limit.burst.max(1) as f64;
+        let per_second = limit.per_minute as f64 / 60.0;
+        let bucket = buckets.entries.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if per_second <= 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimitError {
+    pub retry_after: Duration,
+}
+
+impl RateLimitError {
+    /// Whole seconds for the `Retry-After` header, rounded up so clients never retry early.
+    pub fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 {
+            secs + 1
+        } else {
+            secs.max(1)
+        }
+    }
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Too many requests, retry in {} seconds", self.retry_after_secs())
+    }
+}
+
+impl ResponseError for RateLimitError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, self.retry_after_secs().to_string()))
+            .json(json!({
+                "status": "error",
+                "message": self.to_string()
+            }))
+    }
+}
+
+fn client_ip(req: &ServiceRequest, config: &RateLimitConfig) -> String {
+    let info = req.connection_info();
+    let address = if config.trust_proxy {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+    address.unwrap_or("unknown").to_string()
+}
+
+// Só identifica o usuário para escolher o bucket; a sessão é validada depois, em Authentication
+fn token_subject(req: &ServiceRequest, state: &AppState) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    let claims = state.jwt.decode_token(token).ok()?;
+    (claims.typ == TokenKind::Access).then(|| claims.sub.to_string())
+}
+
+// Integrações ficam num bucket por chave. Chaves que não existem caem no bucket do IP, senão
+// bastaria inventar uma chave nova a cada requisição para escapar do limite
+async fn api_key_id(req: &ServiceRequest, state: &AppState) -> Option<String> {
+    let key = req.headers().get(API_KEY_HEADER)?.to_str().ok()?.trim();
+    if key.is_empty() {
+        return None;
+    }
+    let id = api_keys::key_id(&state.db, key).await.ok()??;
+    Some(id.to_string())
+}
+
+/// Bucket key and limit for a request.
+async fn classify(req: &ServiceRequest, state: &AppState) -> (String, BucketConfig) {
+    let config = &state.config.rate_limit;
+    let path = req.path().trim_end_matches('/');
+
+    if path.starts_with("/api/auth/") {
+        return (format!("auth:{}", client_ip(req, config)), config.auth);
+    }
+
+    // Como em Authentication, o bearer token vale antes da chave de API
+    let caller = match token_subject(req, state) {
+        Some(user_id) => format!("user:{}", user_id),
+        None => match api_key_id(req, state).await {
+            Some(key_id) => format!("key:{}", key_id),
+            None => format!("ip:{}", client_ip(req, config)),
+        },
+    };
+
+    if req.method() == Method::POST && UPLOAD_PATHS.contains(&path) {
+        return (format!("upload:{}", caller), config.upload);
+    }
+
+    let limit = if caller.starts_with("ip:") { config.ip } else { config.user };
+    (caller, limit)
+}
+
+/// Answers 429 with `Retry-After` once the caller's bucket is empty.
+pub struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            // Preflight de CORS não conta
+            if req.method() == Method::OPTIONS {
+                return service.call(req).await;
+            }
+
+            if let Some(state) = req.app_data::<Data<AppState>>().cloned() {
+                if state.config.rate_limit.enabled {
+                    let (key, limit) = classify(&req, &state).await;
+                    if let Err(retry_after) = state.rate_limiter.check(&key, limit) {
+                        return Err(RateLimitError { retry_after }.into());
+                    }
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    services::files::{self, LEGACY_PHOTOS_DIR, LEGACY_UPLOADS_DIR},
+    storage::{ObjectInfo, StorageError},
+    AppState,
+};
+
+/// Unreferenced objects younger than this are left alone: an upload through `/upload`
+/// only gets its row when the client attaches it afterwards.
+pub const DEFAULT_MIN_AGE_HOURS: i64 = 24;
+
+// Tabelas que apontam para o storage: coluna de id e onde ficavam os arquivos sem chave
+struct Source {
+    table: &'static str,
+    id_column: &'static str,
+    legacy_dir: Option<&'static str>,
+}
+
+const SOURCES: [Source; 5] = [
+    Source { table: "photos", id_column: "id", legacy_dir: Some(LEGACY_PHOTOS_DIR) },
+    Source { table: "videos", id_column: "id", legacy_dir: Some(LEGACY_UPLOADS_DIR) },
+    Source { table: "documents", id_column: "id", legacy_dir: Some(LEGACY_UPLOADS_DIR) },
+    Source { table: "file_metadata", id_column: "id", legacy_dir: Some(LEGACY_UPLOADS_DIR) },
+    // Variantes perdidas são geradas de novo no próximo pedido
+    Source { table: "photo_variants", id_column: "photo_id", legacy_dir: None },
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Only report; nothing is deleted.
+    pub dry_run: bool,
+    pub min_age: Duration,
+}
+
+/// An object in storage that no record points to.
+#[derive(Debug, Serialize)]
+pub struct OrphanedFile {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// A record whose file is gone.
+#[derive(Debug, Serialize)]
+pub struct MissingFile {
+    pub table: &'static str,
+    pub id: Uuid,
+    /// `None` for records saved before storage keys, looked up by filename instead.
+    pub storage_key: Option<String>,
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub dry_run: bool,
+    pub orphaned_files: Vec<OrphanedFile>,
+    pub missing_files: Vec<MissingFile>,
+    /// Records without a storage key whose filename is not a safe single path segment.
+    /// Their file is never looked up, so they are reported and never deleted.
+    pub unchecked_files: Vec<MissingFile>,
+    pub deleted_files: usize,
+    pub deleted_records: usize,
+    pub errors: Vec<String>,
+}
+
+struct Record {
+    source: &'static Source,
+    id: Uuid,
+    storage_key: Option<String>,
+    filename: Option<String>,
+}
+
+/// Objects no record references, last modified before `cutoff`. Dotfiles and anything
+/// under a dot directory (e.g. a `STORAGE_TMP_DIR` inside `STORAGE_DIR`) are never
+/// considered, and neither are objects of unknown age.
+pub fn find_orphans(objects: Vec<ObjectInfo>, referenced: &HashSet<String>, cutoff: DateTime<Utc>) -> Vec<ObjectInfo> {
+    objects
+        .into_iter()
+        .filter(|object| !referenced.contains(&object.key))
+        .filter(|object| !object.key.split('/').any(|segment| segment.starts_with('.')))
+        .filter(|object| object.last_modified.is_some_and(|modified| modified < cutoff))
+        .collect()
+}
+
+async fn load_records(data: &AppState) -> Result<Vec<Record>, StorageError> {
+    let mut records = Vec::new();
+    for source in &SOURCES {
+        let filename = if source.legacy_dir.is_some() { "filename" } else { "NULL::text" };
+        // Nomes vêm sempre de SOURCES, nunca da requisição
+        let rows: Vec<(Uuid, Option<String>, Option<String>)> = sqlx::query_as(&format!(
+            "SELECT {}, storage_key, {} FROM {}",
+            source.id_column, filename, source.table
+        ))
+        .fetch_all(&data.db)
+        .await
+        .map_err(StorageError::Database)?;
+
+        records.extend(rows.into_iter().map(|(id, storage_key, filename)| Record {
+            source,
+            id,
+            storage_key,
+            filename,
+        }));
+    }
+    Ok(records)
+}
+
+enum Check {
+    Present,
+    Missing,
+    // Nome antigo que não dá para procurar com segurança: só entra no relatório
+    Unchecked,
+}
+
+// Confirma a ausência antes de apagar: a listagem pode ser anterior ao upload
+async fn check(data: &AppState, record: &Record, listed: &HashSet<String>) -> Result<Check, StorageError> {
+    let missing = match (&record.storage_key, record.source.legacy_dir, &record.filename) {
+        (Some(key), _, _) => !listed.contains(key) && data.storage.stat(key).await?.is_none(),
+        (None, Some(dir), Some(filename)) => match files::legacy_path(dir, filename) {
+            Some(path) => !tokio::fs::try_exists(path).await?,
+            None => return Ok(Check::Unchecked),
+        },
+        _ => false,
+    };
+    Ok(if missing { Check::Missing } else { Check::Present })
+}
+
+// O envio avulso sai com o objeto, senão continuaria contando na cota
+async fn forget_upload(data: &AppState, key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM standalone_uploads WHERE storage_key = $1")
+        .bind(key)
+        .execute(&data.db)
+        .await
+        .map(|_| ())
+}
+
+async fn delete_record(data: &AppState, record: &Record) -> Result<u64, sqlx::Error> {
+    // A chave entra no filtro para não apagar um registro que foi apontado para outro arquivo
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE {} = $1 AND storage_key IS NOT DISTINCT FROM $2",
+        record.source.table, record.source.id_column
+    ))
+    .bind(record.id)
+    .bind(&record.storage_key)
+    .execute(&data.db)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+/// Compares storage with the database. Objects no record references are orphans; records
+/// whose object (or, for records without a storage key, legacy file) is gone are
+/// dangling. Unless `dry_run`, orphans are deleted from storage and dangling records from
+/// the database. Legacy records whose filename cannot be looked up are only reported.
+pub async fn run(data: &AppState, options: Options) -> Result<Report, StorageError> {
+    // Registros antes da listagem: um upload concluído no meio aparece como recente, não como perdido
+    let records = load_records(data).await?;
+    let objects = data.storage.list("").await?;
+    let listed: HashSet<String> = objects.iter().map(|object| object.key.clone()).collect();
+
+    // Registros antigos guardam o nome do arquivo em uploads/, que também pode ser o STORAGE_DIR
+    let referenced: HashSet<String> = records
+        .iter()
+        .filter_map(|record| match &record.storage_key {
+            Some(key) => Some(key.clone()),
+            None => record.filename.clone(),
+        })
+        .collect();
+
+    let mut report = Report {
+        dry_run: options.dry_run,
+        orphaned_files: Vec::new(),
+        missing_files: Vec::new(),
+        unchecked_files: Vec::new(),
+        deleted_files: 0,
+        deleted_records: 0,
+        errors: Vec::new(),
+    };
+
+    let cutoff = Utc::now().checked_sub_signed(options.min_age).unwrap_or(DateTime::<Utc>::MIN_UTC);
+    for object in find_orphans(objects, &referenced, cutoff) {
+        if !options.dry_run {
+            match data.storage.delete(&object.key).await {
+                Ok(()) => {
+                    report.deleted_files += 1;
+                    if let Err(error) = forget_upload(data, &object.key).await {
+                        report.errors.push(format!("Could not forget upload {}: {}", object.key, error));
+                    }
+                }
+                Err(error) => report.errors.push(format!("Could not delete {}: {}", object.key, error)),
+            }
+        }
+        report.orphaned_files.push(OrphanedFile {
+            key: object.key,
+            size: object.size,
+            last_modified: object.last_modified,
+        });
+    }
+
+    for record in &records {
+        match check(data, record, &listed).await {
+            Ok(Check::Missing) => {}
+            Ok(Check::Present) => continue,
+            Ok(Check::Unchecked) => {
+                report.unchecked_files.push(MissingFile {
+                    table: record.source.table,
+                    id: record.id,
+                    storage_key: None,
+                    filename: record.filename.clone(),
+                });
+                continue;
+            }
+            Err(error) => {
+                report.errors.push(format!("Could not check {} {}: {}", record.source.table, record.id, error));
+                continue;
+            }
+        }
+        if !options.dry_run {
+            match delete_record(data, record).await {
+                // Zero quando o registro já saiu, como as variantes de uma foto apagada acima
+                Ok(deleted) => {
+                    report.deleted_records += deleted as usize;
+                    if let Some(key) = &record.storage_key {
+                        if let Err(error) = forget_upload(data, key).await {
+                            report.errors.push(format!("Could not forget upload {}: {}", key, error));
+                        }
+                    }
+                }
+                Err(error) => report.errors.push(format!("Could not delete {} {}: {}", record.source.table, record.id, error)),
+            }
+        }
+        report.missing_files.push(MissingFile {
+            table: record.source.table,
+            id: record.id,
+            storage_key: record.storage_key.clone(),
+            filename: record.filename.clone(),
+        });
+    }
+
+    Ok(report)
+}
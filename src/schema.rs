@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid; // Adicionado para o uso do tipo Uuid
 
@@ -15,38 +16,84 @@ pub struct CreateStudentSchema {
 
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct CreateDocumentSchema {
-    pub student_id: Uuid, // Altere aqui de user_id para student_id
-    pub doc_type: String,
-    pub filename: String, // Adicionado para corresponder à criação de documentos
+pub struct CreateUserSchema {
+    pub username: String,
+    pub email: Option<String>,
+    #[serde(alias = "hashed_password")] // Aceita o nome antigo do campo; o valor é sempre texto puro
+    pub password: String,
+    pub role: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct CreateUserSchema {
+pub struct LoginUserSchema {
     pub username: String,
-    pub hashed_password: String,
+    pub password: String,
+}
+
+/// Second step of a login with TOTP: either the current code or one of the recovery codes.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TotpLoginSchema {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TotpCodeSchema {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshTokenSchema {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateInvitationSchema {
+    pub email: String,
     pub role: String,
 }
 
-#[derive(Deserialize)]
-pub struct CreateParentSchema {
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateApiKeySchema {
     pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AcceptInvitationSchema {
+    pub token: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetRequestSchema {
     pub email: String,
-    pub phone: String,
 }
 
-#[derive(Deserialize)]
-pub struct CreatePhotoSchema {
-    pub student_id: Uuid,
-    pub filename: String,
-    pub description: String,
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetConfirmSchema {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParentMagicLinkSchema {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParentMagicLinkVerifySchema {
+    pub token: String,
 }
 
 #[derive(Deserialize)]
-pub struct CreateVideoSchema {
-    pub student_id: Uuid,
-    pub filename: String,
-    pub description: String,
+pub struct CreateParentSchema {
+    pub name: String,
+    pub email: String,
+    pub phone: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -54,6 +101,7 @@ pub struct CreateFileMetadataSchema {
     pub user_id: Uuid, // Pode ser opcional se o arquivo não estiver associado a um usuário
     pub file_type: String,     // Deve ser 'video' ou 'photo'
     pub filename: String,
+    pub storage_key: Option<String>, // Devolvida pelo upload; obrigatória, ausente responde 400
     pub description: String,
 }
 
@@ -64,6 +112,41 @@ pub struct CreateLogSchema {
     pub description: String,
 }
 
+/// Query string of a signed download link, see `signed_urls::UrlSigner`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignedFileQuery {
+    pub exp: i64,
+    pub sig: String,
+}
+
+/// Query string of `GET /photos`. `sort=taken_at` orders by when the photos were taken,
+/// newest first.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhotoFilterOptions {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    pub sort: Option<String>,
+}
+
+/// Query string of `GET /videos`. Durations are in seconds; `codec` is the sample entry
+/// name, e.g. `avc1`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VideoFilterOptions {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
+    pub codec: Option<String>,
+}
+
+/// Query string of `GET /photos/{id}/variants/{size}`. `format` always gets that format;
+/// without it the smaller variant the `Accept` header allows is served, which for photos is
+/// usually the JPEG, since the WebP variants are lossless.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VariantQuery {
+    pub format: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FilterOptions {
     pub page: Option<usize>,
@@ -92,7 +175,9 @@ pub struct UpdateDocumentSchema {
 #[derive(Serialize, Deserialize, Debug)] // Adicionado Serialize para consistência
 pub struct UpdateUserSchema {
     pub username: Option<String>,
-    pub hashed_password: Option<String>,
+    pub email: Option<String>,
+    #[serde(alias = "hashed_password")]
+    pub password: Option<String>,
     pub role: Option<String>, // Adicionado para permitir atualização do filename
 }
 
@@ -130,4 +215,11 @@ pub struct UpdateLogSchema {
     pub user_id: Option<Uuid>, // Pode ser opcional se o arquivo não estiver associado a um usuário
     pub action: Option<String>,     // Deve ser 'video' ou 'photo'
     pub description: Option<String>,
-}
\ No newline at end of file
+}
+/// Query string of `POST /storage/reconcile`. Only reports unless `dry_run=false`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReconcileOptions {
+    pub dry_run: Option<bool>,
+    /// Unreferenced files younger than this are kept; 24 when omitted.
+    pub min_age_hours: Option<i64>,
+}
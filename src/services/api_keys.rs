@@ -0,0 +1,156 @@
+use actix_web::{
+    delete, get, post, web::{Data, Json, Path, Query, ServiceConfig}, HttpResponse, Responder
+};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    auth::{
+        api_keys::{display_prefix, generate_api_key, is_grantable},
+        permissions::{Permission, RequirePermission},
+        tokens,
+        AuthenticatedUser,
+    },
+    model::ApiKeyModel,
+    schema::{CreateApiKeySchema, FilterOptions},
+    AppState
+};
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// Cria uma chave para scripts e dispositivos; o valor só aparece nesta resposta
+#[post("/api-keys", wrap = "RequirePermission(Permission::UsersManage)")]
+async fn create_api_key(
+    body: Json<CreateApiKeySchema>,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return bad_request("Name is required".to_string());
+    }
+    if body.scopes.is_empty() {
+        return bad_request("At least one scope is required".to_string());
+    }
+
+    let mut scopes = Vec::new();
+    for scope in &body.scopes {
+        match scope.parse::<Permission>() {
+            Ok(permission) if is_grantable(permission) => {
+                if !scopes.contains(&permission) {
+                    scopes.push(permission);
+                }
+            }
+            _ => return bad_request(format!("Invalid scope: {}", scope)),
+        }
+    }
+    let scopes: Vec<String> = scopes.iter().map(|scope| scope.as_str().to_string()).collect();
+
+    if body.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return bad_request("expires_at must be in the future".to_string());
+    }
+
+    let key = generate_api_key();
+    let api_key = match sqlx::query_as!(
+        ApiKeyModel,
+        r#"
+        INSERT INTO api_keys (name, prefix, key_hash, scopes, created_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, name, prefix, scopes, created_by, expires_at, last_used_at, revoked_at, created_at
+        "#,
+        name,
+        display_prefix(&key),
+        tokens::hash_token(&key),
+        &scopes,
+        user.id,
+        body.expires_at
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(api_key) => api_key,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to create API key: {:?}", error)
+            }));
+        }
+    };
+
+    HttpResponse::Created().json(json!({
+        "status": "success",
+        "api_key": api_key,
+        "key": key
+    }))
+}
+
+#[get("/api-keys", wrap = "RequirePermission(Permission::UsersManage)")]
+async fn get_all_api_keys(
+    opts: Query<FilterOptions>,
+    data: Data<AppState>
+) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+
+    match sqlx::query_as!(
+        ApiKeyModel,
+        r#"
+        SELECT id, name, prefix, scopes, created_by, expires_at, last_used_at, revoked_at, created_at
+        FROM api_keys
+        ORDER BY created_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        limit as i32,
+        offset as i32
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(api_keys) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "api_keys": api_keys
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get API keys: {:?}", error)
+        })),
+    }
+}
+
+// Revoga a chave; ela para de funcionar na próxima requisição
+#[delete("/api-keys/{id}", wrap = "RequirePermission(Permission::UsersManage)")]
+async fn revoke_api_key(
+    path: Path<Uuid>,
+    data: Data<AppState>
+) -> impl Responder {
+    let api_key_id = path.into_inner();
+
+    match sqlx::query!(
+        "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, NOW()) WHERE id = $1",
+        api_key_id
+    )
+    .execute(&data.db)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "API key not found"
+        })),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to revoke API key: {:?}", error)
+        })),
+    }
+}
+
+pub fn config_api_keys(conf: &mut ServiceConfig) {
+    conf.service(create_api_key)
+       .service(get_all_api_keys)
+       .service(revoke_api_key);
+}
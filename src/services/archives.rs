@@ -0,0 +1,276 @@
+use std::io;
+
+use actix_web::{
+    get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{Bytes, Data, Path, ServiceConfig},
+    HttpResponse, Responder
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream::{self, BoxStream}, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{self, Sender};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        permissions::{Permission, RequirePermission},
+        AuthenticatedUser,
+    },
+    model::{DocumentModel, PhotoModel, StudentModel, VideoModel},
+    services::files::{self, LEGACY_PHOTOS_DIR, LEGACY_UPLOADS_DIR},
+    storage::{self, Envelope},
+    zip::{Crc32, ZipWriter},
+    AppState
+};
+
+// Pedaços em trânsito entre a tarefa que monta o ZIP e a resposta
+const CHANNEL_CAPACITY: usize = 8;
+
+type Chunk = io::Result<Bytes>;
+
+// Um arquivo do aluno a incluir no ZIP
+struct ArchiveFile {
+    folder: &'static str,
+    id: Uuid,
+    filename: String,
+    storage_key: Option<String>,
+    legacy_dir: &'static str,
+    created_at: Option<DateTime<Utc>>,
+    envelope: Option<Envelope>,
+}
+
+impl ArchiveFile {
+    fn path(&self) -> String {
+        format!("{}/{}-{}", self.folder, self.id, storage::original_filename(&self.filename))
+    }
+}
+
+// Registros do manifesto, com o caminho do arquivo no ZIP (null quando o arquivo sumiu)
+fn manifest_entry(record: &impl Serialize, archive_path: Option<&str>) -> Value {
+    let mut entry = serde_json::to_value(record).unwrap_or(Value::Null);
+    if let Value::Object(fields) = &mut entry {
+        fields.insert("archive_path".to_string(), json!(archive_path));
+    }
+    entry
+}
+
+async fn open(data: &AppState, file: &ArchiveFile) -> io::Result<Option<(u64, BoxStream<'static, Chunk>)>> {
+    match &file.storage_key {
+        Some(key) => {
+            // Documentos cifrados entram no ZIP decifrados
+            let keys = &data.config.storage.document_keys;
+            match storage::open_document(data.storage.as_ref(), keys, key, file.envelope.as_ref()).await {
+                Ok(object) => Ok(Some((object.size, object.stream))),
+                Err(storage::StorageError::NotFound(_)) => Ok(None),
+                Err(error) => Err(io::Error::other(error.to_string())),
+            }
+        }
+        None => {
+            let Some(path) = files::legacy_path(file.legacy_dir, &file.filename) else {
+                return Ok(None);
+            };
+            match tokio::fs::File::open(path).await {
+                Ok(handle) => {
+                    let size = handle.metadata().await?.len();
+                    Ok(Some((size, ReaderStream::new(handle).boxed())))
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error),
+            }
+        }
+    }
+}
+
+// Envia um pedaço; Err quando o cliente desistiu do download
+async fn send(tx: &Sender<Chunk>, bytes: impl Into<Bytes>) -> io::Result<()> {
+    tx.send(Ok(bytes.into()))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+}
+
+// Envia cada arquivo que ainda existe como uma entrada do ZIP. Devolve o caminho de cada
+// um no ZIP, ou None para os que sumiram
+async fn write_files(
+    data: &AppState,
+    tx: &Sender<Chunk>,
+    zip: &mut ZipWriter,
+    archive: &[ArchiveFile],
+) -> io::Result<Vec<Option<String>>> {
+    let mut paths = Vec::with_capacity(archive.len());
+    for file in archive {
+        let Some((size, mut contents)) = open(data, file).await? else {
+            paths.push(None);
+            continue;
+        };
+        let path = file.path();
+        send(tx, zip.start_file(&path, size, file.created_at.unwrap_or_else(Utc::now))).await?;
+
+        let mut crc = Crc32::new();
+        let mut written = 0u64;
+        while let Some(chunk) = contents.next().await {
+            let chunk = chunk?;
+            crc.update(&chunk);
+            written += chunk.len() as u64;
+            send(tx, chunk).await?;
+        }
+        send(tx, zip.end_file(crc.finish(), written)?).await?;
+        paths.push(Some(path));
+    }
+    Ok(paths)
+}
+
+// Arquivos, depois manifest.json, depois o diretório central
+async fn write_archive(
+    data: &AppState,
+    tx: &Sender<Chunk>,
+    student: StudentModel,
+    photos: Vec<PhotoModel>,
+    videos: Vec<VideoModel>,
+    documents: Vec<DocumentModel>,
+) -> io::Result<()> {
+    let archive: Vec<ArchiveFile> = photos
+        .iter()
+        .map(|photo| ArchiveFile {
+            folder: "photos",
+            id: photo.id,
+            filename: photo.filename.clone(),
+            storage_key: photo.storage_key.clone(),
+            legacy_dir: LEGACY_PHOTOS_DIR,
+            created_at: photo.created_at,
+            envelope: None,
+        })
+        .chain(videos.iter().map(|video| ArchiveFile {
+            folder: "videos",
+            id: video.id,
+            filename: video.filename.clone(),
+            storage_key: video.storage_key.clone(),
+            legacy_dir: LEGACY_UPLOADS_DIR,
+            created_at: video.created_at,
+            envelope: None,
+        }))
+        .chain(documents.iter().map(|document| ArchiveFile {
+            folder: "documents",
+            id: document.id,
+            filename: document.filename.clone(),
+            storage_key: document.storage_key.clone(),
+            legacy_dir: LEGACY_UPLOADS_DIR,
+            created_at: document.created_at,
+            envelope: Envelope::from_record(document.encryption_key_id.clone(), document.encrypted_data_key.clone()),
+        }))
+        .collect();
+
+    let mut zip = ZipWriter::default();
+    let paths = write_files(data, tx, &mut zip, &archive).await?;
+
+    // Mesma ordem de `archive`: fotos, vídeos e documentos
+    let mut paths = paths.iter().map(Option::as_deref);
+    let photos: Vec<Value> = photos.iter().map(|photo| manifest_entry(photo, paths.next().flatten())).collect();
+    let videos: Vec<Value> = videos.iter().map(|video| manifest_entry(video, paths.next().flatten())).collect();
+    let documents: Vec<Value> = documents
+        .iter()
+        .map(|document| manifest_entry(document, paths.next().flatten()))
+        .collect();
+    let generated_at = Utc::now();
+    let manifest = serde_json::to_vec_pretty(&json!({
+        "generated_at": generated_at,
+        "student": student,
+        "photos": photos,
+        "videos": videos,
+        "documents": documents
+    }))?;
+
+    send(tx, zip.start_file("manifest.json", manifest.len() as u64, generated_at)).await?;
+    let mut crc = Crc32::new();
+    crc.update(&manifest);
+    let written = manifest.len() as u64;
+    send(tx, manifest).await?;
+    send(tx, zip.end_file(crc.finish(), written)?).await?;
+    send(tx, zip.finish()).await
+}
+
+// Baixa as fotos, vídeos e documentos do aluno em um ZIP montado durante o envio, com os
+// registros do banco em manifest.json. Arquivos que sumiram do storage ficam de fora.
+#[get(
+    "/students/{id}/archive",
+    wrap = "RequirePermission(Permission::StudentsRead)",
+    wrap = "RequirePermission(Permission::MediaRead)"
+)]
+async fn get_student_archive(
+    path: Path<Uuid>,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    let student_id = path.into_inner();
+
+    let student = match sqlx::query_as!(
+        StudentModel,
+        "SELECT * FROM students WHERE id = $1 AND ($2::uuid IS NULL OR id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        student_id,
+        user.parent_id()
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(student) => student,
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "error",
+                "message": "Student not found"
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get student: {:?}", error)
+            }));
+        }
+    };
+
+    let records = tokio::try_join!(
+        sqlx::query_as!(PhotoModel, "SELECT * FROM photos WHERE student_id = $1 ORDER BY created_at, id", student_id)
+            .fetch_all(&data.db),
+        sqlx::query_as!(VideoModel, "SELECT * FROM videos WHERE student_id = $1 ORDER BY created_at, id", student_id)
+            .fetch_all(&data.db),
+        sqlx::query_as!(DocumentModel, "SELECT * FROM documents WHERE student_id = $1 ORDER BY created_at, id", student_id)
+            .fetch_all(&data.db),
+    );
+    let (photos, videos, documents) = match records {
+        Ok(records) => records,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get the student's files: {:?}", error)
+            }));
+        }
+    };
+
+    // O ZIP é montado numa tarefa à parte; o canal limitado segura a leitura quando o
+    // cliente baixa devagar, então nada além de alguns pedaços fica em memória
+    let (tx, rx) = mpsc::channel::<Chunk>(CHANNEL_CAPACITY);
+    let state = data.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(error) = write_archive(&state, &tx, student, photos, videos, documents).await {
+            if error.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Failed to build the archive of student {}: {:?}", student_id, error);
+                // Um erro no meio corta a resposta, para o cliente não ficar com um ZIP truncado
+                let _ = tx.send(Err(error)).await;
+            }
+        }
+    });
+
+    let body = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("student-{}.zip", student_id))],
+        })
+        .streaming(body)
+}
+
+pub fn config_archives(conf: &mut ServiceConfig) {
+    conf.service(get_student_archive);
+}
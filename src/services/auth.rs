@@ -0,0 +1,965 @@
+use actix_web::{
+    delete, http::header::RETRY_AFTER, post, web::{self, Data, Json, ServiceConfig}, HttpResponse, Responder,
+    ResponseError
+};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    auth::{
+        jwt::TokenKind,
+        lockout,
+        password::{hash_password, validate_password, verify_dummy_password, verify_password},
+        permissions::Role,
+        tokens::{self, Subject, TokenError, TokenPair},
+        totp, AuthError, AuthenticatedUser, TotpCaller,
+    },
+    mailer::Email,
+    model::{ParentModel, UserInvitationModel, UserModel},
+    schema::{
+        AcceptInvitationSchema, LoginUserSchema, ParentMagicLinkSchema, ParentMagicLinkVerifySchema,
+        PasswordResetConfirmSchema, PasswordResetRequestSchema, RefreshTokenSchema, TotpCodeSchema,
+        TotpLoginSchema,
+    },
+    AppState
+};
+
+fn token_response(tokens: TokenPair) -> serde_json::Value {
+    json!({
+        "access_token": tokens.access_token,
+        "refresh_token": tokens.refresh_token,
+        "token_type": "Bearer",
+        "expires_in": tokens.expires_in
+    })
+}
+
+fn token_error(error: TokenError) -> HttpResponse {
+    match error {
+        TokenError::Invalid => HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "Invalid or expired refresh token"
+        })),
+        error => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to issue tokens: {}", error)
+        })),
+    }
+}
+
+fn invalid_second_factor() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "Invalid two-factor code"
+    }))
+}
+
+// Senha conferida, mas a sessão só sai depois do segundo fator (ou do cadastro dele)
+fn second_factor_response(data: &AppState, user: &UserModel, kind: TokenKind) -> HttpResponse {
+    let (status, field) = match kind {
+        TokenKind::TotpEnrollment => ("totp_enrollment_required", "enrollment_token"),
+        _ => ("mfa_required", "mfa_token"),
+    };
+
+    match data.jwt.encode_mfa_token(user.id, &user.role, kind) {
+        Ok(token) => HttpResponse::Ok().json(json!({
+            "status": status,
+            field: token,
+            "expires_in": data.jwt.mfa_token_ttl.num_seconds()
+        })),
+        Err(error) => token_error(error.into()),
+    }
+}
+
+async fn get_user(data: &AppState, user_id: Uuid) -> Result<Option<UserModel>, HttpResponse> {
+    sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|error| {
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get user: {:?}", error)
+            }))
+        })
+}
+
+fn account_locked(retry_after: chrono::Duration) -> HttpResponse {
+    let seconds = retry_after.num_seconds().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, seconds.to_string()))
+        .json(json!({
+            "status": "error",
+            "message": format!("Account temporarily locked after too many failed logins, retry in {} seconds", seconds)
+        }))
+}
+
+// Conta a tentativa errada; a que atinge o limite já responde com o bloqueio
+async fn login_failed(data: &AppState, user_id: Uuid, failure: HttpResponse) -> HttpResponse {
+    match lockout::record_failure(&data.db, user_id, data.config.max_failed_logins, data.config.login_lockout).await {
+        Ok(Some(locked_until)) => account_locked(locked_until - Utc::now()),
+        Ok(None) => failure,
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to record login attempt: {:?}", error)
+        })),
+    }
+}
+
+// login_failed para um username sem conta
+async fn unknown_login_failed(data: &AppState, username: &str) -> HttpResponse {
+    match lockout::record_unknown_failure(&data.db, username, data.config.max_failed_logins, data.config.login_lockout).await {
+        Ok(Some(locked_until)) => account_locked(locked_until - Utc::now()),
+        Ok(None) => invalid_credentials(),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to record login attempt: {:?}", error)
+        })),
+    }
+}
+
+fn invalid_credentials() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "Invalid username or password"
+    }))
+}
+
+// Login com username e senha
+#[post("/auth/login")]
+async fn login(
+    body: Json<LoginUserSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let body = body.into_inner();
+
+    let user = match sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE username = $1",
+        body.username
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(user) => user,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get user: {:?}", error)
+            }));
+        }
+    };
+
+    // Argon2 é caro de propósito; roda fora das threads do servidor. Roda também para contas
+    // bloqueadas ou inexistentes, para o tempo de resposta não revelar qual é o caso
+    let password = body.password;
+    let (user, valid) = match web::block(move || match user {
+        Some(user) => {
+            let valid = verify_password(&password, &user.hashed_password);
+            (Some(user), valid)
+        }
+        None => {
+            verify_dummy_password(&password);
+            (None, false)
+        }
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify password: {:?}", error)
+            }));
+        }
+    };
+
+    // Usernames inexistentes também bloqueiam, como as contas: as respostas não revelam quais existem
+    let locked = match &user {
+        Some(user) => Ok(lockout::remaining(user)),
+        None => lockout::remaining_unknown(&data.db, &body.username).await,
+    };
+    match locked {
+        Ok(Some(retry_after)) => return account_locked(retry_after),
+        Ok(None) => {}
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get login attempts: {:?}", error)
+            }));
+        }
+    }
+
+    let user = match user {
+        Some(user) if valid => user,
+        Some(user) => return login_failed(&data, user.id, invalid_credentials()).await,
+        None => return unknown_login_failed(&data, &body.username).await,
+    };
+
+    if user.totp_enabled_at.is_some() {
+        return second_factor_response(&data, &user, TokenKind::MfaChallenge);
+    }
+    // Admins não recebem sessão sem TOTP: primeiro precisam cadastrar o segundo fator
+    if user.role == Role::Admin.as_str() {
+        return second_factor_response(&data, &user, TokenKind::TotpEnrollment);
+    }
+
+    if let Err(error) = lockout::reset(&data.db, user.id).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to record login attempt: {:?}", error)
+        }));
+    }
+
+    match tokens::issue_session(&data.db, &data.jwt, Subject::User(user.id), &user.role).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "user": user,
+            "tokens": token_response(tokens)
+        })),
+        Err(error) => token_error(error),
+    }
+}
+
+// Troca um refresh token por um novo par (o antigo deixa de valer)
+#[post("/auth/refresh")]
+async fn refresh(
+    body: Json<RefreshTokenSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    match tokens::rotate_refresh_token(&data.db, &data.jwt, &body.refresh_token).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "tokens": token_response(tokens)
+        })),
+        Err(error) => token_error(error),
+    }
+}
+
+// Encerra a sessão atual: o refresh token e os access tokens dela deixam de valer
+#[post("/auth/logout")]
+async fn logout(
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    let Some(session_id) = user.session_id() else {
+        return AuthError::SessionRequired.error_response();
+    };
+
+    match tokens::revoke_session(&data.db, session_id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to revoke session: {:?}", error)
+        })),
+    }
+}
+
+// Pais entram sem senha: pedem um link de uso único enviado ao e-mail cadastrado
+#[post("/auth/parents/magic-link")]
+async fn request_parent_magic_link(
+    body: Json<ParentMagicLinkSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    // A resposta é sempre a mesma para não revelar quais e-mails estão cadastrados
+    let accepted = HttpResponse::Accepted().json(json!({
+        "status": "success",
+        "message": "If the email is registered, a sign-in link has been sent"
+    }));
+
+    let parent = match sqlx::query_as!(
+        ParentModel,
+        "SELECT * FROM parents WHERE lower(email) = lower($1)",
+        body.email.trim()
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(parent)) => parent,
+        Ok(None) => return accepted,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get parent: {:?}", error)
+            }));
+        }
+    };
+
+    let token = tokens::generate_token();
+    let expires_at = Utc::now() + data.config.parent_login_token_ttl;
+
+    if let Err(error) = sqlx::query(
+        "INSERT INTO parent_login_tokens (parent_id, token_hash, expires_at) VALUES ($1, $2, $3)"
+    )
+    .bind(parent.id)
+    .bind(tokens::hash_token(&token))
+    .bind(expires_at)
+    .execute(&data.db)
+    .await
+    {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to create sign-in link: {:?}", error)
+        }));
+    }
+
+    let link = format!("{}/parent/login?token={}", data.config.portal_url.trim_end_matches('/'), token);
+    let email = Email {
+        to: parent.email.clone(),
+        subject: "Your sign-in link".to_string(),
+        body: format!(
+            "Hello {},\n\nUse this link to sign in to the parent portal:\n{}\n\nThe link works once and expires in {} minutes.",
+            parent.name, link, data.config.parent_login_token_ttl.num_minutes()
+        ),
+    };
+    if let Err(error) = data.mailer.send(email).await {
+        eprintln!("Failed to send parent sign-in link: {}", error);
+    }
+
+    accepted
+}
+
+// Troca o token do link por uma sessão de pai
+#[post("/auth/parents/verify")]
+async fn verify_parent_magic_link(
+    body: Json<ParentMagicLinkVerifySchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    // Marca o token como usado na mesma instrução que o valida: só funciona uma vez
+    let parent_id: Option<Uuid> = match sqlx::query_scalar(
+        r#"
+        UPDATE parent_login_tokens SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING parent_id
+        "#
+    )
+    .bind(tokens::hash_token(&body.token))
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(parent_id) => parent_id,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify sign-in link: {:?}", error)
+            }));
+        }
+    };
+
+    let Some(parent_id) = parent_id else {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "Invalid or expired sign-in link"
+        }));
+    };
+
+    let parent = match sqlx::query_as!(ParentModel, "SELECT * FROM parents WHERE id = $1", parent_id)
+        .fetch_one(&data.db)
+        .await
+    {
+        Ok(parent) => parent,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get parent: {:?}", error)
+            }));
+        }
+    };
+
+    match tokens::issue_session(&data.db, &data.jwt, Subject::Parent(parent.id), Role::Parent.as_str()).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "parent": parent,
+            "tokens": token_response(tokens)
+        })),
+        Err(error) => token_error(error),
+    }
+}
+
+// Cria a conta a partir de um convite enviado por um admin
+#[post("/auth/invitations/accept")]
+async fn accept_invitation(
+    body: Json<AcceptInvitationSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    if let Err(message) = validate_password(&body.password) {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": message
+        }));
+    }
+
+    let hashed_password = match hash_password(&body.password) {
+        Ok(hash) => hash,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to hash password: {:?}", error)
+            }));
+        }
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to start transaction: {:?}", error)
+            }));
+        }
+    };
+
+    let invitation = match sqlx::query_as::<_, UserInvitationModel>(
+        r#"
+        UPDATE user_invitations SET accepted_at = NOW()
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > NOW()
+        RETURNING id, email, role, invited_by, expires_at, accepted_at, created_at
+        "#
+    )
+    .bind(tokens::hash_token(&body.token))
+    .fetch_optional(&mut tx)
+    .await
+    {
+        Ok(Some(invitation)) => invitation,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(json!({
+                "status": "error",
+                "message": "Invalid or expired invitation"
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to accept invitation: {:?}", error)
+            }));
+        }
+    };
+
+    let user = match sqlx::query_as::<_, UserModel>(
+        r#"
+        INSERT INTO users (username, email, hashed_password, role)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#
+    )
+    .bind(&body.username)
+    .bind(&invitation.email)
+    .bind(&hashed_password)
+    .bind(&invitation.role)
+    .fetch_one(&mut tx)
+    .await
+    {
+        Ok(user) => user,
+        Err(error) => {
+            // O convite continua válido: o rollback desfaz o accepted_at
+            return HttpResponse::Conflict().json(json!({
+                "status": "error",
+                "message": format!("Failed to create user: {:?}", error)
+            }));
+        }
+    };
+
+    if let Err(error) = tx.commit().await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to accept invitation: {:?}", error)
+        }));
+    }
+
+    HttpResponse::Created().json(json!({
+        "status": "success",
+        "user": user
+    }))
+}
+
+// Envia um link para redefinir a senha
+#[post("/auth/password-reset")]
+async fn request_password_reset(
+    body: Json<PasswordResetRequestSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    // Mesma resposta para e-mails cadastrados ou não
+    let accepted = HttpResponse::Accepted().json(json!({
+        "status": "success",
+        "message": "If the email is registered, a reset link has been sent"
+    }));
+
+    let user = match sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE lower(email) = lower($1)",
+        body.email.trim()
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return accepted,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get user: {:?}", error)
+            }));
+        }
+    };
+
+    let Some(address) = user.email.clone() else {
+        return accepted;
+    };
+
+    let token = tokens::generate_token();
+    if let Err(error) = sqlx::query(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)"
+    )
+    .bind(user.id)
+    .bind(tokens::hash_token(&token))
+    .bind(Utc::now() + data.config.password_reset_ttl)
+    .execute(&data.db)
+    .await
+    {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to create reset token: {:?}", error)
+        }));
+    }
+
+    let link = format!("{}/reset-password?token={}", data.config.portal_url.trim_end_matches('/'), token);
+    let email = Email {
+        to: address,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hello {},\n\nUse this link to choose a new password:\n{}\n\nThe link works once and expires in {} minutes. If you did not ask for it, ignore this email.",
+            user.username, link, data.config.password_reset_ttl.num_minutes()
+        ),
+    };
+    if let Err(error) = data.mailer.send(email).await {
+        eprintln!("Failed to send password reset email: {}", error);
+    }
+
+    accepted
+}
+
+// Define a nova senha e encerra todas as sessões abertas do usuário
+#[post("/auth/password-reset/confirm")]
+async fn confirm_password_reset(
+    body: Json<PasswordResetConfirmSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    if let Err(message) = validate_password(&body.password) {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": message
+        }));
+    }
+
+    let hashed_password = match hash_password(&body.password) {
+        Ok(hash) => hash,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to hash password: {:?}", error)
+            }));
+        }
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to start transaction: {:?}", error)
+            }));
+        }
+    };
+
+    let user_id: Option<Uuid> = match sqlx::query_scalar(
+        r#"
+        UPDATE password_reset_tokens SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#
+    )
+    .bind(tokens::hash_token(&body.token))
+    .fetch_optional(&mut tx)
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify reset token: {:?}", error)
+            }));
+        }
+    };
+
+    let Some(user_id) = user_id else {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "Invalid or expired reset token"
+        }));
+    };
+
+    if let Err(error) = sqlx::query("UPDATE users SET hashed_password = $1, failed_login_attempts = 0, locked_until = NULL WHERE id = $2")
+        .bind(&hashed_password)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await
+    {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to reset password: {:?}", error)
+        }));
+    }
+
+    // Outros links pendentes deixam de valer
+    if let Err(error) = sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await
+    {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to reset password: {:?}", error)
+        }));
+    }
+
+    if let Err(error) = tx.commit().await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to reset password: {:?}", error)
+        }));
+    }
+
+    if let Err(error) = tokens::revoke_all_sessions(&data.db, user_id).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to revoke sessions: {:?}", error)
+        }));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Password has been reset"
+    }))
+}
+
+// Segundo passo do login quando o usuário tem TOTP ativo
+#[post("/auth/login/totp")]
+async fn login_totp(
+    body: Json<TotpLoginSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let claims = match data.jwt.decode_token_of_kind(&body.mfa_token, TokenKind::MfaChallenge) {
+        Ok(claims) => claims,
+        Err(_) => {
+            return HttpResponse::Unauthorized().json(json!({
+                "status": "error",
+                "message": "Invalid or expired two-factor challenge"
+            }));
+        }
+    };
+
+    let user = match get_user(&data, claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return invalid_second_factor(),
+        Err(response) => return response,
+    };
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret.clone(),
+        _ => return invalid_second_factor(),
+    };
+    if let Some(retry_after) = lockout::remaining(&user) {
+        return account_locked(retry_after);
+    }
+
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => totp::verify_code(&data.db, user.id, &secret, code).await,
+        (None, Some(recovery_code)) => totp::use_recovery_code(&data.db, user.id, recovery_code).await,
+        (None, None) => Ok(false),
+    };
+    match verified {
+        Ok(true) => {}
+        Ok(false) => return login_failed(&data, user.id, invalid_second_factor()).await,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify two-factor code: {:?}", error)
+            }));
+        }
+    }
+
+    if let Err(error) = lockout::reset(&data.db, user.id).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to record login attempt: {:?}", error)
+        }));
+    }
+
+    match tokens::issue_session(&data.db, &data.jwt, Subject::User(user.id), &user.role).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "user": user,
+            "tokens": token_response(tokens)
+        })),
+        Err(error) => token_error(error),
+    }
+}
+
+// Gera um novo segredo TOTP; só passa a valer depois de confirmado em /auth/totp/activate
+#[post("/auth/totp/setup")]
+async fn setup_totp(
+    caller: TotpCaller,
+    data: Data<AppState>
+) -> impl Responder {
+    if !caller.role.is_staff() {
+        return HttpResponse::Forbidden().json(json!({
+            "status": "error",
+            "message": "Two-factor authentication is only available to staff accounts"
+        }));
+    }
+
+    let secret = totp::generate_secret();
+    let user = match sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET totp_secret = $1 WHERE id = $2 AND totp_enabled_at IS NULL RETURNING *",
+        secret,
+        caller.id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(json!({
+                "status": "error",
+                "message": "Two-factor authentication is already enabled"
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to set up two-factor authentication: {:?}", error)
+            }));
+        }
+    };
+
+    let account = user.email.as_deref().unwrap_or(&user.username);
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "secret": secret,
+        "otpauth_url": totp::provisioning_uri(&data.config.totp_issuer, account, &secret)
+    }))
+}
+
+// Confirma o cadastro com o primeiro código e devolve os códigos de recuperação
+#[post("/auth/totp/activate")]
+async fn activate_totp(
+    caller: TotpCaller,
+    body: Json<TotpCodeSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let user = match get_user(&data, caller.id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return invalid_second_factor(),
+        Err(response) => return response,
+    };
+
+    if user.totp_enabled_at.is_some() {
+        return HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "Two-factor authentication is already enabled"
+        }));
+    }
+    let Some(secret) = &user.totp_secret else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Start with POST /api/auth/totp/setup"
+        }));
+    };
+    let Some(step) = totp::matching_step(secret, &body.code, Utc::now().timestamp()) else {
+        return invalid_second_factor();
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to start transaction: {:?}", error)
+            }));
+        }
+    };
+
+    // A condição no WHERE impede duas ativações concorrentes com o mesmo segredo
+    match sqlx::query(
+        "UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $1 WHERE id = $2 AND totp_secret = $3 AND totp_enabled_at IS NULL"
+    )
+    .bind(step)
+    .bind(user.id)
+    .bind(secret)
+    .execute(&mut tx)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 1 => {}
+        Ok(_) => {
+            return HttpResponse::Conflict().json(json!({
+                "status": "error",
+                "message": "Two-factor setup changed, start again"
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to enable two-factor authentication: {:?}", error)
+            }));
+        }
+    }
+
+    let recovery_codes = totp::generate_recovery_codes();
+    if let Err(error) = totp::store_recovery_codes(&mut tx, user.id, &recovery_codes).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to store recovery codes: {:?}", error)
+        }));
+    }
+
+    if let Err(error) = tx.commit().await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to enable two-factor authentication: {:?}", error)
+        }));
+    }
+
+    // Quem chegou aqui pelo token de cadastro ainda não tem sessão: ela é aberta agora
+    if caller.session_id.is_some() {
+        return HttpResponse::Ok().json(json!({
+            "status": "success",
+            "recovery_codes": recovery_codes
+        }));
+    }
+    match tokens::issue_session(&data.db, &data.jwt, Subject::User(user.id), &user.role).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "recovery_codes": recovery_codes,
+            "tokens": token_response(tokens)
+        })),
+        Err(error) => token_error(error),
+    }
+}
+
+// Troca os códigos de recuperação; os anteriores deixam de valer
+#[post("/auth/totp/recovery-codes")]
+async fn regenerate_recovery_codes(
+    caller: TotpCaller,
+    body: Json<TotpCodeSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let user = match get_user(&data, caller.id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return invalid_second_factor(),
+        Err(response) => return response,
+    };
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret.clone(),
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Two-factor authentication is not enabled"
+            }));
+        }
+    };
+
+    match totp::verify_code(&data.db, user.id, &secret, &body.code).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_second_factor(),
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify two-factor code: {:?}", error)
+            }));
+        }
+    }
+
+    let recovery_codes = totp::generate_recovery_codes();
+    let result = match data.db.begin().await {
+        Ok(mut tx) => match totp::store_recovery_codes(&mut tx, user.id, &recovery_codes).await {
+            Ok(()) => tx.commit().await,
+            Err(error) => Err(error),
+        },
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "recovery_codes": recovery_codes
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to store recovery codes: {:?}", error)
+        })),
+    }
+}
+
+// Desliga o TOTP da própria conta. Para admins ele é obrigatório
+#[delete("/auth/totp")]
+async fn disable_totp(
+    caller: TotpCaller,
+    body: Json<TotpCodeSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    if caller.role == Role::Admin {
+        return HttpResponse::Forbidden().json(json!({
+            "status": "error",
+            "message": "Two-factor authentication is mandatory for admin accounts"
+        }));
+    }
+
+    let user = match get_user(&data, caller.id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return invalid_second_factor(),
+        Err(response) => return response,
+    };
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret.clone(),
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Two-factor authentication is not enabled"
+            }));
+        }
+    };
+
+    match totp::verify_code(&data.db, user.id, &secret, &body.code).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_second_factor(),
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to verify two-factor code: {:?}", error)
+            }));
+        }
+    }
+
+    match totp::clear(&data.db, user.id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to disable two-factor authentication: {:?}", error)
+        })),
+    }
+}
+
+// Configuração das rotas de autenticação
+pub fn config_auth(conf: &mut ServiceConfig) {
+    conf.service(login)
+       .service(login_totp)
+       .service(refresh)
+       .service(logout)
+       .service(request_parent_magic_link)
+       .service(verify_parent_magic_link)
+       .service(accept_invitation)
+       .service(request_password_reset)
+       .service(confirm_password_reset)
+       .service(setup_totp)
+       .service(activate_totp)
+       .service(regenerate_recovery_codes)
+       .service(disable_totp);
+}
@@ -1,60 +1,29 @@
 use actix_web::{
     get, post, delete, patch,
     web::{Data, Json, Path, ServiceConfig, Query},
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use actix_multipart::Multipart; // Importação correta aqui
-use futures_util::StreamExt;
-use tokio::fs::File; // Use o tipo correto
-use tokio::io::AsyncWriteExt;
 use serde_json::json;
-use sqlx::PgPool;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
-use std::path::PathBuf;
-use std::fs;
 
-use crate::{model::DocumentModel, schema::{CreateDocumentSchema, UpdateDocumentSchema, FilterOptions}, AppState};
-
-const UPLOAD_DIR: &str = "uploads";
+use crate::{
+    auth::{permissions::{Permission, RequirePermission}, AuthenticatedUser},
+    model::DocumentModel, schema::{UpdateDocumentSchema, FilterOptions}, services::files, storage::{self, Envelope, MediaOwner, StagedFile, StorageError}, AppState
+};
 
-fn create_upload_dir() {
-    if !PathBuf::from(UPLOAD_DIR).exists() {
-        fs::create_dir(UPLOAD_DIR).unwrap();
+// Envio avulso: devolve a storage_key de cada arquivo gravado
+#[post("/upload", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn upload_document(req: HttpRequest, payload: Multipart, data: Data<AppState>) -> impl Responder {
+    // Sem espaço na instalação: recusa antes de receber o corpo
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, None, storage::content_length(&req).unwrap_or(0)).await {
+        return error.error_response();
     }
-}
-
-#[post("/upload")]
-async fn upload_document(mut payload: Multipart, _data: Data<AppState>) -> impl Responder {
-    create_upload_dir();
-
-    while let Some(field) = payload.next().await {
-        match field {
-            Ok(mut field) => {
-                let filename = field.content_disposition().get_filename()
-                    .map(|f| f.to_string())
-                    .unwrap_or_else(|| "default_filename".to_string());
-
-                let filepath = format!("{}/{}", UPLOAD_DIR, filename);
-                let mut f = File::create(&filepath).await.expect("Unable to create file");
-
-                while let Some(chunk) = field.next().await {
-                    match chunk {
-                        Ok(data) => {
-                            f.write_all(&data).await.expect("Unable to write data");
-                        },
-                        Err(e) => {
-                            return HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Error reading chunk: {:?}", e)}));
-                        }
-                    }
-                }
-            },
-            Err(e) => {
-                return HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Error reading field: {:?}", e)}));
-            }
-        }
+    match storage::save_fields(data.storage.as_ref(), &data.db, &data.config.storage, storage::DOCUMENTS, payload).await {
+        Ok(files) => HttpResponse::Ok().json(json!({"status": "success", "files": files})),
+        Err(e) => e.error_response(),
     }
-
-    HttpResponse::Ok().json(json!({"status": "success", "message": "File uploaded successfully."}))
 }
 
 #[get("/healthchecker")]
@@ -62,105 +31,130 @@ async fn health_checker() -> impl Responder {
     HttpResponse::Ok().json(json!({"status": "success", "message": "API is up and running smoothly."}))
 }
 
-#[post("/documents")]
-async fn create_document(mut payload: Multipart, data: Data<AppState>) -> impl Responder {
-    create_upload_dir(); // Certifique-se de que essa função cria a pasta de uploads
-
-    let mut student_id = String::new();
-    let mut doc_type = String::new();
-    let mut filename = String::new();
-
-    while let Some(item) = payload.next().await {
-        let mut field = item.unwrap(); // Agora mutável
+/// Inserts the row for a staged document, with the envelope of its data key when the
+/// file was encrypted. When the student already has a document with the same content,
+/// that one is returned instead, with `false`.
+async fn insert_document(
+    tx: &mut Transaction<'_, Postgres>,
+    student_id: Uuid,
+    doc_type: Option<&str>,
+    file: &StagedFile,
+    envelope: Option<&Envelope>,
+) -> Result<(DocumentModel, bool), sqlx::Error> {
+    let query = r#"
+        INSERT INTO documents (student_id, doc_type, filename, storage_key, sha256, bytes, encryption_key_id, encrypted_data_key)
+        VALUES ($1::uuid, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (student_id, sha256) DO NOTHING
+        RETURNING id, student_id, doc_type, filename, storage_key, sha256, bytes, created_at, encryption_key_id, encrypted_data_key
+    "#;
 
-        if field.name() == "student_id" {
-            student_id = field.fold(String::new(), |mut acc, data| async {
-                if let Ok(bytes) = data {
-                    acc.push_str(&String::from_utf8_lossy(&bytes));
-                }
-                acc
-            }).await;
-        } else if field.name() == "doc_type" {
-            doc_type = field.fold(String::new(), |mut acc, data| async {
-                if let Ok(bytes) = data {
-                    acc.push_str(&String::from_utf8_lossy(&bytes));
-                }
-                acc
-            }).await;
-        } else if field.name() == "file" {
-            if let Some(file_name) = field.content_disposition().get_filename() {
-                let file_path = format!("{}/{}", UPLOAD_DIR, file_name);
-                
-                // Usando tokio::fs::File para criar o arquivo
-                let mut f = File::create(&file_path).await.expect("Unable to create file");
+    let inserted = sqlx::query_as::<_, DocumentModel>(query)
+        .bind(student_id)
+        .bind(doc_type.unwrap_or_default())
+        .bind(&file.filename)
+        .bind(&file.storage_key)
+        .bind(&file.sha256)
+        .bind(file.size as i64)
+        .bind(envelope.map(|envelope| &envelope.key_id))
+        .bind(envelope.map(|envelope| &envelope.wrapped_key))
+        .fetch_optional(&mut *tx)
+        .await?;
 
-                // Armazenar o nome do arquivo antes de usar o campo
-                filename = file_name.to_string();
+    match inserted {
+        Some(document) => Ok((document, true)),
+        None => sqlx::query_as!(
+            DocumentModel,
+            "SELECT * FROM documents WHERE student_id = $1 AND sha256 = $2",
+            student_id,
+            file.sha256
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map(|document| (document, false)),
+    }
+}
 
-                // Usar um loop para escrever os chunks no arquivo
-                while let Some(chunk) = field.next().await {
-                    let data = chunk.unwrap();
-                    f.write_all(&data).await.expect("Unable to write data");
-                }
-            }
-        }
+#[post("/documents", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn create_document(req: HttpRequest, payload: Multipart, data: Data<AppState>) -> impl Responder {
+    // Sem espaço na instalação: recusa antes de receber o corpo
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, None, storage::content_length(&req).unwrap_or(0)).await {
+        return error.error_response();
     }
+    // O arquivo fica num temporário até o INSERT dar certo; qualquer falha descarta os dois
+    let content_length = storage::content_length(&req);
+    let form = match storage::read_upload_form(payload, &data.db, &data.config.storage, storage::DOCUMENTS, "file", content_length).await {
+        Ok(form) => form,
+        Err(error) => return error.error_response(),
+    };
 
     // Tenta converter student_id para UUID
-    let student_id_uuid = match Uuid::parse_str(&student_id) {
-        Ok(uuid) => uuid,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(json!({
-                "status": "error",
-                "message": "Invalid UUID format for student_id"
-            }));
-        }
+    let Some(student_id_uuid) = form.text("student_id").and_then(|id| Uuid::parse_str(id.trim()).ok()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Invalid UUID format for student_id"
+        }));
+    };
+    let Some(file) = &form.file else {
+        return HttpResponse::BadRequest().json(json!({"status": "error", "message": "Missing 'file' field"}));
     };
 
-    let query = r#"
-        INSERT INTO documents (student_id, doc_type, filename)
-        VALUES ($1::uuid, $2, $3)
-        RETURNING id, student_id, doc_type, filename, created_at
-    "#;
+    // Só o arquivo cifrado chega ao storage; sha256 e bytes seguem os do conteúdo em claro
+    let envelope = match file.encrypt(&data.config.storage.document_keys).await {
+        Ok(envelope) => envelope,
+        Err(error) => return error.error_response(),
+    };
 
-    match sqlx::query_as::<_, DocumentModel>(query)
-        .bind(student_id_uuid)
-        .bind(&doc_type)
-        .bind(&filename)
-        .fetch_one(&data.db)
-        .await
-    {
-        Ok(document) => {
-            HttpResponse::Ok().json(json!({
-                "status": "success",
-                "document": {
-                    "id": document.id,
-                    "student_id": document.student_id,
-                    "doc_type": document.doc_type,
-                    "filename": document.filename,
-                    "created_at": document.created_at
-                }
-            }))
-        }
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => return StorageError::Database(error).error_response(),
+    };
+
+    let (document, created) = match insert_document(&mut tx, student_id_uuid, form.text("doc_type"), file, envelope.as_ref()).await {
+        Ok(inserted) => inserted,
         Err(error) => {
-            HttpResponse::InternalServerError().json(json!({
+            return HttpResponse::InternalServerError().json(json!({
                 "status": "error",
                 "message": format!("Failed to create document: {:?}", error)
-            }))
+            }));
         }
+    };
+
+    // Conteúdo repetido: nada novo a gravar nem a contar na cota, o arquivo temporário é descartado
+    let stored = if created {
+        storage::commit_within_quota(tx, file, data.storage.as_ref(), &data.config.storage, student_id_uuid).await
+    } else {
+        Ok(())
+    };
+    match stored {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "duplicate": !created,
+            "document": data.url_signer.with_url(document.id, document)
+        })),
+        Err(error) => error.error_response(),
     }
 }
 
-#[get("/documents")]
-pub async fn get_all_documents(opts: Query<FilterOptions>, data: Data<AppState>) -> impl Responder {
+#[get("/documents", wrap = "RequirePermission(Permission::MediaRead)")]
+pub async fn get_all_documents(opts: Query<FilterOptions>, user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
     let offset = (opts.page.unwrap_or(1) - 1) * limit;
 
-    match sqlx::query_as!(DocumentModel, "SELECT * FROM documents ORDER BY id LIMIT $1 OFFSET $2", limit as i32, offset as i32)
+    match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE ($3::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $3)) ORDER BY id LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32,
+        user.parent_id()
+    )
         .fetch_all(&data.db)
         .await
     {
         Ok(documents) => {
+            let documents: Vec<_> = documents
+                .into_iter()
+                .map(|document| data.url_signer.with_url(document.id, document))
+                .collect();
             HttpResponse::Ok().json(json!({"status": "success", "documents": documents}))
         }
         Err(error) => {
@@ -169,16 +163,24 @@ pub async fn get_all_documents(opts: Query<FilterOptions>, data: Data<AppState>)
     }
 }
 
-#[get("/documents/{id}")]
-pub async fn get_document_by_id(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+#[get("/documents/{id}", wrap = "RequirePermission(Permission::MediaRead)")]
+pub async fn get_document_by_id(path: Path<Uuid>, user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
     let document_id = path.into_inner();
 
-    match sqlx::query_as!(DocumentModel, "SELECT * FROM documents WHERE id = $1", document_id)
+    match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = $1 AND ($2::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        document_id,
+        user.parent_id()
+    )
         .fetch_one(&data.db)
         .await
     {
         Ok(document) => {
-            HttpResponse::Ok().json(json!({"status": "success", "document": document}))
+            HttpResponse::Ok().json(json!({"status": "success", "document": data.url_signer.with_url(document.id, document)}))
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            HttpResponse::NotFound().json(json!({"status": "error", "message": "Document not found"}))
         }
         Err(error) => {
             HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Failed to get document: {:?}", error)}))
@@ -186,14 +188,12 @@ pub async fn get_document_by_id(path: Path<Uuid>, data: Data<AppState>) -> impl
     }
 }
 
-#[delete("/documents/{id}")]
+#[delete("/documents/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn delete_document_by_id(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
     let document_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM documents WHERE id = $1", document_id)
-        .execute(&data.db)
-        .await
-    {
+    // Os arquivos saem junto com o registro; os que falharem ficam na fila de remoção
+    match storage::delete_with_files(&data.db, data.storage.as_ref(), MediaOwner::Document(document_id)).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(err) => {
             HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Failed to delete document: {:?}", err)}))
@@ -201,7 +201,7 @@ async fn delete_document_by_id(path: Path<Uuid>, data: Data<AppState>) -> impl R
     }
 }
 
-#[patch("/documents/{id}")]
+#[patch("/documents/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn update_document_by_id(
     path: Path<Uuid>,
     body: Json<UpdateDocumentSchema>,
@@ -219,6 +219,9 @@ async fn update_document_by_id(
     .await
     {
         Ok(existing_document) => {
+            if let Some(response) = files::refuse_legacy_rename(body.filename.as_deref(), existing_document.storage_key.as_deref()) {
+                return response;
+            }
             // Atualizar o documento
             let update_result = sqlx::query_as!(
                 DocumentModel,
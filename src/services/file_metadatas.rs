@@ -1,79 +1,79 @@
 use actix_web::{
     get, post, delete, patch, web::{Data, Json, Path, Query, ServiceConfig},
-    HttpResponse, Responder
+    HttpRequest, HttpResponse, Responder, ResponseError
 };
-use std::fs::File; // Adicione esta linha
 use actix_multipart::Multipart;
-use futures_util::StreamExt;
-use tokio::fs::File as TokioFile; // Use um alias para tokio::fs::File
-use tokio::io::AsyncWriteExt;
 use serde_json::json;
-use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
+    auth::permissions::{Permission, RequirePermission},
     model::FileMetadataModel,
     schema::{CreateFileMetadataSchema, UpdateFileMetadataSchema, FilterOptions},
+    services::files,
+    storage::{self, MediaOwner},
     AppState
 };
 
-#[post("/file_metadatas/upload")]
+// Grava os arquivos sob chaves geradas e devolve a storage_key de cada um
+#[post("/file_metadatas/upload", wrap = "RequirePermission(Permission::FilesWrite)")]
 async fn upload_file(
-    mut payload: Multipart,
+    req: HttpRequest,
+    payload: Multipart,
     data: Data<AppState>
 ) -> impl Responder {
-    while let Some(field) = payload.next().await {
-        match field {
-            Ok(mut field) => { // Declare como mutável
-                let filename = field
-                    .content_disposition()
-                    .get_filename()
-                    .map(|f| f.to_string())
-                    .unwrap_or_else(|| "default_filename".to_string());
-
-                // Salva o arquivo
-                let filepath = format!("./uploads/{}", filename);
-                let mut f = TokioFile::create(&filepath).await.expect("Unable to create file");
-
-                while let Some(chunk) = field.next().await { // Agora field é mutável
-                    match chunk {
-                        Ok(data) => {
-                            f.write_all(&data).await.expect("Unable to write data");
-                        },
-                        Err(e) => {
-                            return HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Error reading chunk: {:?}", e)}));
-                        }
-                    }
-                }
-            },
-            Err(e) => {
-                return HttpResponse::InternalServerError().json(json!({"status": "error", "message": format!("Error reading field: {:?}", e)}));
-            }
-        }
+    // Sem espaço na instalação: recusa antes de receber o corpo
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, None, storage::content_length(&req).unwrap_or(0)).await {
+        return error.error_response();
+    }
+    match storage::save_fields(data.storage.as_ref(), &data.db, &data.config.storage, storage::FILES, payload).await {
+        Ok(files) => HttpResponse::Ok().json(json!({"status": "success", "files": files})),
+        Err(e) => e.error_response(),
     }
-
-    HttpResponse::Ok().json(json!({"status": "success", "message": "File uploaded successfully."}))
 }
 
 
-#[post("/file_metadatas")]
+#[post("/file_metadatas", wrap = "RequirePermission(Permission::FilesWrite)")]
 async fn create_file_metadata(
     body: Json<CreateFileMetadataSchema>,
     data: Data<AppState>
 ) -> impl Responder {
+    // Sem chave o registro seria tratado como arquivo antigo de uploads/ pela reconciliação
+    let Some(storage_key) = &body.storage_key else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "storage_key is required"
+        }));
+    };
+    if !storage::is_uploaded(data.storage.as_ref(), storage::FILES, storage_key).await {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "storage_key does not match an uploaded file"
+        }));
+    }
+    // Hash e tamanho vêm do objeto gravado, nunca do cliente
+    let sha256 = match storage::hash_object(data.storage.as_ref(), storage_key).await {
+        Ok(hash) => hash,
+        Err(error) => return error.error_response(),
+    };
+    let bytes = match data.storage.stat(storage_key).await {
+        Ok(object) => object.map(|object| object.size as i64),
+        Err(error) => return error.error_response(),
+    };
+
     let query = r#"
-        INSERT INTO file_metadata (user_id, file_type, filename, description)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id, user_id, file_type, filename, description, uploaded_at
+        INSERT INTO file_metadata (user_id, file_type, filename, storage_key, sha256, bytes, description)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, file_type, filename, storage_key, sha256, bytes, description, uploaded_at
     "#;
 
-    // Gera um nome de arquivo com base no UUID
-    let filename = format!("document_{}.jpg", Uuid::new_v4());
-
     match sqlx::query_as::<_, FileMetadataModel>(query)
-        .bind(&body.user_id)
+        .bind(body.user_id)
         .bind(&body.file_type)
-        .bind(&filename)
+        .bind(storage::original_filename(&body.filename))
+        .bind(storage_key)
+        .bind(&sha256)
+        .bind(bytes)
         .bind(&body.description)
         .fetch_one(&data.db) // Certifique-se de usar o pool de conexão correto
         .await
@@ -86,12 +86,19 @@ async fn create_file_metadata(
                     "user_id": file_metadata.user_id,
                     "file_type": file_metadata.file_type,
                     "filename": file_metadata.filename,
+                    "sha256": file_metadata.sha256,
                     "description": file_metadata.description,
                     "uploaded_at": file_metadata.uploaded_at
                 }
             });
             HttpResponse::Ok().json(response)
         }
+        Err(error) if storage::is_key_taken(&error) => {
+            HttpResponse::Conflict().json(json!({
+                "status": "error",
+                "message": "This upload is already attached to another file_metadata"
+            }))
+        }
         Err(error) => {
             let response = json!({
                 "status": "error",
@@ -102,7 +109,7 @@ async fn create_file_metadata(
     }
 }
 
-#[get("/file_metadatas")]
+#[get("/file_metadatas", wrap = "RequirePermission(Permission::FilesRead)")]
 pub async fn get_all_file_metadatas(
     opts: Query<FilterOptions>,
     data: Data<AppState>
@@ -136,7 +143,7 @@ pub async fn get_all_file_metadatas(
     }
 }
 
-#[get("/file_metadatas/{id}")]
+#[get("/file_metadatas/{id}", wrap = "RequirePermission(Permission::FilesRead)")]
 async fn get_file_metadata_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -168,7 +175,7 @@ async fn get_file_metadata_by_id(
     }
 }
 
-#[patch("/file_metadatas/{id}")]
+#[patch("/file_metadatas/{id}", wrap = "RequirePermission(Permission::FilesWrite)")]
 async fn update_file_metadata_by_id(
     path: Path<Uuid>,
     body: Json<UpdateFileMetadataSchema>,
@@ -181,6 +188,9 @@ async fn update_file_metadata_by_id(
         .await
     {
         Ok(file_metadata) => {
+            if let Some(response) = files::refuse_legacy_rename(body.filename.as_deref(), file_metadata.storage_key.as_deref()) {
+                return response;
+            }
             let update_result = sqlx::query_as!(
                 FileMetadataModel,
                 "UPDATE file_metadata SET user_id = COALESCE($1, user_id), file_type = COALESCE($2, file_type), filename = COALESCE($3, filename), description = COALESCE($4, description) WHERE id = $5 RETURNING *",
@@ -220,17 +230,15 @@ async fn update_file_metadata_by_id(
     }
 }
 
-#[delete("/file_metadatas/{id}")]
+#[delete("/file_metadatas/{id}", wrap = "RequirePermission(Permission::FilesWrite)")]
 async fn delete_file_metadata_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
 ) -> impl Responder {
     let file_metadata_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM file_metadata WHERE id = $1", file_metadata_id)
-        .execute(&data.db)
-        .await
-    {
+    // Os arquivos saem junto com o registro; os que falharem ficam na fila de remoção
+    match storage::delete_with_files(&data.db, data.storage.as_ref(), MediaOwner::FileMetadata(file_metadata_id)).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(err) => {
             let response = json!({
@@ -0,0 +1,341 @@
+use std::ops::Range;
+use std::path::{Component, Path as FsPath, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use actix_files::NamedFile;
+use actix_web::{
+    get,
+    http::header::{
+        Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, HeaderValue, HttpDate, TryIntoHeaderValue,
+        ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    web::{Data, Path, Query, ServiceConfig},
+    HttpRequest, HttpResponse, Responder
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    config::MasterKey,
+    schema::SignedFileQuery,
+    storage::{self, Envelope, StorageBackend, StorageError},
+    AppState
+};
+
+// Onde os uploads ficavam antes das storage_keys; registros antigos não têm chave
+pub const LEGACY_PHOTOS_DIR: &str = "./static";
+pub const LEGACY_UPLOADS_DIR: &str = "./uploads";
+
+/// What a `Range` header asks of an object of a given size.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range: the whole object is sent.
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+/// Resolves a `Range` header against an object of `size` bytes. Only a single byte range
+/// is honoured; anything else is ignored, as RFC 9110 allows, and the whole object is
+/// sent.
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    // Vários intervalos exigiriam multipart/byteranges
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // "bytes=-500": os últimos 500 bytes
+        return match end.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if size == 0 => RangeRequest::Unsatisfiable,
+            Ok(suffix) => RangeRequest::Partial(size.saturating_sub(suffix)..size),
+            Err(_) => RangeRequest::Full,
+        };
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+    let last = match end {
+        "" => u64::MAX,
+        end => match end.parse::<u64>() {
+            Ok(last) => last,
+            Err(_) => return RangeRequest::Full,
+        },
+    };
+    if last < start {
+        RangeRequest::Full
+    } else if start >= size {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(start..last.min(size - 1) + 1)
+    }
+}
+
+// If-Range: o intervalo só vale se o arquivo ainda for o mesmo que o cliente tem
+fn if_range_matches(req: &HttpRequest, etag: Option<&str>, last_modified: Option<DateTime<Utc>>) -> bool {
+    let Some(value) = req.headers().get(IF_RANGE).and_then(|value| value.to_str().ok()) else {
+        return true;
+    };
+    if value.starts_with('"') {
+        return etag == Some(value);
+    }
+    match (HttpDate::from_str(value), last_modified) {
+        (Ok(date), Some(last_modified)) => {
+            DateTime::<Utc>::from(SystemTime::from(date)).timestamp() == last_modified.timestamp()
+        }
+        // ETag fraca (W/) ou data ilegível: nunca casa, vai o arquivo inteiro
+        _ => false,
+    }
+}
+
+/// Streams the object under `key`, honouring `Range` and `If-Range`: 206 with the
+/// requested bytes, 416 for a range past the end, or 200 with everything. `sha256` is
+/// the content checksum on record, used as the strong ETag.
+pub async fn stream_object(
+    req: &HttpRequest,
+    storage: &dyn StorageBackend,
+    key: &str,
+    sha256: Option<&str>,
+) -> Result<HttpResponse, StorageError> {
+    let info = storage.stat(key).await?.ok_or_else(|| StorageError::NotFound(key.to_string()))?;
+    let etag = sha256.map(|sha256| format!("\"{}\"", sha256));
+
+    let range = match req.headers().get(RANGE).and_then(|value| value.to_str().ok()) {
+        Some(header) if if_range_matches(req, etag.as_deref(), info.last_modified) => parse_range(header, info.size),
+        _ => RangeRequest::Full,
+    };
+
+    // Tipo pela extensão da chave, que segue o formato detectado no upload
+    let content_type = mime_guess::from_path(key).first_or_octet_stream();
+    let mut response = match range {
+        RangeRequest::Unsatisfiable => HttpResponse::RangeNotSatisfiable()
+            .insert_header((CONTENT_RANGE, format!("bytes */{}", info.size)))
+            .json(json!({
+                "status": "error",
+                "message": "Requested range not satisfiable"
+            })),
+        RangeRequest::Full => {
+            let object = storage.get_range(key, None).await?;
+            HttpResponse::Ok()
+                .content_type(content_type.essence_str())
+                .no_chunking(object.length)
+                .streaming(object.stream)
+        }
+        RangeRequest::Partial(range) => {
+            let object = storage.get_range(key, Some(range.clone())).await?;
+            HttpResponse::PartialContent()
+                .content_type(content_type.essence_str())
+                .insert_header((CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end - 1, object.size)))
+                .no_chunking(object.length)
+                .streaming(object.stream)
+        }
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        headers.insert(ETAG, value);
+    }
+    if let Some(last_modified) = info.last_modified {
+        let date = HttpDate::from(SystemTime::from(last_modified));
+        if let Ok(value) = HeaderValue::from_str(&date.to_string()) {
+            headers.insert(LAST_MODIFIED, value);
+        }
+    }
+    Ok(response)
+}
+
+/// Streams a document stored encrypted, decrypted on the way out. Ranges would need the
+/// chunks around them decrypted, so the whole file is always sent, with 200.
+pub async fn stream_encrypted(
+    storage: &dyn StorageBackend,
+    keys: &[MasterKey],
+    key: &str,
+    envelope: &Envelope,
+    sha256: Option<&str>,
+) -> Result<HttpResponse, StorageError> {
+    let object = storage::open_document(storage, keys, key, Some(envelope)).await?;
+    let content_type = mime_guess::from_path(key).first_or_octet_stream();
+    let mut response = HttpResponse::Ok()
+        .content_type(content_type.essence_str())
+        .insert_header((ACCEPT_RANGES, "none"))
+        .no_chunking(object.length)
+        .streaming(object.stream);
+
+    // O sha256 é do conteúdo em claro, que é o que sai daqui
+    if let Some(value) = sha256.and_then(|sha256| HeaderValue::from_str(&format!("\"{}\"", sha256)).ok()) {
+        response.headers_mut().insert(ETAG, value);
+    }
+    Ok(response)
+}
+
+/// Serves a file saved before storage keys existed, from the local `dir`. `NamedFile`
+/// handles ranges itself. `None` when the name is unsafe or the file is gone.
+pub async fn legacy_file(req: &HttpRequest, dir: &str, filename: &str) -> Option<HttpResponse> {
+    let file = NamedFile::open_async(legacy_path(dir, filename)?).await.ok()?;
+    Some(file.into_response(req))
+}
+
+/// Refuses a new `filename` for a record stored before storage keys. On those records the
+/// filename is the file's path on disk, so changing it would point the record at another
+/// file; returns the 400 to send back, if any.
+pub fn refuse_legacy_rename(filename: Option<&str>, storage_key: Option<&str>) -> Option<HttpResponse> {
+    (filename.is_some() && storage_key.is_none()).then(|| {
+        HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "filename cannot be changed on files stored before storage keys"
+        }))
+    })
+}
+
+/// Where a file saved before storage keys existed lives, or `None` when the name is unsafe.
+pub fn legacy_path(dir: &str, filename: &str) -> Option<PathBuf> {
+    Some(FsPath::new(dir).join(legacy_filename(filename)?))
+}
+
+// O filename vem do banco, mas foi enviado pelo cliente. Os uploads antigos ficavam todos
+// direto no diretório, então só um nome simples vale: nada de "..", caminhos absolutos ou
+// subpastas, que é onde o storage local guarda as chaves (photos/aa/bb/...) quando
+// STORAGE_DIR é o mesmo ./uploads.
+fn legacy_filename(filename: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(filename);
+    let mut components = path.components();
+    let is_safe = matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+    is_safe.then_some(path)
+}
+
+fn download_filename(filename: &str) -> Vec<DispositionParam> {
+    let mut parameters = vec![DispositionParam::Filename(filename.to_string())];
+    if !filename.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: filename.as_bytes().to_vec(),
+        }));
+    }
+    parameters
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "status": "error",
+        "message": "File not found"
+    }))
+}
+
+// Tipo, storage_key, filename, sha256 e o envelope da chave dos documentos cifrados
+type FileRow = (String, Option<String>, String, Option<String>, Option<String>, Option<Vec<u8>>);
+
+// Entrega o arquivo de uma foto, vídeo ou documento a partir de um link assinado.
+// Não exige token: o link emitido pelos GETs de cada recurso é a autorização.
+#[get("/files/{id}")]
+async fn download_file(
+    req: HttpRequest,
+    path: Path<Uuid>,
+    query: Query<SignedFileQuery>,
+    data: Data<AppState>
+) -> impl Responder {
+    let file_id = path.into_inner();
+    let now = Utc::now().timestamp();
+
+    if !data.url_signer.verify(file_id, query.exp, &query.sig, now) {
+        return HttpResponse::Forbidden().json(json!({
+            "status": "error",
+            "message": "Invalid or expired file link"
+        }));
+    }
+
+    let file: Option<FileRow> = match sqlx::query_as(
+        r#"
+        SELECT 'photo', storage_key, filename, sha256, NULL::text, NULL::bytea FROM photos WHERE id = $1
+        UNION ALL SELECT 'video', storage_key, filename, sha256, NULL, NULL FROM videos WHERE id = $1
+        UNION ALL SELECT 'document', storage_key, filename, sha256, encryption_key_id, encrypted_data_key FROM documents WHERE id = $1
+        LIMIT 1
+        "#
+    )
+    .bind(file_id)
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(file) => file,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get file: {:?}", error)
+            }));
+        }
+    };
+
+    let Some((kind, storage_key, filename, sha256, key_id, wrapped_key)) = file else {
+        return not_found();
+    };
+
+    let mut response = match storage_key {
+        Some(storage_key) => {
+            // Documentos cifrados saem decifrados
+            let streamed = match Envelope::from_record(key_id, wrapped_key) {
+                Some(envelope) => {
+                    let keys = &data.config.storage.document_keys;
+                    stream_encrypted(data.storage.as_ref(), keys, &storage_key, &envelope, sha256.as_deref()).await
+                }
+                None => stream_object(&req, data.storage.as_ref(), &storage_key, sha256.as_deref()).await,
+            };
+            match streamed {
+                Ok(mut response) => {
+                    response.headers_mut().insert(CONTENT_DISPOSITION, disposition(&storage_key, &filename));
+                    response
+                }
+                Err(StorageError::NotFound(_)) => return not_found(),
+                Err(error) => {
+                    return HttpResponse::InternalServerError().json(json!({
+                        "status": "error",
+                        "message": error.to_string()
+                    }));
+                }
+            }
+        }
+        // Registros anteriores às storage_keys continuam no disco local, pelo filename
+        None => {
+            let dir = if kind == "photo" { LEGACY_PHOTOS_DIR } else { LEGACY_UPLOADS_DIR };
+            match legacy_file(&req, dir, &filename).await {
+                Some(response) => response,
+                None => return not_found(),
+            }
+        }
+    };
+
+    // O navegador pode guardar a resposta enquanto o link valer, mas proxies não
+    let max_age = (query.exp - now).max(0);
+    if let Ok(value) = HeaderValue::from_str(&format!("private, max-age={}", max_age)) {
+        response.headers_mut().insert(CACHE_CONTROL, value);
+    }
+    response
+}
+
+// Mídia abre no navegador, o resto é baixado; o download usa o nome original, não o UUID
+fn disposition(storage_key: &str, filename: &str) -> HeaderValue {
+    let content_type = mime_guess::from_path(storage_key).first_or_octet_stream();
+    let disposition = match content_type.type_() {
+        mime_guess::mime::IMAGE | mime_guess::mime::VIDEO | mime_guess::mime::AUDIO => DispositionType::Inline,
+        _ => DispositionType::Attachment,
+    };
+    let header = ContentDisposition {
+        disposition,
+        parameters: download_filename(filename),
+    };
+    header.try_into_value().unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+pub fn config_files(conf: &mut ServiceConfig) {
+    conf.service(download_file);
+}
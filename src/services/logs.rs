@@ -1,16 +1,16 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, scope, Query, Path, ServiceConfig}, HttpResponse, Responder
+    get, post, delete, patch, web::{Data, Json, Query, Path, ServiceConfig}, HttpResponse, Responder
 };
 use serde_json::json;
 use crate::{
+    auth::permissions::{Permission, RequirePermission},
     model::LogModel,
     schema::{CreateLogSchema, UpdateLogSchema, FilterOptions},
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-#[post("/logs")]
+#[post("/logs", wrap = "RequirePermission(Permission::LogsWrite)")]
 async fn create_log(
     body: Json<CreateLogSchema>,
     data: Data<AppState>
@@ -22,7 +22,7 @@ async fn create_log(
     "#;
 
     match sqlx::query_as::<_, LogModel>(query)
-        .bind(&body.user_id)
+        .bind(body.user_id)
         .bind(&body.action)
         .bind(&body.description)
         .fetch_one(&data.db)
@@ -51,7 +51,7 @@ async fn create_log(
     }
 }
 
-#[get("/logs")]
+#[get("/logs", wrap = "RequirePermission(Permission::LogsRead)")]
 pub async fn get_all_logs(
     opts: Query<FilterOptions>,
     data: Data<AppState>
@@ -85,7 +85,7 @@ pub async fn get_all_logs(
     }
 }
 
-#[get("/logs/{id}")]
+#[get("/logs/{id}", wrap = "RequirePermission(Permission::LogsRead)")]
 async fn get_log_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -117,7 +117,7 @@ async fn get_log_by_id(
     }
 }
 
-#[patch("/logs/{id}")]
+#[patch("/logs/{id}", wrap = "RequirePermission(Permission::LogsWrite)")]
 async fn update_log_by_id(
     path: Path<Uuid>,
     body: Json<UpdateLogSchema>,
@@ -129,7 +129,7 @@ async fn update_log_by_id(
         .fetch_one(&data.db)
         .await
     {
-        Ok(log) => {
+        Ok(_) => {
             let update_result = sqlx::query_as!(
                 LogModel,
                 "UPDATE logs SET user_id = COALESCE($1, user_id), action = COALESCE($2, action), description = COALESCE($3, description) WHERE id = $4 RETURNING *",
@@ -168,7 +168,7 @@ async fn update_log_by_id(
     }
 }
 
-#[delete("/logs/{id}")]
+#[delete("/logs/{id}", wrap = "RequirePermission(Permission::LogsDelete)")]
 async fn delete_log_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -2,28 +2,43 @@ pub mod tasks;
 pub mod documents;
 pub mod users;
 pub mod students;
+pub mod archives;
 pub mod parents;
 pub mod photos;
 pub mod videos;
+pub mod video_uploads;
 pub mod file_metadatas;
 pub mod logs;
 pub mod health;
+pub mod auth;
+pub mod api_keys;
+pub mod files;
+pub mod storage;
 
 use actix_web::web::ServiceConfig;
 
+use crate::auth::middleware::Authentication;
+
 pub fn config(conf: &mut ServiceConfig) {
     conf.service(
         actix_web::web::scope("/api")
+            .wrap(Authentication) // Exige token em todas as rotas, exceto as públicas
             .configure(health::config_health)
+            .configure(auth::config_auth)
             .configure(tasks::config_tasks)
             //.configure(documents::config_documents)
             .configure(documents::configure_services) // Updated to call configure_services
             .configure(users::config_users)
+            .configure(api_keys::config_api_keys)
             .configure(students::config_students)
+            .configure(archives::config_archives)
             .configure(parents::config_parents)
             .configure(photos::config_photos)
+            .configure(video_uploads::config_video_uploads)
             .configure(videos::config_videos)
             .configure(file_metadatas::config_file_metadatas)
+            .configure(files::config_files)
+            .configure(storage::config_storage)
             .configure(logs::config_logs)
     );
 }
@@ -1,16 +1,16 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, scope, Query, Path, ServiceConfig}, HttpResponse, Responder
+    get, post, delete, patch, web::{Data, Json, Query, Path, ServiceConfig}, HttpResponse, Responder
 };
 use serde_json::json;
 use crate::{
+    auth::permissions::{Permission, RequirePermission},
     model::ParentModel,
     schema::{CreateParentSchema, UpdateParentSchema, FilterOptions},
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-#[post("/parents")]
+#[post("/parents", wrap = "RequirePermission(Permission::ParentsWrite)")]
 async fn create_parent(
     body: Json<CreateParentSchema>,
     data: Data<AppState>
@@ -51,7 +51,7 @@ async fn create_parent(
     }
 }
 
-#[get("/parents")]
+#[get("/parents", wrap = "RequirePermission(Permission::ParentsRead)")]
 pub async fn get_all_parents(
     opts: Query<FilterOptions>,
     data: Data<AppState>
@@ -85,7 +85,7 @@ pub async fn get_all_parents(
     }
 }
 
-#[get("/parents/{id}")]
+#[get("/parents/{id}", wrap = "RequirePermission(Permission::ParentsRead)")]
 async fn get_parent_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -117,7 +117,7 @@ async fn get_parent_by_id(
     }
 }
 
-#[patch("/parents/{id}")]
+#[patch("/parents/{id}", wrap = "RequirePermission(Permission::ParentsWrite)")]
 async fn update_parent_by_id(
     path: Path<Uuid>,
     body: Json<UpdateParentSchema>,
@@ -129,7 +129,7 @@ async fn update_parent_by_id(
         .fetch_one(&data.db)
         .await
     {
-        Ok(parent) => {
+        Ok(_) => {
             let update_result = sqlx::query_as!(
                 ParentModel,
                 "UPDATE parents SET name = COALESCE($1, name), email = COALESCE($2, email), phone = COALESCE($3, phone) WHERE id = $4 RETURNING *",
@@ -168,7 +168,7 @@ async fn update_parent_by_id(
     }
 }
 
-#[delete("/parents/{id}")]
+#[delete("/parents/{id}", wrap = "RequirePermission(Permission::ParentsWrite)")]
 async fn delete_parent_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -1,81 +1,252 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, Path, Query, ServiceConfig},
-    HttpResponse, Responder
+    get, post, delete, patch,
+    http::header::{HeaderValue, ACCEPT, CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY},
+    web::{self, Data, Json, Path, Query, ServiceConfig},
+    HttpRequest, HttpResponse, Responder, ResponseError
 };
-use actix_files::Files;
 use actix_multipart::Multipart;
-use futures_util::StreamExt;
-use tokio::io::AsyncWriteExt;
 use serde_json::json;
-use sqlx::PgPool;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 use crate::{
-    model::{PhotoModel, StudentModel},
-    schema::{CreatePhotoSchema, UpdatePhotoSchema, FilterOptions},
+    auth::{
+        permissions::{Permission, RequirePermission},
+        AuthenticatedUser,
+    },
+    images::{self, VariantFormat, VariantSize},
+    model::{PhotoModel, PhotoVariantModel, StudentModel},
+    schema::{UpdatePhotoSchema, PhotoFilterOptions, VariantQuery},
+    services::files,
+    storage::{self, MediaOwner, StagedFile, StorageError},
     AppState
 };
 
-// Função para criar uma nova foto
-#[post("/photos")]
-async fn create_photo(
-    body: Json<CreatePhotoSchema>,
-    data: Data<AppState>
-) -> impl Responder {
+/// Inserts the row for a staged photo file. When the student already has a photo with
+/// the same content, that one is returned instead, with `false`.
+async fn insert_photo(
+    tx: &mut Transaction<'_, Postgres>,
+    student_id: Uuid,
+    description: Option<&str>,
+    file: &StagedFile,
+) -> Result<(PhotoModel, bool), sqlx::Error> {
     let query = r#"
-        INSERT INTO photos (student_id, filename, description)
-        VALUES ($1, $2, $3)
-        RETURNING id, student_id, filename, description, created_at
+        INSERT INTO photos (student_id, filename, storage_key, sha256, bytes, description, taken_at, orientation)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (student_id, sha256) DO NOTHING
+        RETURNING id, student_id, filename, storage_key, sha256, bytes, description, taken_at, orientation, created_at
     "#;
 
-    match sqlx::query_as::<_, PhotoModel>(query)
-        .bind(&body.student_id)
-        .bind(&body.filename)
-        .bind(&body.description)
-        .fetch_one(&data.db)
+    let inserted = sqlx::query_as::<_, PhotoModel>(query)
+        .bind(student_id)
+        .bind(&file.filename)
+        .bind(&file.storage_key)
+        .bind(&file.sha256)
+        .bind(file.size as i64)
+        .bind(description.unwrap_or_default())
+        .bind(file.metadata.taken_at)
+        .bind(file.metadata.orientation.map(i16::from))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    match inserted {
+        Some(photo) => Ok((photo, true)),
+        None => sqlx::query_as!(
+            PhotoModel,
+            "SELECT * FROM photos WHERE student_id = $1 AND sha256 = $2",
+            student_id,
+            file.sha256
+        )
+        .fetch_one(&mut *tx)
         .await
-    {
-        Ok(photo) => {
-            let response = json!({
-                "status": "success",
-                "photo": {
-                    "id": photo.id,
-                    "student_id": photo.student_id,
-                    "filename": photo.filename,
-                    "description": photo.description,
-                    "created_at": photo.created_at
-                }
-            });
-            HttpResponse::Ok().json(response)
-        }
+        .map(|photo| (photo, false)),
+    }
+}
+
+// Variantes não mudam depois de geradas: o navegador pode guardá-las por um ano
+const VARIANT_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
+/// Generates the variants a photo is still missing from its original file and records
+/// them. Photos from before variants existed get theirs the first time one is asked for.
+async fn ensure_variants(data: &AppState, photo: &PhotoModel) -> Result<Vec<PhotoVariantModel>, StorageError> {
+    let existing = sqlx::query_as!(
+        PhotoVariantModel,
+        "SELECT * FROM photo_variants WHERE photo_id = $1",
+        photo.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(StorageError::Database)?;
+
+    // Registros antigos sem storage_key ficam sem variantes
+    let Some(storage_key) = photo.storage_key.clone() else {
+        return Ok(existing);
+    };
+    if existing.len() == VariantSize::ALL.len() * VariantFormat::ALL.len() {
+        return Ok(existing);
+    }
+
+    let original = storage::read_object(data.storage.as_ref(), &storage_key, data.config.storage.max_image_bytes).await?;
+    let orientation = photo.orientation.and_then(|orientation| u8::try_from(orientation).ok());
+    let variants = web::block(move || images::render_variants(&original, orientation))
+        .await
+        .map_err(|error| StorageError::Image(error.to_string()))?
+        .map_err(|error| StorageError::Image(error.to_string()))?;
+
+    let mut recorded = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let key = images::variant_key(&storage_key, variant.size, variant.format);
+        storage::put_bytes(data.storage.as_ref(), &data.config.storage, &key, &variant.bytes).await?;
+
+        let query = r#"
+            INSERT INTO photo_variants (photo_id, size, format, storage_key, width, height, bytes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (photo_id, size, format) DO UPDATE
+            SET width = EXCLUDED.width, height = EXCLUDED.height, bytes = EXCLUDED.bytes
+            RETURNING photo_id, size, format, storage_key, width, height, bytes, created_at
+        "#;
+        let row = sqlx::query_as::<_, PhotoVariantModel>(query)
+            .bind(photo.id)
+            .bind(variant.size.name())
+            .bind(variant.format.name())
+            .bind(&key)
+            .bind(variant.width as i32)
+            .bind(variant.height as i32)
+            .bind(variant.bytes.len() as i64)
+            .fetch_one(&data.db)
+            .await
+            .map_err(StorageError::Database)?;
+        recorded.push(row);
+    }
+    Ok(recorded)
+}
+
+fn accepts_webp(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("image/webp"))
+}
+
+fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| matches!(tag.trim(), "*") || tag.trim() == etag))
+}
+
+// Função para criar uma nova foto. Recebe student_id, description e o arquivo no
+// mesmo formulário multipart: o registro só é gravado junto com o arquivo.
+#[post("/photos", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn create_photo(
+    req: HttpRequest,
+    payload: Multipart,
+    data: Data<AppState>
+) -> impl Responder {
+    // Sem espaço na instalação: recusa antes de receber o corpo
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, None, storage::content_length(&req).unwrap_or(0)).await {
+        return error.error_response();
+    }
+    let content_length = storage::content_length(&req);
+    let form = match storage::read_upload_form(payload, &data.db, &data.config.storage, storage::PHOTOS, "file", content_length).await {
+        Ok(form) => form,
+        Err(error) => return error.error_response(),
+    };
+
+    let Some(student_id) = form.text("student_id").and_then(|id| Uuid::parse_str(id.trim()).ok()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Invalid UUID format for student_id"
+        }));
+    };
+    let Some(file) = &form.file else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Missing 'file' field"
+        }));
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => return StorageError::Database(error).error_response(),
+    };
+
+    let (photo, created) = match insert_photo(&mut tx, student_id, form.text("description"), file).await {
+        Ok(inserted) => inserted,
         Err(error) => {
             let response = json!({
                 "status": "error",
                 "message": format!("Failed to create photo: {:?}", error)
             });
-            HttpResponse::InternalServerError().json(response)
+            return HttpResponse::InternalServerError().json(response);
         }
+    };
+
+    // Conteúdo repetido: nada novo a gravar nem a contar na cota, o arquivo temporário é descartado
+    let stored = if created {
+        storage::commit_within_quota(tx, file, data.storage.as_ref(), &data.config.storage, student_id).await
+    } else {
+        Ok(())
+    };
+    match stored {
+        Ok(()) => {
+            // A foto já está salva; se as variantes falharem, são geradas no primeiro pedido
+            let variants = match ensure_variants(&data, &photo).await {
+                Ok(variants) => variants,
+                Err(error) => {
+                    eprintln!("Failed to generate variants for photo {}: {}", photo.id, error);
+                    Vec::new()
+                }
+            };
+            let response = json!({
+                "status": "success",
+                "duplicate": !created,
+                "photo": data.url_signer.with_url(photo.id, photo),
+                "variants": variants
+            });
+            HttpResponse::Ok().json(response)
+        }
+        Err(error) => error.error_response(),
     }
 }
 
 // Função para obter todas as fotos
-#[get("/photos")]
+#[get("/photos", wrap = "RequirePermission(Permission::MediaRead)")]
 pub async fn get_all_photos(
-    opts: Query<FilterOptions>,
+    opts: Query<PhotoFilterOptions>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
     let offset = (opts.page.unwrap_or(1) - 1) * limit;
 
+    // sort=taken_at: mais recentes primeiro pela data da foto; sem data vão para o fim
+    let by_taken_at = match opts.sort.as_deref() {
+        None => false,
+        Some("taken_at") => true,
+        Some(_) => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Invalid sort; expected taken_at"
+            }));
+        }
+    };
+
     match sqlx::query_as!(
         PhotoModel,
-        "SELECT * FROM photos ORDER BY id LIMIT $1 OFFSET $2",
+        "SELECT * FROM photos WHERE ($3::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $3)) ORDER BY CASE WHEN $4 THEN taken_at END DESC NULLS LAST, id LIMIT $1 OFFSET $2",
         limit as i32,
-        offset as i32
+        offset as i32,
+        user.parent_id(),
+        by_taken_at
     )
     .fetch_all(&data.db)
     .await
     {
         Ok(photos) => {
+            let photos: Vec<_> = photos
+                .into_iter()
+                .map(|photo| data.url_signer.with_url(photo.id, photo))
+                .collect();
             let response = json!({
                 "status": "success",
                 "photos": photos
@@ -93,17 +264,19 @@ pub async fn get_all_photos(
 }
 
 // Função para obter uma foto por ID
-#[get("/photos/{id}")]
+#[get("/photos/{id}", wrap = "RequirePermission(Permission::MediaRead)")]
 async fn get_photo_by_id(
     path: Path<Uuid>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let photo_id = path.into_inner();
 
     match sqlx::query_as!(
         PhotoModel,
-        "SELECT * FROM photos WHERE id = $1",
-        photo_id
+        "SELECT * FROM photos WHERE id = $1 AND ($2::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        photo_id,
+        user.parent_id()
     )
     .fetch_one(&data.db)
     .await
@@ -111,10 +284,17 @@ async fn get_photo_by_id(
         Ok(photo) => {
             let response = json!({
                 "status": "success",
-                "photo": photo
+                "photo": data.url_signer.with_url(photo.id, photo)
             });
             HttpResponse::Ok().json(response)
         }
+        Err(sqlx::Error::RowNotFound) => {
+            let response = json!({
+                "status": "error",
+                "message": "Photo not found"
+            });
+            HttpResponse::NotFound().json(response)
+        }
         Err(error) => {
             let response = json!({
                 "status": "error",
@@ -125,8 +305,97 @@ async fn get_photo_by_id(
     }
 }
 
+// Função para obter uma versão reduzida de uma foto (thumbnail, medium ou large)
+#[get("/photos/{id}/variants/{size}", wrap = "RequirePermission(Permission::MediaRead)")]
+async fn get_photo_variant(
+    req: HttpRequest,
+    path: Path<(Uuid, String)>,
+    query: Query<VariantQuery>,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    let (photo_id, size) = path.into_inner();
+
+    let Some(size) = VariantSize::parse(&size) else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Invalid size; expected thumbnail, medium or large"
+        }));
+    };
+    let requested = match query.format.as_deref().map(VariantFormat::parse) {
+        Some(Some(format)) => Some(format),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Invalid format; expected webp or jpeg"
+            }));
+        }
+        None => None,
+    };
+
+    let photo = match sqlx::query_as!(
+        PhotoModel,
+        "SELECT * FROM photos WHERE id = $1 AND ($2::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        photo_id,
+        user.parent_id()
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(photo)) => photo,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "error",
+                "message": "Photo not found"
+            }));
+        }
+        Err(error) => return StorageError::Database(error).error_response(),
+    };
+
+    let variants = match ensure_variants(&data, &photo).await {
+        Ok(variants) => variants,
+        Err(error) => return error.error_response(),
+    };
+    // Sem formato na query: o WebP (sem perdas) só vai para quem o aceita, e se for menor
+    let variant = variants
+        .into_iter()
+        .filter(|variant| variant.size == size.name())
+        .filter(|variant| match requested {
+            Some(format) => variant.format == format.name(),
+            None => variant.format == VariantFormat::Jpeg.name() || accepts_webp(&req),
+        })
+        .min_by_key(|variant| variant.bytes);
+    let Some(variant) = variant else {
+        return HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "This photo has no variants"
+        }));
+    };
+
+    let etag = format!("\"{}-{}-{}\"", photo.id.simple(), variant.size, variant.format);
+    let mut response = if etag_matches(&req, &etag) {
+        HttpResponse::NotModified().finish()
+    } else {
+        match data.storage.get_range(&variant.storage_key, None).await {
+            Ok(object) => HttpResponse::Ok()
+                .content_type(mime_guess::from_path(&variant.storage_key).first_or_octet_stream().essence_str())
+                .no_chunking(object.length)
+                .streaming(object.stream),
+            Err(error) => return error.error_response(),
+        }
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(VARIANT_CACHE_CONTROL));
+    headers.insert(VARY, HeaderValue::from_static("Accept"));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, value);
+    }
+    response
+}
+
 // Função para atualizar uma foto
-#[patch("/photos/{id}")]
+#[patch("/photos/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn update_photo_by_id(
     path: Path<Uuid>,
     body: Json<UpdatePhotoSchema>,
@@ -142,7 +411,10 @@ async fn update_photo_by_id(
     .fetch_one(&data.db)
     .await
     {
-        Ok(_) => {
+        Ok(photo) => {
+            if let Some(response) = files::refuse_legacy_rename(body.filename.as_deref(), photo.storage_key.as_deref()) {
+                return response;
+            }
             let update_result = sqlx::query_as!(
                 PhotoModel,
                 "UPDATE photos SET student_id = COALESCE($1, student_id), filename = COALESCE($2, filename), description = COALESCE($3, description) WHERE id = $4 RETURNING *",
@@ -182,17 +454,15 @@ async fn update_photo_by_id(
 }
 
 // Função para deletar uma foto por ID
-#[delete("/photos/{id}")]
+#[delete("/photos/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn delete_photo_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
 ) -> impl Responder {
     let photo_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM photos WHERE id = $1", photo_id)
-        .execute(&data.db)
-        .await
-    {
+    // Os arquivos saem junto com o registro; os que falharem ficam na fila de remoção
+    match storage::delete_with_files(&data.db, data.storage.as_ref(), MediaOwner::Photo(photo_id)).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(err) => {
             let response = json!({
@@ -204,64 +474,14 @@ async fn delete_photo_by_id(
     }
 }
 
-// Função para fazer upload de imagens
-#[post("/upload")]
-async fn upload_image(mut payload: Multipart) -> impl Responder {
-    while let Some(field) = payload.next().await {
-        match field {
-            Ok(mut field) => {
-                let filename = field
-                    .content_disposition()
-                    .get_filename()
-                    .map_or("temp".to_string(), |f| f.to_string());
-
-                let filepath = format!("./static/{}", filename);
-                println!("Saving file to: {}", filepath); // Adiciona log para depuração
-
-                // Cria o arquivo e verifica se a criação foi bem-sucedida
-                let file_result = match tokio::fs::File::create(&filepath).await {
-                    Ok(f) => f,
-                    Err(e) => return HttpResponse::InternalServerError().json(json!({
-                        "status": "error",
-                        "message": format!("Failed to create file: {:?}", e)
-                    })),
-                };
-
-                let mut file = tokio::io::BufWriter::new(file_result);
-
-                // Lê e escreve o conteúdo do arquivo
-                while let Some(chunk) = field.next().await {
-                    let data = match chunk {
-                        Ok(d) => d,
-                        Err(e) => return HttpResponse::InternalServerError().json(json!({
-                            "status": "error",
-                            "message": format!("Failed to read chunk: {:?}", e)
-                        })),
-                    };
-
-                    if let Err(e) = file.write_all(&data).await {
-                        return HttpResponse::InternalServerError().json(json!({
-                            "status": "error",
-                            "message": format!("Failed to write data: {:?}", e)
-                        }));
-                    }
-                }
-            }
-            Err(e) => return HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to process file: {:?}", e)
-            })),
-        }
-    }
-
-    HttpResponse::Ok().finish()
-}
-
-
 // Função para obter todos os alunos
-#[get("/students")]
-async fn get_students(data: Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(StudentModel, "SELECT * FROM students")
+#[get("/students", wrap = "RequirePermission(Permission::StudentsRead)")]
+async fn get_students(user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
+    match sqlx::query_as!(
+        StudentModel,
+        "SELECT * FROM students WHERE ($1::uuid IS NULL OR id IN (SELECT student_id FROM student_parents WHERE parent_id = $1))",
+        user.parent_id()
+    )
         .fetch_all(&data.db)
         .await
     {
@@ -287,9 +507,8 @@ pub fn config_photos(conf: &mut ServiceConfig) {
     conf.service(create_photo)
        .service(get_all_photos)
        .service(get_photo_by_id)
+       .service(get_photo_variant)
        .service(update_photo_by_id)
        .service(delete_photo_by_id)
-       .service(upload_image)
-       .service(Files::new("/static", "./static").show_files_listing())
        .service(get_students);
 }
@@ -0,0 +1,48 @@
+use actix_web::{
+    get, post, web::{Data, Query, ServiceConfig}, HttpResponse, Responder, ResponseError
+};
+use chrono::Duration;
+use serde_json::json;
+
+use crate::{
+    auth::permissions::{Permission, RequirePermission},
+    reconcile::{self, Options, DEFAULT_MIN_AGE_HOURS},
+    schema::ReconcileOptions,
+    storage::{self, StorageError},
+    AppState
+};
+
+// Compara o storage com o banco; sem dry_run=false só relata o que seria apagado
+#[post("/storage/reconcile", wrap = "RequirePermission(Permission::StorageManage)")]
+async fn reconcile_storage(query: Query<ReconcileOptions>, data: Data<AppState>) -> impl Responder {
+    let min_age_hours = query.min_age_hours.unwrap_or(DEFAULT_MIN_AGE_HOURS);
+    let Some(min_age) = Duration::try_hours(min_age_hours).filter(|_| min_age_hours >= 0) else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "min_age_hours must be a non-negative number of hours"
+        }));
+    };
+
+    let options = Options {
+        dry_run: query.dry_run.unwrap_or(true),
+        min_age,
+    };
+    match reconcile::run(&data, options).await {
+        Ok(report) => HttpResponse::Ok().json(json!({"status": "success", "report": report})),
+        Err(error) => error.error_response(),
+    }
+}
+
+// Espaço ocupado por tipo de mídia e por aluno, com as cotas configuradas
+#[get("/storage/usage", wrap = "RequirePermission(Permission::StorageManage)")]
+async fn get_storage_usage(data: Data<AppState>) -> impl Responder {
+    match storage::usage_report(&data.db, &data.config.storage).await {
+        Ok(usage) => HttpResponse::Ok().json(json!({"status": "success", "usage": usage})),
+        Err(error) => StorageError::Database(error).error_response(),
+    }
+}
+
+pub fn config_storage(conf: &mut ServiceConfig) {
+    conf.service(reconcile_storage)
+        .service(get_storage_usage);
+}
@@ -1,16 +1,20 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, scope, Query, Path, ServiceConfig}, HttpResponse, Responder
+    get, post, delete, patch, web::{Data, Json, Query, Path, ServiceConfig}, HttpResponse, Responder
 };
 use serde_json::json;
 use crate::{
+    auth::{
+        permissions::{Permission, RequirePermission},
+        AuthenticatedUser,
+    },
     model::StudentModel,
     schema::{CreateStudentSchema, UpdateStudentSchema, FilterOptions},
+    storage::{self, MediaOwner},
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-#[post("/students")]
+#[post("/students", wrap = "RequirePermission(Permission::StudentsWrite)")]
 async fn create_student(
     body: Json<CreateStudentSchema>,
     data: Data<AppState>
@@ -23,7 +27,7 @@ async fn create_student(
 
     match sqlx::query_as::<_, StudentModel>(query)
         .bind(&body.name)
-        .bind(&body.age)
+        .bind(body.age)
         .fetch_one(&data.db)
         .await
     {
@@ -49,9 +53,10 @@ async fn create_student(
     }
 }
 
-#[get("/students")]
+#[get("/students", wrap = "RequirePermission(Permission::StudentsRead)")]
 pub async fn get_all_students(
     opts: Query<FilterOptions>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
@@ -59,9 +64,10 @@ pub async fn get_all_students(
 
     match sqlx::query_as!(
         StudentModel,
-        "SELECT * FROM students ORDER BY id LIMIT $1 OFFSET $2",
+        "SELECT * FROM students WHERE ($3::uuid IS NULL OR id IN (SELECT student_id FROM student_parents WHERE parent_id = $3)) ORDER BY id LIMIT $1 OFFSET $2",
         limit as i32,
-        offset as i32
+        offset as i32,
+        user.parent_id()
     )
     .fetch_all(&data.db)
     .await
@@ -83,17 +89,19 @@ pub async fn get_all_students(
     }
 }
 
-#[get("/students/{id}")]
+#[get("/students/{id}", wrap = "RequirePermission(Permission::StudentsRead)")]
 async fn get_student_by_id(
     path: Path<Uuid>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let student_id = path.into_inner();
 
     match sqlx::query_as!(
         StudentModel,
-        "SELECT * FROM students WHERE id = $1",
-        student_id
+        "SELECT * FROM students WHERE id = $1 AND ($2::uuid IS NULL OR id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        student_id,
+        user.parent_id()
     )
     .fetch_one(&data.db)
     .await
@@ -105,6 +113,13 @@ async fn get_student_by_id(
             });
             HttpResponse::Ok().json(response)
         }
+        Err(sqlx::Error::RowNotFound) => {
+            let response = json!({
+                "status": "error",
+                "message": "Student not found"
+            });
+            HttpResponse::NotFound().json(response)
+        }
         Err(error) => {
             let response = json!({
                 "status": "error",
@@ -115,7 +130,7 @@ async fn get_student_by_id(
     }
 }
 
-#[patch("/students/{id}")]
+#[patch("/students/{id}", wrap = "RequirePermission(Permission::StudentsWrite)")]
 async fn update_student_by_id(
     path: Path<Uuid>,
     body: Json<UpdateStudentSchema>,
@@ -151,17 +166,15 @@ async fn update_student_by_id(
 }
 
 
-#[delete("/students/{id}")]
+#[delete("/students/{id}", wrap = "RequirePermission(Permission::StudentsDelete)")]
 async fn delete_student_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
 ) -> impl Responder {
     let student_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM students WHERE id = $1", student_id)
-        .execute(&data.db)
-        .await
-    {
+    // Os arquivos saem junto com o registro; os que falharem ficam na fila de remoção
+    match storage::delete_with_files(&data.db, data.storage.as_ref(), MediaOwner::Student(student_id)).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(err) => {
             let response = json!({
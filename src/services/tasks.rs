@@ -1,16 +1,16 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, scope, Query, Path, ServiceConfig}, HttpResponse, Responder
+    get, post, delete, patch, web::{Data, Json, Query, Path, ServiceConfig}, HttpResponse, Responder
 };
 use serde_json::json;
 use crate::{
+    auth::permissions::{Permission, RequirePermission},
     model::TaskModel,
     schema::{CreateTaskSchema, UpdateTaskSchema, FilterOptions},
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-#[post("/tasks")]
+#[post("/tasks", wrap = "RequirePermission(Permission::TasksManage)")]
 async fn create_task(
     body: Json<CreateTaskSchema>,
     data: Data<AppState>
@@ -49,7 +49,7 @@ async fn create_task(
     }
 }
 
-#[get("/tasks")]
+#[get("/tasks", wrap = "RequirePermission(Permission::TasksManage)")]
 pub async fn get_all_tasks(
     opts: Query<FilterOptions>,
     data: Data<AppState>
@@ -83,7 +83,7 @@ pub async fn get_all_tasks(
     }
 }
 
-#[get("/tasks/{id}")]
+#[get("/tasks/{id}", wrap = "RequirePermission(Permission::TasksManage)")]
 async fn get_task_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -115,7 +115,7 @@ async fn get_task_by_id(
     }
 }
 
-#[patch("/tasks/{id}")]
+#[patch("/tasks/{id}", wrap = "RequirePermission(Permission::TasksManage)")]
 async fn update_task_by_id(
     path: Path<Uuid>,
     body: Json<UpdateTaskSchema>,
@@ -127,7 +127,7 @@ async fn update_task_by_id(
         .fetch_one(&data.db)
         .await
     {
-        Ok(task) => {
+        Ok(_) => {
             let update_result = sqlx::query_as!(
                 TaskModel,
                 "UPDATE tasks SET title = COALESCE($1, title), content = COALESCE($2, content) WHERE id = $3 RETURNING *",
@@ -165,7 +165,7 @@ async fn update_task_by_id(
     }
 }
 
-#[delete("/tasks/{id}")]
+#[delete("/tasks/{id}", wrap = "RequirePermission(Permission::TasksManage)")]
 async fn delete_task_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -1,28 +1,69 @@
 use actix_web::{
-    get, post, delete, patch, web::{Data, Json, scope, Query, Path, ServiceConfig}, HttpResponse, Responder
+    get, post, delete, patch, web::{Data, Json, Query, Path, ServiceConfig}, HttpResponse, Responder
 };
+use chrono::Utc;
 use serde_json::json;
 use crate::{
-    model::UserModel,
-    schema::{CreateUserSchema, UpdateUserSchema, FilterOptions},
+    auth::{
+        password::{hash_password, validate_password},
+        permissions::{Permission, RequirePermission, Role},
+        tokens, totp,
+        AuthenticatedUser,
+    },
+    mailer::Email,
+    model::{UserInvitationModel, UserModel},
+    schema::{CreateInvitationSchema, CreateUserSchema, UpdateUserSchema, FilterOptions},
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-#[post("/users")]
+// Contas de pais não ficam na tabela users
+fn is_staff_role(role: &str) -> bool {
+    matches!(role.parse::<Role>(), Ok(role) if role.is_staff())
+}
+
+fn invalid_role() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "status": "error",
+        "message": "Invalid role: expected 'admin' or 'coach'"
+    }))
+}
+
+#[post("/users", wrap = "RequirePermission(Permission::UsersManage)")]
 async fn create_user(
     body: Json<CreateUserSchema>,
     data: Data<AppState>) -> impl Responder {
+        if !is_staff_role(&body.role) {
+            return invalid_role();
+        }
+
+        if let Err(message) = validate_password(&body.password) {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": message
+            }));
+        }
+
+        let hashed_password = match hash_password(&body.password) {
+            Ok(hash) => hash,
+            Err(error) => {
+                return HttpResponse::InternalServerError().json(json!({
+                    "status": "error",
+                    "message": format!("Failed to hash password: {:?}", error)
+                }));
+            }
+        };
+
         let query = r#"
-            INSERT INTO users (username, hashed_password, role)
-            VALUES ($1, $2, $3)
-            RETURNING id, username, hashed_password, role, created_at
+            INSERT INTO users (username, email, hashed_password, role)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
         "#;
     
         match sqlx::query_as::<_, UserModel>(query)
             .bind(&body.username)
-            .bind(&body.hashed_password)
+            .bind(&body.email)
+            .bind(&hashed_password)
             .bind(&body.role)
             .fetch_one(&data.db)
             .await
@@ -33,7 +74,7 @@ async fn create_user(
                     "user": {
                         "id": user.id,
                         "username": user.username,
-                        "hashed_password": user.hashed_password,
+                        "email": user.email,
                         "role": user.role,
                         "created_at": user.created_at
                     }
@@ -51,7 +92,7 @@ async fn create_user(
     }
     
 
-#[get("/users")]
+#[get("/users", wrap = "RequirePermission(Permission::UsersManage)")]
 pub async fn get_all_users(
     opts: Query<FilterOptions>,
     data: Data<AppState>
@@ -85,7 +126,7 @@ pub async fn get_all_users(
     }
 }
 
-#[get("/users/{id}")]
+#[get("/users/{id}", wrap = "RequirePermission(Permission::UsersManage)")]
 async fn get_user_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -117,7 +158,7 @@ async fn get_user_by_id(
     }
 }
 
-#[patch("/users/{id}")]
+#[patch("/users/{id}", wrap = "RequirePermission(Permission::UsersManage)")]
 async fn update_user_by_id(
     path: Path<Uuid>,
     body: Json<UpdateUserSchema>,
@@ -125,6 +166,32 @@ async fn update_user_by_id(
 ) -> impl Responder {
     let user_id = path.into_inner();
 
+    if body.role.as_deref().is_some_and(|role| !is_staff_role(role)) {
+        return invalid_role();
+    }
+
+    // A senha nova chega em texto puro e só o hash é gravado
+    let hashed_password = match body.password.as_deref() {
+        Some(password) => {
+            if let Err(message) = validate_password(password) {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "error",
+                    "message": message
+                }));
+            }
+            match hash_password(password) {
+                Ok(hash) => Some(hash),
+                Err(error) => {
+                    return HttpResponse::InternalServerError().json(json!({
+                        "status": "error",
+                        "message": format!("Failed to hash password: {:?}", error)
+                    }));
+                }
+            }
+        }
+        None => None,
+    };
+
     // Recuperar a tarefa existente
     match sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", user_id)
         .fetch_one(&data.db)
@@ -134,10 +201,11 @@ async fn update_user_by_id(
             // Atualizar a tarefa
             let update_result = sqlx::query_as!(
                 UserModel,
-                "UPDATE users SET username = COALESCE($1, username), hashed_password = COALESCE($2, hashed_password), role = COALESCE($3, role) WHERE id = $4 RETURNING *",
+                "UPDATE users SET username = COALESCE($1, username), hashed_password = COALESCE($2, hashed_password), role = COALESCE($3, role), email = COALESCE($4, email) WHERE id = $5 RETURNING *",
                 body.username.as_ref(),
-                body.hashed_password.as_ref(),
+                hashed_password.as_ref(),
                 body.role.as_ref(),
+                body.email.as_ref(),
                 user_id
             )
             .fetch_one(&data.db)
@@ -145,6 +213,17 @@ async fn update_user_by_id(
 
             match update_result {
                 Ok(updated_user) => {
+                    // Nova senha ou novo papel: as sessões abertas caem e o próximo login
+                    // aplica as regras do papel novo (admins cadastram o TOTP)
+                    let role_changed = body.role.as_deref().is_some_and(|role| role != user.role);
+                    if hashed_password.is_some() || role_changed {
+                        if let Err(error) = tokens::revoke_all_sessions(&data.db, user_id).await {
+                            return HttpResponse::InternalServerError().json(json!({
+                                "status": "error",
+                                "message": format!("Failed to revoke sessions: {:?}", error)
+                            }));
+                        }
+                    }
                     let response = json!({
                         "status": "success",
                         "user": updated_user
@@ -170,7 +249,7 @@ async fn update_user_by_id(
     }
 }
 
-#[delete("/users/{id}")]
+#[delete("/users/{id}", wrap = "RequirePermission(Permission::UsersManage)")]
 async fn delete_user_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
@@ -192,11 +271,97 @@ async fn delete_user_by_id(
     }
 }
 
+// Remove o TOTP de quem perdeu o autenticador e os códigos de recuperação.
+// As sessões abertas caem; admins cadastram de novo no próximo login
+#[delete("/users/{id}/totp", wrap = "RequirePermission(Permission::UsersManage)")]
+async fn reset_user_totp(
+    path: Path<Uuid>,
+    data: Data<AppState>
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    if let Err(error) = totp::clear(&data.db, user_id).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to reset two-factor authentication: {:?}", error)
+        }));
+    }
+
+    match tokens::revoke_all_sessions(&data.db, user_id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to revoke sessions: {:?}", error)
+        })),
+    }
+}
+
+// Convida um novo membro da equipe por e-mail; a conta só é criada quando o convite é aceito
+#[post("/users/invitations", wrap = "RequirePermission(Permission::UsersManage)")]
+async fn create_invitation(
+    body: Json<CreateInvitationSchema>,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    if !is_staff_role(&body.role) {
+        return invalid_role();
+    }
+
+    let token = tokens::generate_token();
+    let query = r#"
+        INSERT INTO user_invitations (email, role, token_hash, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, email, role, invited_by, expires_at, accepted_at, created_at
+    "#;
+
+    let invitation = match sqlx::query_as::<_, UserInvitationModel>(query)
+        .bind(body.email.trim())
+        .bind(&body.role)
+        .bind(tokens::hash_token(&token))
+        .bind(user.id)
+        .bind(Utc::now() + data.config.invitation_ttl)
+        .fetch_one(&data.db)
+        .await
+    {
+        Ok(invitation) => invitation,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to create invitation: {:?}", error)
+            }));
+        }
+    };
+
+    let link = format!("{}/accept-invite?token={}", data.config.portal_url.trim_end_matches('/'), token);
+    let email = Email {
+        to: invitation.email.clone(),
+        subject: "You have been invited".to_string(),
+        body: format!(
+            "You have been invited to join as {}.\n\nCreate your account here:\n{}\n\nThis link expires on {}.",
+            invitation.role, link, invitation.expires_at.format("%Y-%m-%d %H:%M UTC")
+        ),
+    };
+
+    if let Err(error) = data.mailer.send(email).await {
+        return HttpResponse::BadGateway().json(json!({
+            "status": "error",
+            "message": format!("Invitation created but the email could not be sent: {}", error)
+        }));
+    }
+
+    HttpResponse::Created().json(json!({
+        "status": "success",
+        "invitation": invitation
+    }))
+}
+
 // Configuração das rotas para usuários
 pub fn config_users(conf: &mut ServiceConfig) {
     conf.service(create_user)
        .service(get_all_users)
        .service(get_user_by_id)
        .service(update_user_by_id)
-       .service(delete_user_by_id);
+       .service(delete_user_by_id)
+       .service(reset_user_totp)
+       .service(create_invitation);
 }
\ No newline at end of file
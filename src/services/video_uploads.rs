@@ -0,0 +1,459 @@
+// Uploads retomáveis de vídeo pelo protocolo tus 1.0 (https://tus.io/protocols/resumable-upload):
+// criação, PATCH por offset, HEAD com o estado, término e expiração. Os bytes ficam num
+// arquivo temporário até o upload terminar; aí o vídeo passa pelo mesmo caminho do
+// POST /videos (checagem de tipo e tamanho, INSERT e gravação no storage juntos).
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::{
+    delete, head, options, patch, post,
+    http::StatusCode,
+    web::{Data, Path, Payload, ServiceConfig},
+    HttpRequest, HttpResponse, HttpResponseBuilder, Responder, ResponseError,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde_json::json;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        permissions::{Permission, RequirePermission},
+        AuthenticatedUser,
+    },
+    config::StorageConfig,
+    model::ResumableUploadModel,
+    services::videos::insert_video,
+    storage::{self, StorageError},
+    AppState
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+// De quanto em quanto tempo os uploads vencidos são apagados
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Uploads com um PATCH em andamento neste processo
+static ACTIVE_UPLOADS: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+
+// Só um PATCH (ou DELETE) por upload de cada vez; liberado no drop
+struct ActiveUpload(Uuid);
+
+impl ActiveUpload {
+    fn acquire(id: Uuid) -> Option<ActiveUpload> {
+        let mut active = ACTIVE_UPLOADS.lock().unwrap();
+        if active.contains(&id) {
+            return None;
+        }
+        active.push(id);
+        Some(ActiveUpload(id))
+    }
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        ACTIVE_UPLOADS.lock().unwrap().retain(|id| *id != self.0);
+    }
+}
+
+fn upload_path(config: &StorageConfig, id: Uuid) -> PathBuf {
+    config.tmp_dir.join("resumable").join(id.simple().to_string())
+}
+
+// Toda resposta do protocolo leva Tus-Resumable e não pode ir para cache
+fn tus(status: StatusCode) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::build(status);
+    builder
+        .insert_header(("Tus-Resumable", TUS_VERSION))
+        .insert_header(("Cache-Control", "no-store"));
+    builder
+}
+
+fn tus_error(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    tus(status).json(json!({
+        "status": "error",
+        "message": message.into()
+    }))
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// Formato de data do HTTP, usado em Upload-Expires
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+// Todas as requisições, menos OPTIONS, declaram a versão do protocolo
+fn version_mismatch(req: &HttpRequest) -> Option<HttpResponse> {
+    if header(req, "Tus-Resumable") == Some(TUS_VERSION) {
+        return None;
+    }
+    Some(tus(StatusCode::PRECONDITION_FAILED)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .json(json!({
+            "status": "error",
+            "message": format!("Tus-Resumable must be {}", TUS_VERSION)
+        })))
+}
+
+/// Parses `Upload-Metadata`: comma-separated `key base64value` pairs, where the value may
+/// be omitted. `None` when a value is not valid base64 or UTF-8.
+pub fn parse_metadata(value: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        metadata.insert(key.to_string(), decoded);
+    }
+    Some(metadata)
+}
+
+async fn find_upload(data: &AppState, id: Uuid, user: &AuthenticatedUser) -> Result<ResumableUploadModel, HttpResponse> {
+    match sqlx::query_as!(
+        ResumableUploadModel,
+        "SELECT * FROM resumable_uploads WHERE id = $1 AND owner_id = $2 AND expires_at > NOW()",
+        id,
+        user.id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(upload)) => Ok(upload),
+        Ok(None) => Err(tus_error(StatusCode::NOT_FOUND, "Upload not found")),
+        Err(error) => Err(tus_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get upload: {:?}", error),
+        )),
+    }
+}
+
+// Apaga o registro e o que já tinha chegado do arquivo
+async fn discard_upload(data: &AppState, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM resumable_uploads WHERE id = $1", id)
+        .execute(&data.db)
+        .await?;
+    let _ = tokio::fs::remove_file(upload_path(&data.config.storage, id)).await;
+    Ok(())
+}
+
+// Upload completo: cria o vídeo com o mesmo pipeline do POST /videos
+async fn complete_upload(data: &AppState, upload: &ResumableUploadModel) -> Result<Uuid, HttpResponse> {
+    let path = upload_path(&data.config.storage, upload.id);
+    let result = async {
+        let file = storage::stage_file(&data.config.storage, storage::VIDEOS, path, &upload.filename).await?;
+
+        let mut tx = data.db.begin().await.map_err(StorageError::Database)?;
+        let (video, created) = insert_video(&mut tx, upload.student_id, upload.description.as_deref(), &file)
+            .await
+            .map_err(StorageError::Database)?;
+        sqlx::query!("UPDATE resumable_uploads SET video_id = $1 WHERE id = $2", video.id, upload.id)
+            .execute(&mut tx)
+            .await
+            .map_err(StorageError::Database)?;
+
+        // Mesmo conteúdo já enviado para o aluno: aponta para o vídeo existente
+        if created {
+            // Outros envios podem ter ocupado a cota desde a criação
+            storage::commit_within_quota(tx, &file, data.storage.as_ref(), &data.config.storage, upload.student_id)
+                .await?;
+        } else {
+            tx.commit().await.map_err(StorageError::Database)?;
+        }
+        Ok::<_, StorageError>(video.id)
+    }
+    .await;
+
+    match result {
+        Ok(video_id) => Ok(video_id),
+        Err(error) => {
+            // O arquivo temporário já foi descartado: não há como continuar este upload
+            let _ = sqlx::query!("DELETE FROM resumable_uploads WHERE id = $1", upload.id)
+                .execute(&data.db)
+                .await;
+            Err(tus_error(error.status_code(), format!("Failed to create video: {}", error)))
+        }
+    }
+}
+
+/// Protocol discovery.
+#[options("/videos/uploads")]
+async fn upload_options(data: Data<AppState>) -> impl Responder {
+    tus(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .insert_header(("Tus-Max-Size", data.config.storage.max_video_bytes.to_string()))
+        .finish()
+}
+
+/// Creates an upload. `Upload-Metadata` must carry `student_id`; `filename` and
+/// `description` are optional.
+#[post("/videos/uploads", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn create_upload(req: HttpRequest, user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
+    if let Some(response) = version_mismatch(&req) {
+        return response;
+    }
+    if header(&req, "Upload-Defer-Length").is_some() {
+        return tus_error(StatusCode::BAD_REQUEST, "Upload-Defer-Length is not supported");
+    }
+    let Some(length) = header(&req, "Upload-Length").and_then(|value| value.parse::<u64>().ok()).filter(|length| *length > 0) else {
+        return tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Length");
+    };
+    let max_length = data.config.storage.max_video_bytes;
+    if length > max_length {
+        return tus_error(StatusCode::PAYLOAD_TOO_LARGE, StorageError::TooLarge(max_length).to_string());
+    }
+    let Some(metadata) = parse_metadata(header(&req, "Upload-Metadata").unwrap_or_default()) else {
+        return tus_error(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata");
+    };
+    let Some(student_id) = metadata.get("student_id").and_then(|id| Uuid::parse_str(id.trim()).ok()) else {
+        return tus_error(StatusCode::BAD_REQUEST, "Invalid UUID format for student_id");
+    };
+    // Cotas conferidas pelo tamanho declarado, antes de qualquer byte chegar
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, Some(student_id), length).await {
+        return tus_error(error.status_code(), error.to_string());
+    }
+
+    let id = Uuid::new_v4();
+    let path = upload_path(&data.config.storage, id);
+    let created = async {
+        tokio::fs::create_dir_all(path.parent().unwrap_or(&data.config.storage.tmp_dir)).await?;
+        tokio::fs::File::create(&path).await
+    }
+    .await;
+    if let Err(error) = created {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create upload: {}", error));
+    }
+
+    let expires_at = Utc::now() + data.config.storage.resumable_upload_ttl;
+    let inserted = sqlx::query!(
+        "INSERT INTO resumable_uploads (id, owner_id, student_id, filename, description, upload_length, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        id,
+        user.id,
+        student_id,
+        storage::original_filename(metadata.get("filename").map(String::as_str).unwrap_or_default()),
+        metadata.get("description"),
+        length as i64,
+        expires_at
+    )
+    .execute(&data.db)
+    .await;
+
+    match inserted {
+        Ok(_) => tus(StatusCode::CREATED)
+            .insert_header(("Location", format!("{}/{}", req.path().trim_end_matches('/'), id)))
+            .insert_header(("Upload-Expires", http_date(expires_at)))
+            .finish(),
+        Err(error) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create upload: {:?}", error))
+        }
+    }
+}
+
+/// Current offset, so a client can resume after losing the connection.
+#[head("/videos/uploads/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn upload_status(req: HttpRequest, path: Path<Uuid>, user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
+    if let Some(response) = version_mismatch(&req) {
+        return response;
+    }
+    let upload = match find_upload(&data, path.into_inner(), &user).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let mut response = tus(StatusCode::OK);
+    response
+        .insert_header(("Upload-Offset", upload.upload_offset.to_string()))
+        .insert_header(("Upload-Length", upload.upload_length.to_string()))
+        .insert_header(("Upload-Expires", http_date(upload.expires_at)));
+    if let Some(video_id) = upload.video_id {
+        response.insert_header(("X-Video-Id", video_id.to_string()));
+    }
+    response.finish()
+}
+
+/// Appends the body at `Upload-Offset`. Bytes received before a dropped connection are
+/// kept. The request that completes the upload creates the video, whose id comes back in
+/// `X-Video-Id`.
+#[patch("/videos/uploads/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn append_upload(
+    req: HttpRequest,
+    path: Path<Uuid>,
+    mut payload: Payload,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    if let Some(response) = version_mismatch(&req) {
+        return response;
+    }
+    if header(&req, "Content-Type") != Some(OFFSET_CONTENT_TYPE) {
+        return tus_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Content-Type must be {}", OFFSET_CONTENT_TYPE),
+        );
+    }
+    let Some(offset) = header(&req, "Upload-Offset").and_then(|value| value.parse::<i64>().ok()) else {
+        return tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Offset");
+    };
+
+    let id = path.into_inner();
+    let Some(_active) = ActiveUpload::acquire(id) else {
+        return tus_error(StatusCode::CONFLICT, "Another request is writing to this upload");
+    };
+    let upload = match find_upload(&data, id, &user).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    if offset != upload.upload_offset {
+        return tus_error(StatusCode::CONFLICT, "Upload-Offset does not match the current offset");
+    }
+    if let Some(video_id) = upload.video_id {
+        return tus(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", upload.upload_offset.to_string()))
+            .insert_header(("X-Video-Id", video_id.to_string()))
+            .finish();
+    }
+
+    let opened = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(upload_path(&data.config.storage, id))
+        .await;
+    let mut file = match opened {
+        Ok(file) => file,
+        Err(error) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to open upload: {}", error)),
+    };
+    // Um PATCH interrompido pode ter gravado além do offset registrado
+    let positioned = async {
+        file.set_len(offset as u64).await?;
+        file.seek(SeekFrom::Start(offset as u64)).await
+    }
+    .await;
+    if let Err(error) = positioned {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to open upload: {}", error));
+    }
+
+    let length = upload.upload_length as u64;
+    let mut received = offset as u64;
+    let mut failure = None;
+    while let Some(chunk) = payload.next().await {
+        // Conexão caída: guarda o que chegou até aqui
+        let Ok(chunk) = chunk else {
+            break;
+        };
+        if received + chunk.len() as u64 > length {
+            failure = Some(tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Body goes past Upload-Length"));
+            break;
+        }
+        if let Err(error) = file.write_all(&chunk).await {
+            failure = Some(tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write upload: {}", error)));
+            break;
+        }
+        received += chunk.len() as u64;
+    }
+    if let Err(error) = file.flush().await {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write upload: {}", error));
+    }
+    drop(file);
+
+    let expires_at = Utc::now() + data.config.storage.resumable_upload_ttl;
+    let updated = sqlx::query!(
+        "UPDATE resumable_uploads SET upload_offset = $1, expires_at = $2 WHERE id = $3 AND upload_offset = $4",
+        received as i64,
+        expires_at,
+        id,
+        offset
+    )
+    .execute(&data.db)
+    .await;
+    match updated {
+        Ok(result) if result.rows_affected() == 1 => {}
+        Ok(_) => return tus_error(StatusCode::CONFLICT, "Upload-Offset does not match the current offset"),
+        Err(error) => {
+            return tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to update upload: {:?}", error))
+        }
+    }
+    if let Some(response) = failure {
+        return response;
+    }
+
+    let mut response = tus(StatusCode::NO_CONTENT);
+    response
+        .insert_header(("Upload-Offset", received.to_string()))
+        .insert_header(("Upload-Expires", http_date(expires_at)));
+    if received == length {
+        match complete_upload(&data, &upload).await {
+            Ok(video_id) => {
+                response.insert_header(("X-Video-Id", video_id.to_string()));
+            }
+            Err(response) => return response,
+        }
+    }
+    response.finish()
+}
+
+/// Termination: drops the upload and the bytes received so far.
+#[delete("/videos/uploads/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn terminate_upload(req: HttpRequest, path: Path<Uuid>, user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
+    if let Some(response) = version_mismatch(&req) {
+        return response;
+    }
+    let id = path.into_inner();
+    let Some(_active) = ActiveUpload::acquire(id) else {
+        return tus_error(StatusCode::CONFLICT, "Another request is writing to this upload");
+    };
+    if let Err(response) = find_upload(&data, id, &user).await {
+        return response;
+    }
+
+    match discard_upload(&data, id).await {
+        Ok(()) => tus(StatusCode::NO_CONTENT).finish(),
+        Err(error) => tus_error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to delete upload: {:?}", error)),
+    }
+}
+
+async fn expire_uploads(data: &AppState) -> Result<usize, sqlx::Error> {
+    let active = ACTIVE_UPLOADS.lock().unwrap().clone();
+    let expired = sqlx::query_scalar!(
+        "DELETE FROM resumable_uploads WHERE expires_at <= NOW() AND NOT (id = ANY($1)) RETURNING id",
+        &active
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    for id in &expired {
+        let _ = tokio::fs::remove_file(upload_path(&data.config.storage, *id)).await;
+    }
+    Ok(expired.len())
+}
+
+/// Periodically deletes uploads past `Upload-Expires`, together with their partial files.
+pub fn spawn_expiry(data: Data<AppState>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            match expire_uploads(&data).await {
+                Ok(0) => {}
+                Ok(count) => println!("Discarded {} expired resumable uploads", count),
+                Err(error) => eprintln!("Failed to expire resumable uploads: {:?}", error),
+            }
+        }
+    });
+}
+
+// Configuração das rotas
+pub fn config_video_uploads(conf: &mut ServiceConfig) {
+    conf.service(upload_options)
+        .service(create_upload)
+        .service(upload_status)
+        .service(append_upload)
+        .service(terminate_upload);
+}
@@ -1,68 +1,149 @@
 use actix_web::{
     get, post, delete, patch, web::{Data, Json, Path, Query, ServiceConfig},
-    HttpResponse, Responder
+    HttpRequest, HttpResponse, Responder, ResponseError
 };
-use actix_files::Files;
 use actix_multipart::Multipart;
-use futures_util::StreamExt;
-use tokio::io::AsyncWriteExt;
 use serde_json::json;
-use sqlx::PgPool;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
-use std::path::PathBuf;
 
 use crate::{
-    model::{VideoModel, PhotoModel, StudentModel},
-    schema::{CreateVideoSchema, UpdateVideoSchema, FilterOptions, CreatePhotoSchema, UpdatePhotoSchema},
+    auth::{
+        permissions::{Permission, RequirePermission},
+        AuthenticatedUser,
+    },
+    model::{VideoModel, StudentModel},
+    services::files,
+    schema::{UpdateVideoSchema, VideoFilterOptions},
+    storage::{self, MediaOwner, StagedFile, StorageError},
     AppState
 };
 
-// Função para criar um novo vídeo
-#[post("/videos")]
-async fn create_video(
-    body: Json<CreateVideoSchema>,
-    data: Data<AppState>
-) -> impl Responder {
+/// Inserts the row for a staged video file. When the student already has a video with
+/// the same content, that one is returned instead, with `false`, and the staged file is
+/// not needed. Otherwise the caller stores the file and commits with
+/// [`storage::commit_within_quota`], so the row and the file appear together.
+pub async fn insert_video(
+    tx: &mut Transaction<'_, Postgres>,
+    student_id: Uuid,
+    description: Option<&str>,
+    file: &StagedFile,
+) -> Result<(VideoModel, bool), sqlx::Error> {
     let query = r#"
-        INSERT INTO videos (student_id, filename, description)
-        VALUES ($1, $2, $3)
-        RETURNING id, student_id, filename, description, created_at
+        INSERT INTO videos (
+            student_id, filename, storage_key, sha256, bytes, description, duration_seconds, width, height,
+            frame_rate, video_codec, audio_codec, bitrate, recorded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (student_id, sha256) DO NOTHING
+        RETURNING id, student_id, filename, storage_key, sha256, bytes, description, duration_seconds, width, height,
+            frame_rate, video_codec, audio_codec, bitrate, recorded_at, created_at
     "#;
 
-    match sqlx::query_as::<_, VideoModel>(query)
-        .bind(&body.student_id)
-        .bind(&body.filename)
-        .bind(&body.description)
-        .fetch_one(&data.db)
+    // WebM não passa pelo leitor de contêiner: as colunas ficam vazias
+    let info = file.video.clone().unwrap_or_default();
+    let inserted = sqlx::query_as::<_, VideoModel>(query)
+        .bind(student_id)
+        .bind(&file.filename)
+        .bind(&file.storage_key)
+        .bind(&file.sha256)
+        .bind(file.size as i64)
+        .bind(description.unwrap_or_default())
+        .bind(info.duration_seconds)
+        .bind(info.width.map(|width| width as i32))
+        .bind(info.height.map(|height| height as i32))
+        .bind(info.frame_rate)
+        .bind(info.video_codec)
+        .bind(info.audio_codec)
+        .bind(info.bitrate.map(|bitrate| bitrate as i64))
+        .bind(info.recorded_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    match inserted {
+        Some(video) => Ok((video, true)),
+        None => sqlx::query_as!(
+            VideoModel,
+            "SELECT * FROM videos WHERE student_id = $1 AND sha256 = $2",
+            student_id,
+            file.sha256
+        )
+        .fetch_one(&mut *tx)
         .await
-    {
-        Ok(video) => {
-            let response = json!( {
-                "status": "success",
-                "video": {
-                    "id": video.id,
-                    "student_id": video.student_id,
-                    "filename": video.filename,
-                    "description": video.description,
-                    "created_at": video.created_at
-                }
-            });
-            HttpResponse::Ok().json(response)
-        }
+        .map(|video| (video, false)),
+    }
+}
+
+// Função para criar um novo vídeo. Recebe student_id, description e o arquivo no
+// mesmo formulário multipart: o registro só é gravado junto com o arquivo.
+#[post("/videos", wrap = "RequirePermission(Permission::MediaWrite)")]
+async fn create_video(
+    req: HttpRequest,
+    payload: Multipart,
+    data: Data<AppState>
+) -> impl Responder {
+    // Sem espaço na instalação: recusa antes de receber o corpo
+    if let Err(error) = storage::check_quota(&data.db, &data.config.storage, None, storage::content_length(&req).unwrap_or(0)).await {
+        return error.error_response();
+    }
+    let content_length = storage::content_length(&req);
+    let form = match storage::read_upload_form(payload, &data.db, &data.config.storage, storage::VIDEOS, "file", content_length).await {
+        Ok(form) => form,
+        Err(error) => return error.error_response(),
+    };
+
+    let Some(student_id) = form.text("student_id").and_then(|id| Uuid::parse_str(id.trim()).ok()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Invalid UUID format for student_id"
+        }));
+    };
+    let Some(file) = &form.file else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Missing 'file' field"
+        }));
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => return StorageError::Database(error).error_response(),
+    };
+
+    let (video, created) = match insert_video(&mut tx, student_id, form.text("description"), file).await {
+        Ok(inserted) => inserted,
         Err(error) => {
-            let response = json!( {
+            let response = json!({
                 "status": "error",
                 "message": format!("Failed to create video: {:?}", error)
             });
-            HttpResponse::InternalServerError().json(response)
+            return HttpResponse::InternalServerError().json(response);
         }
+    };
+
+    // Conteúdo repetido: nada novo a gravar nem a contar na cota, o arquivo temporário é descartado
+    let stored = if created {
+        storage::commit_within_quota(tx, file, data.storage.as_ref(), &data.config.storage, student_id).await
+    } else {
+        Ok(())
+    };
+    match stored {
+        Ok(()) => {
+            let response = json!({
+                "status": "success",
+                "duplicate": !created,
+                "video": data.url_signer.with_url(video.id, video)
+            });
+            HttpResponse::Ok().json(response)
+        }
+        Err(error) => error.error_response(),
     }
 }
 
-// Função para obter todos os vídeos
-#[get("/videos")]
+#[get("/videos", wrap = "RequirePermission(Permission::MediaRead)")]
 pub async fn get_all_videos(
-    opts: Query<FilterOptions>,
+    opts: Query<VideoFilterOptions>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
@@ -70,14 +151,29 @@ pub async fn get_all_videos(
 
     match sqlx::query_as!(
         VideoModel,
-        "SELECT * FROM videos ORDER BY id LIMIT $1 OFFSET $2",
+        r#"
+        SELECT * FROM videos
+        WHERE ($3::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $3))
+          AND ($4::float8 IS NULL OR duration_seconds >= $4)
+          AND ($5::float8 IS NULL OR duration_seconds <= $5)
+          AND ($6::text IS NULL OR video_codec = $6)
+        ORDER BY id LIMIT $1 OFFSET $2
+        "#,
         limit as i32,
-        offset as i32
+        offset as i32,
+        user.parent_id(),
+        opts.min_duration,
+        opts.max_duration,
+        opts.codec.as_deref()
     )
     .fetch_all(&data.db)
     .await
     {
         Ok(videos) => {
+            let videos: Vec<_> = videos
+                .into_iter()
+                .map(|video| data.url_signer.with_url(video.id, video))
+                .collect();
             let response = json!( {
                 "status": "success",
                 "videos": videos
@@ -95,17 +191,19 @@ pub async fn get_all_videos(
 }
 
 // Função para obter um vídeo por ID
-#[get("/videos/{id}")]
+#[get("/videos/{id}", wrap = "RequirePermission(Permission::MediaRead)")]
 async fn get_video_by_id(
     path: Path<Uuid>,
+    user: AuthenticatedUser,
     data: Data<AppState>
 ) -> impl Responder {
     let video_id = path.into_inner();
 
     match sqlx::query_as!(
         VideoModel,
-        "SELECT * FROM videos WHERE id = $1",
-        video_id
+        "SELECT * FROM videos WHERE id = $1 AND ($2::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        video_id,
+        user.parent_id()
     )
     .fetch_one(&data.db)
     .await
@@ -113,10 +211,17 @@ async fn get_video_by_id(
         Ok(video) => {
             let response = json!( {
                 "status": "success",
-                "video": video
+                "video": data.url_signer.with_url(video.id, video)
             });
             HttpResponse::Ok().json(response)
         }
+        Err(sqlx::Error::RowNotFound) => {
+            let response = json!({
+                "status": "error",
+                "message": "Video not found"
+            });
+            HttpResponse::NotFound().json(response)
+        }
         Err(error) => {
             let response = json!( {
                 "status": "error",
@@ -127,8 +232,56 @@ async fn get_video_by_id(
     }
 }
 
+// Função para assistir a um vídeo: aceita Range, para o player avançar sem baixar tudo
+#[get("/videos/{id}/stream", wrap = "RequirePermission(Permission::MediaRead)")]
+async fn stream_video(
+    req: HttpRequest,
+    path: Path<Uuid>,
+    user: AuthenticatedUser,
+    data: Data<AppState>
+) -> impl Responder {
+    let video_id = path.into_inner();
+
+    let video = match sqlx::query_as!(
+        VideoModel,
+        "SELECT * FROM videos WHERE id = $1 AND ($2::uuid IS NULL OR student_id IN (SELECT student_id FROM student_parents WHERE parent_id = $2))",
+        video_id,
+        user.parent_id()
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(video)) => video,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "error",
+                "message": "Video not found"
+            }));
+        }
+        Err(error) => return StorageError::Database(error).error_response(),
+    };
+
+    let response = match &video.storage_key {
+        Some(storage_key) => {
+            files::stream_object(&req, data.storage.as_ref(), storage_key, video.sha256.as_deref())
+                .await
+                .map(Some)
+        }
+        // Vídeos anteriores às storage_keys continuam em ./uploads, pelo filename
+        None => Ok(files::legacy_file(&req, files::LEGACY_UPLOADS_DIR, &video.filename).await),
+    };
+    match response {
+        Ok(Some(response)) => response,
+        Ok(None) | Err(StorageError::NotFound(_)) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Video file not found"
+        })),
+        Err(error) => error.error_response(),
+    }
+}
+
 // Função para atualizar um vídeo
-#[patch("/videos/{id}")]
+#[patch("/videos/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn update_video_by_id(
     path: Path<Uuid>,
     body: Json<UpdateVideoSchema>,
@@ -144,7 +297,10 @@ async fn update_video_by_id(
     .fetch_one(&data.db)
     .await
     {
-        Ok(_) => {
+        Ok(video) => {
+            if let Some(response) = files::refuse_legacy_rename(body.filename.as_deref(), video.storage_key.as_deref()) {
+                return response;
+            }
             let update_result = sqlx::query_as!(
                 VideoModel,
                 "UPDATE videos SET student_id = COALESCE($1, student_id), filename = COALESCE($2, filename), description = COALESCE($3, description) WHERE id = $4 RETURNING *",
@@ -184,17 +340,15 @@ async fn update_video_by_id(
 }
 
 // Função para deletar um vídeo por ID
-#[delete("/videos/{id}")]
+#[delete("/videos/{id}", wrap = "RequirePermission(Permission::MediaWrite)")]
 async fn delete_video_by_id(
     path: Path<Uuid>,
     data: Data<AppState>
 ) -> impl Responder {
     let video_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM videos WHERE id = $1", video_id)
-        .execute(&data.db)
-        .await
-    {
+    // Os arquivos saem junto com o registro; os que falharem ficam na fila de remoção
+    match storage::delete_with_files(&data.db, data.storage.as_ref(), MediaOwner::Video(video_id)).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(err) => {
             let response = json!( {
@@ -206,75 +360,14 @@ async fn delete_video_by_id(
     }
 }
 
-// Função para upload de vídeo
-#[post("/upload-video")]
-async fn upload_video(
-    data: Data<AppState>,
-    mut payload: Multipart
-) -> impl Responder {
-    while let Some(field) = payload.next().await {
-        match field {
-            Ok(mut field) => {
-                // Get the filename from the field
-                let filename = field.content_disposition()
-                    .get_filename()
-                    .map_or("temp.mp4".to_string(), |f| f.to_string());
-                let filepath = format!("./uploads/{}", filename);
-                println!("Saving file to: {}", filepath);
-
-                // Create a file in the uploads directory
-                let mut file = match tokio::fs::File::create(&filepath).await {
-                    Ok(file) => file,
-                    Err(e) => return HttpResponse::InternalServerError().json({
-                        let message = format!("Failed to create file: {:?}", e);
-                        println!("{}", message);
-                        json!({ "status": "error", "message": message })
-                    }),
-                };
-
-                // Write the chunks into the file
-                while let Some(chunk) = field.next().await {
-                    match chunk {
-                        Ok(data) => {
-                            if let Err(e) = file.write_all(&data).await {
-                                return HttpResponse::InternalServerError().json({
-                                    let message = format!("Failed to write to file: {:?}", e);
-                                    println!("{}", message);
-                                    json!({ "status": "error", "message": message })
-                                });
-                            }
-                        }
-                        Err(e) => {
-                            return HttpResponse::InternalServerError().json({
-                                let message = format!("Failed to read chunk: {:?}", e);
-                                println!("{}", message);
-                                json!({ "status": "error", "message": message })
-                            });
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                return HttpResponse::InternalServerError().json({
-                    let message = format!("Failed to process file: {:?}", e);
-                    println!("{}", message);
-                    json!({ "status": "error", "message": message })
-                });
-            }
-        }
-    }
-
-    HttpResponse::Ok().json(json!( {
-        "status": "success",
-        "message": "File uploaded successfully"
-    }))
-}
-
-
 // Função para obter todos os alunos
-#[get("/students")]
-async fn get_students(data: Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(StudentModel, "SELECT * FROM students")
+#[get("/students", wrap = "RequirePermission(Permission::StudentsRead)")]
+async fn get_students(user: AuthenticatedUser, data: Data<AppState>) -> impl Responder {
+    match sqlx::query_as!(
+        StudentModel,
+        "SELECT * FROM students WHERE ($1::uuid IS NULL OR id IN (SELECT student_id FROM student_parents WHERE parent_id = $1))",
+        user.parent_id()
+    )
         .fetch_all(&data.db)
         .await
     {
@@ -300,9 +393,8 @@ pub fn config_videos(conf: &mut ServiceConfig) {
     conf.service(create_video)
        .service(get_all_videos)
        .service(get_video_by_id)
+       .service(stream_video)
        .service(update_video_by_id)
        .service(delete_video_by_id)
-       .service(upload_video) // Adicionando o serviço de upload de vídeo
-       .service(Files::new("/static", "./static").show_files_listing())
        .service(get_students);
 }
@@ -0,0 +1,66 @@
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A record returned together with the signed URL that downloads its file.
+#[derive(Debug, Serialize)]
+pub struct WithUrl<T> {
+    #[serde(flatten)]
+    pub item: T,
+    pub url: String,
+}
+
+/// Issues and checks the expiring links served by `GET /api/files/{id}`. The signature
+/// is an HMAC-SHA256 of the file id and expiry, so links cannot be forged or extended.
+pub struct UrlSigner {
+    key: Vec<u8>,
+    pub ttl: Duration,
+}
+
+impl UrlSigner {
+    pub fn new(secret: &str, ttl: Duration) -> UrlSigner {
+        UrlSigner {
+            key: secret.as_bytes().to_vec(),
+            ttl,
+        }
+    }
+
+    fn mac(&self, id: Uuid, expires_at: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        // Prefixo separa essas assinaturas de qualquer outro uso do mesmo segredo
+        mac.update(format!("file:{}:{}", id, expires_at).as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, id: Uuid, expires_at: i64) -> String {
+        hex::encode(self.mac(id, expires_at).finalize().into_bytes())
+    }
+
+    /// Relative URL valid for [`UrlSigner::ttl`].
+    pub fn signed_path(&self, id: Uuid) -> String {
+        let expires_at = (Utc::now() + self.ttl).timestamp();
+        format!("/api/files/{}?exp={}&sig={}", id, expires_at, self.sign(id, expires_at))
+    }
+
+    pub fn with_url<T>(&self, id: Uuid, item: T) -> WithUrl<T> {
+        WithUrl {
+            url: self.signed_path(id),
+            item,
+        }
+    }
+
+    /// Checks the signature in constant time and that the link has not expired.
+    pub fn verify(&self, id: Uuid, expires_at: i64, signature: &str, now: i64) -> bool {
+        if expires_at < now {
+            return false;
+        }
+        match hex::decode(signature) {
+            Ok(signature) => self.mac(id, expires_at).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::StorageBackend;
+use crate::services::files::{legacy_path, LEGACY_PHOTOS_DIR, LEGACY_UPLOADS_DIR};
+
+// Intervalo da fila e quantas chaves cada rodada tenta
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const RETRY_BATCH: i64 = 100;
+
+/// A record being deleted whose stored files go with it.
+#[derive(Debug, Clone, Copy)]
+pub enum MediaOwner {
+    /// The photo and its variants.
+    Photo(Uuid),
+    Video(Uuid),
+    Document(Uuid),
+    FileMetadata(Uuid),
+    /// Everything that cascades from the student: photos, variants, videos and documents.
+    Student(Uuid),
+}
+
+impl MediaOwner {
+    fn table(self) -> &'static str {
+        match self {
+            MediaOwner::Photo(_) => "photos",
+            MediaOwner::Video(_) => "videos",
+            MediaOwner::Document(_) => "documents",
+            MediaOwner::FileMetadata(_) => "file_metadata",
+            MediaOwner::Student(_) => "students",
+        }
+    }
+
+    fn id(self) -> Uuid {
+        match self {
+            MediaOwner::Photo(id)
+            | MediaOwner::Video(id)
+            | MediaOwner::Document(id)
+            | MediaOwner::FileMetadata(id)
+            | MediaOwner::Student(id) => id,
+        }
+    }
+
+    // Arquivos que deixam de ter registro quando o dono sai, cascatas incluídas: a chave,
+    // ou o nome do arquivo antigo e a tabela que diz em que diretório ele fica
+    fn files_query(self) -> &'static str {
+        match self {
+            MediaOwner::Photo(_) => {
+                "SELECT storage_key, filename, 'photos' FROM photos WHERE id = $1
+                 UNION ALL SELECT storage_key, NULL, 'photo_variants' FROM photo_variants WHERE photo_id = $1"
+            }
+            MediaOwner::Video(_) => "SELECT storage_key, filename, 'videos' FROM videos WHERE id = $1",
+            MediaOwner::Document(_) => "SELECT storage_key, filename, 'documents' FROM documents WHERE id = $1",
+            MediaOwner::FileMetadata(_) => "SELECT storage_key, filename, 'file_metadata' FROM file_metadata WHERE id = $1",
+            MediaOwner::Student(_) => {
+                "SELECT storage_key, filename, 'photos' FROM photos WHERE student_id = $1
+                 UNION ALL SELECT v.storage_key, NULL, 'photo_variants' FROM photo_variants v JOIN photos p ON p.id = v.photo_id WHERE p.student_id = $1
+                 UNION ALL SELECT storage_key, filename, 'videos' FROM videos WHERE student_id = $1
+                 UNION ALL SELECT storage_key, filename, 'documents' FROM documents WHERE student_id = $1"
+            }
+        }
+    }
+}
+
+/// A queued removal: a key in storage, or the path of a file saved before storage keys
+/// together with the legacy directory it was found in.
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct QueuedFile {
+    storage_key: String,
+    legacy_dir: Option<String>,
+}
+
+// Onde ficavam os arquivos sem chave de cada tabela, e as tabelas que guardam cada diretório
+fn legacy_dir(table: &str) -> Option<&'static str> {
+    match table {
+        "photos" => Some(LEGACY_PHOTOS_DIR),
+        "videos" | "documents" | "file_metadata" => Some(LEGACY_UPLOADS_DIR),
+        _ => None,
+    }
+}
+
+fn legacy_tables(dir: &str) -> &'static [&'static str] {
+    match dir {
+        LEGACY_PHOTOS_DIR => &["photos"],
+        LEGACY_UPLOADS_DIR => &["videos", "documents", "file_metadata"],
+        _ => &[],
+    }
+}
+
+/// Deletes the record and queues its stored files in the same transaction, then removes
+/// the files right away. Files saved before storage keys existed are queued by their path
+/// in `./static` or `./uploads`; a legacy filename that is not a plain file name is only
+/// logged. Files that fail to be removed stay queued and are retried by
+/// [`spawn_deletion_retries`]. Returns how many records were deleted.
+pub async fn delete_with_files(db: &PgPool, storage: &dyn StorageBackend, owner: MediaOwner) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let owned: Vec<(Option<String>, Option<String>, String)> = sqlx::query_as(owner.files_query())
+        .bind(owner.id())
+        .fetch_all(&mut tx)
+        .await?;
+
+    let (mut keys, mut dirs) = (Vec::new(), Vec::new());
+    for (storage_key, filename, table) in owned {
+        match (storage_key, filename, legacy_dir(&table)) {
+            (Some(key), _, _) => {
+                keys.push(key);
+                dirs.push(None);
+            }
+            (None, Some(filename), Some(dir)) => match legacy_path(dir, &filename) {
+                Some(path) => {
+                    keys.push(path.to_string_lossy().into_owned());
+                    dirs.push(Some(dir.to_string()));
+                }
+                // Nome inseguro não é procurado no disco; fica no log para alguém conferir
+                None => eprintln!("Not removing legacy file {:?} of {} {}: not a plain file name", filename, table, owner.id()),
+            },
+            _ => {}
+        }
+    }
+    let queued: Vec<QueuedFile> = sqlx::query_as(
+        "INSERT INTO storage_deletions (storage_key, legacy_dir)
+         SELECT * FROM UNNEST($1::text[], $2::text[])
+         ON CONFLICT (storage_key) DO NOTHING
+         RETURNING storage_key, legacy_dir",
+    )
+    .bind(&keys)
+    .bind(&dirs)
+    .fetch_all(&mut tx)
+    .await?;
+
+    // `table` vem sempre de MediaOwner, nunca da requisição
+    let deleted = sqlx::query(&format!("DELETE FROM {} WHERE id = $1", owner.table()))
+        .bind(owner.id())
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+
+    purge(db, storage, &queued).await;
+    Ok(deleted)
+}
+
+async fn is_referenced(db: &PgPool, key: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM photos WHERE storage_key = $1)
+             OR EXISTS (SELECT 1 FROM photo_variants WHERE storage_key = $1)
+             OR EXISTS (SELECT 1 FROM videos WHERE storage_key = $1)
+             OR EXISTS (SELECT 1 FROM documents WHERE storage_key = $1)
+             OR EXISTS (SELECT 1 FROM file_metadata WHERE storage_key = $1)",
+    )
+    .bind(key)
+    .fetch_one(db)
+    .await
+}
+
+// Arquivos antigos eram gravados pelo nome, então outro registro pode ter o mesmo arquivo
+async fn is_legacy_referenced(db: &PgPool, dir: &str, filename: &str) -> Result<bool, sqlx::Error> {
+    let tables = legacy_tables(dir);
+    if tables.is_empty() {
+        // Diretório desconhecido: na dúvida o arquivo fica
+        return Ok(true);
+    }
+    let checks: Vec<String> = tables
+        .iter()
+        .map(|table| format!("EXISTS (SELECT 1 FROM {} WHERE storage_key IS NULL AND filename = $1)", table))
+        .collect();
+    sqlx::query_scalar(&format!("SELECT {}", checks.join(" OR ")))
+        .bind(filename)
+        .fetch_one(db)
+        .await
+}
+
+async fn remove_legacy_file(db: &PgPool, dir: &str, path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    let filename = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    if is_legacy_referenced(db, dir, &filename).await.map_err(|error| error.to_string())? {
+        return Ok(());
+    }
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+async fn purge_one(db: &PgPool, storage: &dyn StorageBackend, file: &QueuedFile) -> Result<(), String> {
+    let key = &file.storage_key;
+    if let Some(dir) = &file.legacy_dir {
+        remove_legacy_file(db, dir, key).await?;
+    } else {
+        // Uma chave enviada por /upload pode ter sido ligada a outro registro depois
+        let referenced = is_referenced(db, key).await.map_err(|error| error.to_string())?;
+        if !referenced {
+            storage.delete(key).await.map_err(|error| error.to_string())?;
+        }
+    }
+    // O envio avulso, se a chave veio de /upload, deixa de contar na cota
+    sqlx::query("DELETE FROM standalone_uploads WHERE storage_key = $1")
+        .bind(key)
+        .execute(db)
+        .await
+        .map_err(|error| error.to_string())?;
+    sqlx::query("DELETE FROM storage_deletions WHERE storage_key = $1")
+        .bind(key)
+        .execute(db)
+        .await
+        .map_err(|error| error.to_string())?;
+    Ok(())
+}
+
+/// Removes the queued `files` from storage (or from the legacy directory) and from the
+/// queue. A failure is recorded on the queue entry, which is tried again later with
+/// exponential backoff (up to a day). Returns how many files were removed.
+async fn purge(db: &PgPool, storage: &dyn StorageBackend, files: &[QueuedFile]) -> usize {
+    let mut purged = 0;
+    for file in files {
+        let key = &file.storage_key;
+        let Err(message) = purge_one(db, storage, file).await else {
+            purged += 1;
+            continue;
+        };
+        eprintln!("Failed to remove {} from storage, will retry: {}", key, message);
+        let recorded = sqlx::query(
+            "UPDATE storage_deletions
+             SET attempts = attempts + 1,
+                 last_error = $2,
+                 next_attempt_at = NOW() + LEAST(INTERVAL '1 minute' * POWER(2, attempts), INTERVAL '1 day')
+             WHERE storage_key = $1",
+        )
+        .bind(key)
+        .bind(&message)
+        .execute(db)
+        .await;
+        if let Err(error) = recorded {
+            eprintln!("Failed to record the failed removal of {}: {:?}", key, error);
+        }
+    }
+    purged
+}
+
+/// Removes objects already queued in `storage_deletions` that no record points at any
+/// more; like [`delete_with_files`], a failed removal stays queued.
+pub(super) async fn purge_keys(db: &PgPool, storage: &dyn StorageBackend, keys: &[String]) -> usize {
+    let files: Vec<QueuedFile> = keys
+        .iter()
+        .map(|key| QueuedFile {
+            storage_key: key.clone(),
+            legacy_dir: None,
+        })
+        .collect();
+    purge(db, storage, &files).await
+}
+
+/// Retries queued removals that are due, once a minute.
+pub fn spawn_deletion_retries(db: PgPool, storage: Arc<dyn StorageBackend>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(RETRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            let due: Result<Vec<QueuedFile>, _> = sqlx::query_as(
+                "SELECT storage_key, legacy_dir FROM storage_deletions WHERE next_attempt_at <= NOW() ORDER BY next_attempt_at LIMIT $1",
+            )
+            .bind(RETRY_BATCH)
+            .fetch_all(&db)
+            .await;
+            match due {
+                Ok(files) if files.is_empty() => {}
+                Ok(files) => {
+                    let purged = purge(&db, storage.as_ref(), &files).await;
+                    println!("Removed {} of {} queued files from storage", purged, files.len());
+                }
+                Err(error) => eprintln!("Failed to read the storage deletion queue: {:?}", error),
+            }
+        }
+    });
+}
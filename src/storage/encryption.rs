@@ -0,0 +1,470 @@
+use std::io::{self, Read, Write};
+
+use aes_gcm::{
+    aead::{stream::{DecryptorBE32, EncryptorBE32}, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use actix_web::web::Bytes;
+use futures_util::{stream::{self, BoxStream}, StreamExt};
+use rand::RngCore;
+use sqlx::PgPool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use super::{
+    deletions, hash_stream, new_storage_key, sniff, temp_path, ObjectBody, StagedFile, StorageBackend, StorageError,
+    TempFile, DOCUMENTS, SNIFF_LEN,
+};
+use crate::config::{MasterKey, StorageConfig};
+
+// Cabeçalho do arquivo cifrado: versão do formato e prefixo do nonce do STREAM
+const MAGIC: &[u8; 4] = b"ENC1";
+const NONCE_PREFIX_LEN: usize = 7;
+const HEADER_LEN: usize = MAGIC.len() + NONCE_PREFIX_LEN;
+// Cada bloco é cifrado e autenticado à parte, então nada precisa caber inteiro na memória
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// A document's data key as stored: wrapped by the master key `key_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub key_id: String,
+    /// Nonce followed by the encrypted key and its tag.
+    pub wrapped_key: Vec<u8>,
+}
+
+impl Envelope {
+    /// The envelope on record, when the document is encrypted.
+    pub fn from_record(key_id: Option<String>, wrapped_key: Option<Vec<u8>>) -> Option<Envelope> {
+        Some(Envelope {
+            key_id: key_id?,
+            wrapped_key: wrapped_key?,
+        })
+    }
+}
+
+fn encryption_error(message: impl Into<String>) -> StorageError {
+    StorageError::Encryption(message.into())
+}
+
+fn cipher(key: &[u8; 32]) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+}
+
+/// Wraps `data_key` with `master`. The key id is authenticated along with it, so an
+/// envelope cannot be passed off as wrapped by another key.
+pub fn wrap_key(master: &MasterKey, data_key: &[u8; 32]) -> Envelope {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: data_key,
+        aad: master.id.as_bytes(),
+    };
+    // Só falha com mensagens maiores que o GCM aceita; 32 bytes nunca chegam perto
+    let encrypted = cipher(&master.key).encrypt(&nonce, payload).expect("a 32-byte key always encrypts");
+    let mut wrapped_key = nonce.to_vec();
+    wrapped_key.extend_from_slice(&encrypted);
+    Envelope {
+        key_id: master.id.clone(),
+        wrapped_key,
+    }
+}
+
+/// Recovers the data key of `envelope` with whichever of `keys` wrapped it.
+pub fn unwrap_key(keys: &[MasterKey], envelope: &Envelope) -> Result<[u8; 32], StorageError> {
+    let master = keys
+        .iter()
+        .find(|key| key.id == envelope.key_id)
+        .ok_or_else(|| encryption_error(format!("master key '{}' is not configured", envelope.key_id)))?;
+    if envelope.wrapped_key.len() < NONCE_LEN {
+        return Err(encryption_error("wrapped key is truncated"));
+    }
+    let (nonce, encrypted) = envelope.wrapped_key.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: encrypted,
+        aad: master.id.as_bytes(),
+    };
+    let data_key = cipher(&master.key)
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| encryption_error(format!("data key does not open with master key '{}'", master.id)))?;
+    <[u8; 32]>::try_from(data_key).map_err(|_| encryption_error("data key has the wrong length"))
+}
+
+/// Encrypts `source` into `destination` under `data_key`: a header, then the contents in
+/// chunks of 64 KiB, each with its own tag, the last one marked as such so a truncated
+/// file does not decrypt.
+pub fn encrypt_to(data_key: &[u8; 32], mut source: impl Read, mut destination: impl Write) -> io::Result<()> {
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    destination.write_all(MAGIC)?;
+    destination.write_all(&prefix)?;
+
+    let mut encryptor = EncryptorBE32::from_aead(cipher(data_key), prefix.as_ref().into());
+    let mut chunk = vec![0u8; CHUNK_LEN];
+    let mut filled = read_full(&mut source, &mut chunk)?;
+    loop {
+        // Lê um bloco adiante para saber se o atual é o último
+        let mut next = vec![0u8; CHUNK_LEN];
+        let next_filled = if filled == CHUNK_LEN { read_full(&mut source, &mut next)? } else { 0 };
+        if next_filled == 0 {
+            let last = encryptor
+                .encrypt_last(&chunk[..filled])
+                .map_err(|_| io::Error::other("failed to encrypt the last chunk"))?;
+            destination.write_all(&last)?;
+            return destination.flush();
+        }
+        let encrypted = encryptor
+            .encrypt_next(&chunk[..filled])
+            .map_err(|_| io::Error::other("failed to encrypt a chunk"))?;
+        destination.write_all(&encrypted)?;
+        chunk = next;
+        filled = next_filled;
+    }
+}
+
+// Como read_exact, mas aceita o fim do arquivo no meio e devolve quanto leu
+fn read_full(source: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match source.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Size of the plaintext of an encrypted object of `size` bytes, or `None` when no file
+/// written by [`encrypt_to`] has that size.
+pub fn plaintext_len(size: u64) -> Option<u64> {
+    let body = size.checked_sub(HEADER_LEN as u64)?;
+    let sealed = (CHUNK_LEN + TAG_LEN) as u64;
+    let chunks = body.div_ceil(sealed).max(1);
+    let plaintext = body.checked_sub(chunks * TAG_LEN as u64)?;
+    // Só o último bloco pode ser curto, e ao menos a tag dele tem de estar lá
+    (body % sealed == 0 || body % sealed >= TAG_LEN as u64).then_some(plaintext)
+}
+
+struct Decryption {
+    encrypted: BoxStream<'static, io::Result<Bytes>>,
+    decryptor: Option<DecryptorBE32<Aes256Gcm>>,
+    data_key: [u8; 32],
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+fn decryption_failed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "encrypted file is corrupted or truncated")
+}
+
+impl Decryption {
+    // Próximo pedaço em claro; None quando o último bloco já saiu
+    async fn next_chunk(&mut self) -> Option<io::Result<Bytes>> {
+        let sealed = CHUNK_LEN + TAG_LEN;
+        while !self.done {
+            if self.decryptor.is_some() && self.buffer.len() > sealed {
+                let rest = self.buffer.split_off(sealed);
+                let chunk = std::mem::replace(&mut self.buffer, rest);
+                let decryptor = self.decryptor.as_mut()?;
+                return Some(decryptor.decrypt_next(chunk.as_slice()).map(Bytes::from).map_err(|_| decryption_failed()));
+            }
+            match self.encrypted.next().await {
+                Some(Ok(bytes)) => {
+                    self.buffer.extend_from_slice(&bytes);
+                    if self.decryptor.is_none() && self.buffer.len() >= HEADER_LEN {
+                        if &self.buffer[..MAGIC.len()] != MAGIC {
+                            return Some(Err(decryption_failed()));
+                        }
+                        let prefix = self.buffer[MAGIC.len()..HEADER_LEN].to_vec();
+                        self.buffer.drain(..HEADER_LEN);
+                        self.decryptor = Some(DecryptorBE32::from_aead(cipher(&self.data_key), prefix.as_slice().into()));
+                    }
+                }
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.done = true;
+                    // Sem cabeçalho completo não há o que decifrar
+                    let Some(decryptor) = self.decryptor.take() else {
+                        return Some(Err(decryption_failed()));
+                    };
+                    let last = std::mem::take(&mut self.buffer);
+                    return Some(decryptor.decrypt_last(last.as_slice()).map(Bytes::from).map_err(|_| decryption_failed()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Decrypts, chunk by chunk, an object written by [`encrypt_to`]. A chunk that fails its
+/// tag, or a file cut short, ends the stream with an error.
+pub fn decrypt_stream(encrypted: BoxStream<'static, io::Result<Bytes>>, data_key: [u8; 32]) -> BoxStream<'static, io::Result<Bytes>> {
+    let decryption = Decryption {
+        encrypted,
+        decryptor: None,
+        data_key,
+        buffer: Vec::new(),
+        done: false,
+    };
+    stream::unfold(Some(decryption), |state| async move {
+        let mut decryption = state?;
+        match decryption.next_chunk().await {
+            Some(Ok(chunk)) => Some((Ok(chunk), Some(decryption))),
+            // Depois de um erro, o stream termina
+            Some(Err(error)) => Some((Err(error), None)),
+            None => None,
+        }
+    })
+    .boxed()
+}
+
+/// Reads a document back in plaintext: decrypted when it has an envelope, as stored
+/// otherwise. `size` and `length` are those of the plaintext.
+pub async fn open_document(
+    storage: &dyn StorageBackend,
+    keys: &[MasterKey],
+    storage_key: &str,
+    envelope: Option<&Envelope>,
+) -> Result<ObjectBody, StorageError> {
+    let Some(envelope) = envelope else {
+        return storage.get_range(storage_key, None).await;
+    };
+    let data_key = unwrap_key(keys, envelope)?;
+    let object = storage.get_range(storage_key, None).await?;
+    let size = plaintext_len(object.size).ok_or_else(|| encryption_error(format!("{} is not an encrypted file", storage_key)))?;
+    Ok(ObjectBody {
+        size,
+        length: size,
+        stream: decrypt_stream(object.stream, data_key),
+    })
+}
+
+/// Hex SHA-256 of a document's plaintext, comparable with the checksum on record.
+pub async fn hash_document(
+    storage: &dyn StorageBackend,
+    keys: &[MasterKey],
+    storage_key: &str,
+    envelope: Option<&Envelope>,
+) -> Result<String, StorageError> {
+    let body = open_document(storage, keys, storage_key, envelope).await?;
+    Ok(hash_stream(body.stream).await?)
+}
+
+// Cifra o arquivo sob uma chave de dados nova e devolve o envelope dela
+async fn encrypt_in_place(master: &MasterKey, source: &std::path::Path) -> Result<Envelope, StorageError> {
+    let mut data_key = [0u8; 32];
+    OsRng.fill_bytes(&mut data_key);
+
+    let Some(dir) = source.parent() else {
+        return Err(encryption_error("staged file has no directory"));
+    };
+    let encrypted = TempFile(temp_path(dir));
+    let (source, destination) = (source.to_path_buf(), encrypted.0.clone());
+    tokio::task::spawn_blocking(move || {
+        let reader = io::BufReader::new(std::fs::File::open(&source)?);
+        let writer = io::BufWriter::new(std::fs::File::create_new(&destination)?);
+        encrypt_to(&data_key, reader, writer)?;
+        // Troca o temporário pelo cifrado; o texto em claro não chega ao storage
+        std::fs::rename(&destination, &source)
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))??;
+
+    Ok(wrap_key(master, &data_key))
+}
+
+impl StagedFile {
+    /// Encrypts the staged file in place under a new data key, wrapped by the current
+    /// master key. `None`, leaving the file as it is, when no master key is configured.
+    /// The checksum and size on the staged file stay those of the plaintext.
+    pub async fn encrypt(&self, keys: &[MasterKey]) -> Result<Option<Envelope>, StorageError> {
+        let Some(master) = keys.first() else {
+            return Ok(None);
+        };
+        encrypt_in_place(master, &self.temp.0).await.map(Some)
+    }
+}
+
+/// Outcome of [`rotate_keys`].
+#[derive(Debug, Default)]
+pub struct RotationReport {
+    pub rewrapped: usize,
+    /// Documents still stored in plaintext, which have no key to rotate; see
+    /// [`encrypt_documents`].
+    pub plaintext: i64,
+    pub errors: Vec<String>,
+}
+
+// Tabelas com envelope: documentos e envios avulsos de /upload, com a coluna e o tipo da chave
+const ENVELOPES: [(&str, &str, &str); 2] = [("documents", "id", "uuid"), ("standalone_uploads", "storage_key", "text")];
+
+// Documentos no storage ainda sem envelope
+const PLAINTEXT_DOCUMENTS: &str = "FROM documents WHERE storage_key IS NOT NULL AND encryption_key_id IS NULL";
+
+/// Re-wraps every document data key that is not wrapped by the current master key,
+/// standalone uploads included. The files are untouched: their data keys stay the same.
+/// Once this reports no errors, the previous master keys can be removed from the
+/// configuration.
+pub async fn rotate_keys(db: &PgPool, keys: &[MasterKey]) -> Result<RotationReport, StorageError> {
+    let Some(current) = keys.first() else {
+        return Err(encryption_error("DOCUMENT_ENCRYPTION_KEYS is not set"));
+    };
+
+    let mut report = RotationReport::default();
+    // Nomes vêm sempre de ENVELOPES, nunca da requisição
+    for (table, id_column, id_type) in ENVELOPES {
+        let rows: Vec<(String, String, Vec<u8>)> = sqlx::query_as(&format!(
+            "SELECT {}::text, encryption_key_id, encrypted_data_key FROM {}
+             WHERE encryption_key_id IS NOT NULL AND encrypted_data_key IS NOT NULL AND encryption_key_id <> $1",
+            id_column, table
+        ))
+        .bind(&current.id)
+        .fetch_all(db)
+        .await
+        .map_err(StorageError::Database)?;
+
+        for (id, key_id, wrapped_key) in rows {
+            let envelope = Envelope { key_id, wrapped_key };
+            let rewrapped = match unwrap_key(keys, &envelope) {
+                Ok(data_key) => wrap_key(current, &data_key),
+                Err(error) => {
+                    report.errors.push(format!("Could not open the key of {} {}: {}", table, id, error));
+                    continue;
+                }
+            };
+            // Só troca se ninguém mexeu no envelope desde a leitura
+            let updated = sqlx::query(&format!(
+                "UPDATE {} SET encryption_key_id = $1, encrypted_data_key = $2
+                 WHERE {} = $3::{} AND encryption_key_id = $4 AND encrypted_data_key = $5",
+                table, id_column, id_type
+            ))
+            .bind(&rewrapped.key_id)
+            .bind(&rewrapped.wrapped_key)
+            .bind(&id)
+            .bind(&envelope.key_id)
+            .bind(&envelope.wrapped_key)
+            .execute(db)
+            .await;
+            match updated {
+                Ok(result) => report.rewrapped += result.rows_affected() as usize,
+                Err(error) => report.errors.push(format!("Could not update {} {}: {:?}", table, id, error)),
+            }
+        }
+    }
+
+    report.plaintext = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", PLAINTEXT_DOCUMENTS))
+        .fetch_one(db)
+        .await
+        .map_err(StorageError::Database)?;
+    Ok(report)
+}
+
+/// Outcome of [`encrypt_documents`].
+#[derive(Debug, Default)]
+pub struct EncryptionReport {
+    pub encrypted: usize,
+    pub errors: Vec<String>,
+}
+
+async fn download(storage: &dyn StorageBackend, key: &str, path: &std::path::Path) -> Result<(), StorageError> {
+    let mut body = storage.get_range(key, None).await?;
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create_new(path).await?);
+    while let Some(chunk) = body.stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+// Aponta o registro para o objeto cifrado e enfileira o em claro, se o documento ainda é o lido
+async fn point_at_encrypted(db: &PgPool, id: Uuid, old_key: &str, new_key: &str, envelope: &Envelope) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let updated = sqlx::query(
+        "UPDATE documents SET storage_key = $1, encryption_key_id = $2, encrypted_data_key = $3
+         WHERE id = $4 AND storage_key = $5 AND encryption_key_id IS NULL",
+    )
+    .bind(new_key)
+    .bind(&envelope.key_id)
+    .bind(&envelope.wrapped_key)
+    .bind(id)
+    .bind(old_key)
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Ok(false);
+    }
+    sqlx::query("INSERT INTO storage_deletions (storage_key) VALUES ($1) ON CONFLICT (storage_key) DO NOTHING")
+        .bind(old_key)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+async fn encrypt_document(
+    db: &PgPool,
+    storage: &dyn StorageBackend,
+    config: &StorageConfig,
+    master: &MasterKey,
+    id: Uuid,
+    old_key: &str,
+) -> Result<bool, StorageError> {
+    tokio::fs::create_dir_all(&config.tmp_dir).await?;
+    let temp = TempFile(temp_path(&config.tmp_dir));
+    download(storage, old_key, &temp.0).await?;
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    tokio::fs::File::open(&temp.0).await?.take(SNIFF_LEN as u64).read_to_end(&mut head).await?;
+    let media_type = sniff(&head).ok_or_else(|| encryption_error(format!("{} is not a known file type", old_key)))?;
+    let envelope = encrypt_in_place(master, &temp.0).await?;
+
+    // Chave nova: o objeto em claro só sai depois que o registro aponta para o cifrado
+    let new_key = new_storage_key(DOCUMENTS, media_type);
+    storage.put(&new_key, &temp.0).await?;
+    match point_at_encrypted(db, id, old_key, &new_key, &envelope).await {
+        Ok(true) => {
+            deletions::purge_keys(db, storage, &[old_key.to_string()]).await;
+            Ok(true)
+        }
+        committed => {
+            if let Err(error) = storage.delete(&new_key).await {
+                eprintln!("Failed to remove {} after rollback: {}", new_key, error);
+            }
+            committed.map_err(StorageError::Database)
+        }
+    }
+}
+
+/// Encrypts the documents stored in plaintext before encryption was configured with the
+/// current master key. Each one is written again under a new key; its record is pointed
+/// at the encrypted object with its envelope, and the plaintext object is queued for
+/// removal like a deleted file. Documents saved before storage keys, in `./uploads`, are
+/// left as they are, and so are plaintext standalone uploads, which no record points at
+/// and reconciliation removes.
+pub async fn encrypt_documents(
+    db: &PgPool,
+    storage: &dyn StorageBackend,
+    config: &StorageConfig,
+) -> Result<EncryptionReport, StorageError> {
+    let Some(master) = config.document_keys.first() else {
+        return Err(encryption_error("DOCUMENT_ENCRYPTION_KEYS is not set"));
+    };
+    let rows: Vec<(Uuid, String)> = sqlx::query_as(&format!("SELECT id, storage_key {}", PLAINTEXT_DOCUMENTS))
+        .fetch_all(db)
+        .await
+        .map_err(StorageError::Database)?;
+
+    let mut report = EncryptionReport::default();
+    for (id, storage_key) in rows {
+        match encrypt_document(db, storage, config, master, id, &storage_key).await {
+            Ok(true) => report.encrypted += 1,
+            // Mudou desde a leitura: quem mudou já gravou o arquivo novo
+            Ok(false) => {}
+            Err(error) => report.errors.push(format!("Could not encrypt document {}: {}", id, error)),
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,134 @@
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::{ObjectBody, ObjectInfo, StorageBackend, StorageError};
+
+/// Objects as plain files under a root directory, the key being the relative path.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> LocalStorage {
+        LocalStorage { root: root.into() }
+    }
+
+    // Chaves são relativas à raiz: nada de caminhos absolutos ou ".."
+    fn resolve(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        let is_safe = !key.is_empty() && relative.components().all(|component| matches!(component, Component::Normal(_)));
+        if !is_safe {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn info(key: String, metadata: &std::fs::Metadata) -> ObjectInfo {
+        ObjectInfo {
+            key,
+            size: metadata.len(),
+            last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        }
+    }
+}
+
+fn not_found(key: &str, error: io::Error) -> StorageError {
+    if error.kind() == io::ErrorKind::NotFound {
+        StorageError::NotFound(key.to_string())
+    } else {
+        StorageError::Io(error)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, file: &Path) -> Result<(), StorageError> {
+        let path = self.resolve(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // rename não atravessa sistemas de arquivos; aí copia
+        if tokio::fs::rename(file, &path).await.is_err() {
+            tokio::fs::copy(file, &path).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_range(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StorageError> {
+        let path = self.resolve(key)?;
+        let mut file = tokio::fs::File::open(&path).await.map_err(|error| not_found(key, error))?;
+        let size = file.metadata().await?.len();
+
+        let range = range.unwrap_or(0..size);
+        let start = range.start.min(size);
+        let length = range.end.min(size).saturating_sub(start);
+        file.seek(SeekFrom::Start(start)).await?;
+
+        Ok(ObjectBody {
+            size,
+            length,
+            stream: ReaderStream::new(file.take(length)).boxed(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.resolve(key)?).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        // Começa do diretório mais fundo que o prefixo já determina
+        let base = match prefix.rsplit_once('/') {
+            Some((dir, _)) => dir.to_string(),
+            None => String::new(),
+        };
+        let mut pending = vec![base];
+        let mut objects = Vec::new();
+
+        while let Some(dir) = pending.pop() {
+            let path = if dir.is_empty() { self.root.clone() } else { self.resolve(&dir)? };
+            let mut entries = match tokio::fs::read_dir(&path).await {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let key = if dir.is_empty() { name } else { format!("{}/{}", dir, name) };
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    if key.starts_with(prefix) || prefix.starts_with(&format!("{}/", key)) {
+                        pending.push(key);
+                    }
+                } else if key.starts_with(prefix) {
+                    objects.push(LocalStorage::info(key, &metadata));
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError> {
+        match tokio::fs::metadata(self.resolve(key)?).await {
+            Ok(metadata) if metadata.is_file() => Ok(Some(LocalStorage::info(key.to_string(), &metadata))),
+            Ok(_) => Ok(None),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
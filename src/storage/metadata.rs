@@ -0,0 +1,342 @@
+use chrono::NaiveDateTime;
+
+use super::{MediaType, StorageError};
+
+// Tags EXIF lidas antes da remoção
+const ORIENTATION: u16 = 0x0112;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const DATE_TIME_DIGITIZED: u16 = 0x9004;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// What is read out of an image's metadata before all of it is removed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImageMetadata {
+    /// Camera clock when the photo was taken. EXIF carries no time zone.
+    pub taken_at: Option<NaiveDateTime>,
+    /// EXIF orientation, 1 to 8.
+    pub orientation: Option<u8>,
+}
+
+fn malformed(format: &str) -> StorageError {
+    StorageError::Image(format!("malformed {} file", format))
+}
+
+// Leitura mínima de um bloco TIFF/EXIF: só o necessário para achar as tags acima
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let data = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+        let big_endian = match data.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    // Posição da entrada `tag` no IFD que começa em `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|index| ifd + 2 + index * 12)
+            .find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    fn short(&self, ifd: usize, tag: u16) -> Option<u16> {
+        let entry = self.entry(ifd, tag)?;
+        (self.u16(entry + 2)? == 3).then_some(())?;
+        self.u16(entry + 8)
+    }
+
+    fn long(&self, ifd: usize, tag: u16) -> Option<u32> {
+        let entry = self.entry(ifd, tag)?;
+        match self.u16(entry + 2)? {
+            3 => self.u16(entry + 8).map(u32::from),
+            4 => self.u32(entry + 8),
+            _ => None,
+        }
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<&'a str> {
+        let entry = self.entry(ifd, tag)?;
+        (self.u16(entry + 2)? == 2).then_some(())?;
+        let count = self.u32(entry + 4)? as usize;
+        // Até 4 bytes o texto fica na própria entrada; acima disso, no offset indicado
+        let start = if count <= 4 { entry + 8 } else { self.u32(entry + 8)? as usize };
+        let bytes = self.data.get(start..start.checked_add(count)?)?;
+        std::str::from_utf8(bytes).ok()
+    }
+}
+
+fn parse_exif_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim_end_matches(['\0', ' ']), "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// Reads the capture time and orientation out of an EXIF block (a TIFF structure, with
+/// or without the `Exif\0\0` header). Anything unreadable is simply left out.
+fn read_exif(data: &[u8]) -> ImageMetadata {
+    let Some(tiff) = Tiff::new(data) else {
+        return ImageMetadata::default();
+    };
+    let Some(ifd0) = tiff.u32(4).map(|offset| offset as usize) else {
+        return ImageMetadata::default();
+    };
+
+    let exif_ifd = tiff.long(ifd0, EXIF_IFD).map(|offset| offset as usize);
+    let taken_at = exif_ifd
+        .and_then(|ifd| tiff.ascii(ifd, DATE_TIME_ORIGINAL).or_else(|| tiff.ascii(ifd, DATE_TIME_DIGITIZED)))
+        .or_else(|| tiff.ascii(ifd0, DATE_TIME))
+        .and_then(parse_exif_date);
+    let orientation = tiff
+        .short(ifd0, ORIENTATION)
+        .filter(|orientation| (1..=8).contains(orientation))
+        .map(|orientation| orientation as u8);
+
+    ImageMetadata { taken_at, orientation }
+}
+
+// Bloco EXIF que só diz a orientação, para a foto continuar de pé nos visualizadores
+fn orientation_exif(orientation: u8) -> Vec<u8> {
+    let mut tiff = b"MM\0*".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&[0, orientation, 0, 0]);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+// Orientação 1 é a normal: não precisa de bloco nenhum
+fn kept_orientation(metadata: &ImageMetadata) -> Option<u8> {
+    metadata.orientation.filter(|&orientation| orientation != 1)
+}
+
+/// Removes location, device and every other descriptive metadata from an image,
+/// returning the cleaned file and what was read out first. Only the orientation is
+/// written back. Files whose structure cannot be followed are refused, since they could
+/// not be cleaned.
+pub fn strip_metadata(media_type: MediaType, data: &[u8]) -> Result<(Vec<u8>, ImageMetadata), StorageError> {
+    match media_type {
+        MediaType::Jpeg => strip_jpeg(data),
+        MediaType::Png => strip_png(data),
+        MediaType::Webp => strip_webp(data),
+        _ => Ok((data.to_vec(), ImageMetadata::default())),
+    }
+}
+
+fn strip_jpeg(data: &[u8]) -> Result<(Vec<u8>, ImageMetadata), StorageError> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(malformed("JPEG"));
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut metadata = None;
+    let mut pos = 2;
+
+    loop {
+        if data.get(pos) != Some(&0xFF) {
+            return Err(malformed("JPEG"));
+        }
+        // Bytes 0xFF repetidos antes de um marcador são preenchimento
+        while data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = *data.get(pos + 1).ok_or_else(|| malformed("JPEG"))?;
+
+        match marker {
+            // EOI: o que vier depois (prévias, dados de fabricante) é descartado
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                return Ok((out, metadata.unwrap_or_default()));
+            }
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = data
+            .get(pos + 2..pos + 4)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+            .filter(|&length| length >= 2)
+            .ok_or_else(|| malformed("JPEG"))?;
+        let end = pos + 2 + length;
+        let segment = data.get(pos..end).ok_or_else(|| malformed("JPEG"))?;
+        let payload = &segment[4..];
+
+        let keep = match marker {
+            // APP1: EXIF (lido e trocado só pela orientação) ou XMP
+            0xE1 => {
+                if metadata.is_none() && payload.starts_with(EXIF_HEADER) {
+                    let read = read_exif(payload);
+                    if let Some(orientation) = kept_orientation(&read) {
+                        let mut exif = EXIF_HEADER.to_vec();
+                        exif.extend_from_slice(&orientation_exif(orientation));
+                        out.extend_from_slice(&[0xFF, 0xE1]);
+                        out.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+                        out.extend_from_slice(&exif);
+                    }
+                    metadata = Some(read);
+                }
+                false
+            }
+            // JFIF e Adobe descrevem as cores; do APP2 só o perfil ICC fica
+            0xE0 | 0xEE => true,
+            0xE2 => payload.starts_with(b"ICC_PROFILE\0"),
+            0xE3..=0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(segment);
+        }
+        pos = end;
+
+        // SOS: os dados comprimidos vão até o próximo marcador de verdade
+        if marker == 0xDA {
+            let mut scan = pos;
+            loop {
+                match (data.get(scan), data.get(scan + 1)) {
+                    (Some(0xFF), Some(0x00 | 0xD0..=0xD7 | 0xFF)) => scan += 1,
+                    (Some(0xFF), Some(_)) => break,
+                    (Some(_), _) => scan += 1,
+                    (None, _) => return Err(malformed("JPEG")),
+                }
+            }
+            out.extend_from_slice(&data[pos..scan]);
+            pos = scan;
+        }
+    }
+}
+
+// CRC-32 dos chunks PNG (polinômio 0xEDB88320), só para o eXIf reescrito
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn strip_png(data: &[u8]) -> Result<(Vec<u8>, ImageMetadata), StorageError> {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(malformed("PNG"));
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..8]);
+    let mut metadata = ImageMetadata::default();
+    let mut pos = 8;
+
+    loop {
+        let header = data.get(pos..pos + 8).ok_or_else(|| malformed("PNG"))?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        let end = pos
+            .checked_add(12)
+            .and_then(|end| end.checked_add(length))
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| malformed("PNG"))?;
+
+        match kind {
+            b"eXIf" => {
+                metadata = read_exif(&data[pos + 8..pos + 8 + length]);
+                if let Some(orientation) = kept_orientation(&metadata) {
+                    let exif = orientation_exif(orientation);
+                    out.extend_from_slice(&(exif.len() as u32).to_be_bytes());
+                    out.extend_from_slice(b"eXIf");
+                    out.extend_from_slice(&exif);
+                    out.extend_from_slice(&crc32(&[b"eXIf", &exif]).to_be_bytes());
+                }
+            }
+            // Textos livres (onde vai o XMP) e data de modificação
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {}
+            _ => out.extend_from_slice(&data[pos..end]),
+        }
+        pos = end;
+
+        if kind == b"IEND" {
+            return Ok((out, metadata));
+        }
+    }
+}
+
+fn strip_webp(data: &[u8]) -> Result<(Vec<u8>, ImageMetadata), StorageError> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(malformed("WebP"));
+    }
+    let mut body = b"WEBP".to_vec();
+    let mut metadata = ImageMetadata::default();
+    let mut flags_at = None;
+    let mut has_exif = false;
+    let mut pos = 12;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(|| malformed("WebP"))?;
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let kind = &header[..4];
+        let payload = data.get(pos + 8..pos + 8 + size).ok_or_else(|| malformed("WebP"))?;
+        // Chunks têm tamanho par; o último pode vir sem o byte de preenchimento
+        let end = (pos + 8 + size + size % 2).min(data.len());
+
+        match kind {
+            b"EXIF" => {
+                metadata = read_exif(payload);
+                if let Some(orientation) = kept_orientation(&metadata) {
+                    let exif = orientation_exif(orientation);
+                    body.extend_from_slice(b"EXIF");
+                    body.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+                    body.extend_from_slice(&exif);
+                    has_exif = true;
+                }
+            }
+            b"XMP " => {}
+            _ => {
+                if kind == b"VP8X" && !payload.is_empty() {
+                    flags_at = Some(body.len() + 8);
+                }
+                body.extend_from_slice(header);
+                body.extend_from_slice(payload);
+                if size % 2 == 1 {
+                    body.push(0);
+                }
+            }
+        }
+        pos = end;
+    }
+
+    // VP8X anuncia quais chunks de metadados existem: bit 0x08 é EXIF, 0x04 é XMP
+    if let Some(flags_at) = flags_at {
+        body[flags_at] &= !0x04;
+        if !has_exif {
+            body[flags_at] &= !0x08;
+        }
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok((out, metadata))
+}
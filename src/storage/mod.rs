@@ -0,0 +1,662 @@
+mod deletions;
+mod encryption;
+mod local;
+mod metadata;
+mod mp4;
+mod quota;
+mod s3;
+mod sniff;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::StatusCode, web::Bytes, HttpResponse, ResponseError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::{stream::BoxStream, Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::config::{StorageConfig, StorageKind};
+
+pub use deletions::{delete_with_files, spawn_deletion_retries, MediaOwner};
+pub use encryption::{encrypt_documents, hash_document, open_document, rotate_keys, Envelope};
+#[cfg(test)]
+pub use encryption::{encrypt_to, plaintext_len, unwrap_key, wrap_key};
+pub use local::LocalStorage;
+pub use metadata::{strip_metadata, ImageMetadata};
+pub use mp4::{probe_mp4, Mp4Error, VideoInfo};
+pub use quota::{check_quota, content_length, usage_report};
+pub use s3::S3Storage;
+#[cfg(test)]
+pub use s3::{parse_list_response, SigV4};
+pub use sniff::{allowed_types, sniff, MediaClass, MediaType, SNIFF_LEN};
+
+// Extensões aceitas nas chaves já emitidas, que usavam a do nome enviado
+const MAX_EXTENSION_LEN: usize = 8;
+const MAX_ORIGINAL_FILENAME_LEN: usize = 255;
+/// Largest text field accepted next to a file in a multipart form.
+pub const MAX_TEXT_FIELD_BYTES: usize = 16 * 1024;
+
+// Primeiro nível das chaves, um por tipo de registro
+pub const PHOTOS: &str = "photos";
+pub const VIDEOS: &str = "videos";
+pub const DOCUMENTS: &str = "documents";
+pub const FILES: &str = "files";
+
+/// A file written under a server-generated key. `filename` is the name the client sent,
+/// kept only as metadata.
+#[derive(Debug, Serialize)]
+pub struct StoredFile {
+    pub storage_key: String,
+    pub filename: String,
+    pub sha256: String,
+}
+
+/// Size and modification time of a stored object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Contents of an object, or of the requested range of it. `size` is the size of the
+/// whole object; `length` is how many bytes `stream` yields.
+pub struct ObjectBody {
+    pub size: u64,
+    pub length: u64,
+    pub stream: BoxStream<'static, io::Result<Bytes>>,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(String),
+    InvalidKey(String),
+    Io(io::Error),
+    Multipart(String),
+    UnsupportedType(String),
+    TooLarge(u64),
+    Http(reqwest::Error),
+    Backend(String),
+    Database(sqlx::Error),
+    Image(String),
+    Video(String),
+    /// The student's quota, in bytes, would be exceeded.
+    OverQuota(u64),
+    /// The installation's quota, in bytes, would be exceeded.
+    StorageFull(u64),
+    Encryption(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(key) => write!(f, "Object not found: {}", key),
+            StorageError::InvalidKey(key) => write!(f, "Invalid storage key: {}", key),
+            StorageError::Io(error) => write!(f, "Failed to write file: {}", error),
+            StorageError::Multipart(error) => write!(f, "Failed to read upload: {}", error),
+            StorageError::UnsupportedType(allowed) => write!(f, "Unsupported file type; accepted: {}", allowed),
+            StorageError::TooLarge(limit) => write!(f, "File exceeds the {} byte limit", limit),
+            StorageError::Http(error) => write!(f, "Storage request failed: {}", error),
+            StorageError::Backend(message) => write!(f, "Storage backend error: {}", message),
+            StorageError::Database(error) => write!(f, "Failed to save record: {}", error),
+            StorageError::Image(error) => write!(f, "Failed to process image: {}", error),
+            StorageError::Video(error) => write!(f, "Invalid video: {}", error),
+            StorageError::OverQuota(limit) => write!(f, "Upload exceeds the student's storage quota of {} bytes", limit),
+            StorageError::StorageFull(limit) => write!(f, "Upload exceeds the storage quota of {} bytes", limit),
+            StorageError::Encryption(error) => write!(f, "Encryption error: {}", error),
+        }
+    }
+}
+
+impl ResponseError for StorageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            StorageError::Multipart(_) | StorageError::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+            StorageError::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            StorageError::TooLarge(_) | StorageError::OverQuota(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            StorageError::StorageFull(_) => StatusCode::INSUFFICIENT_STORAGE,
+            StorageError::Image(_) | StorageError::Video(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({
+            "status": "error",
+            "message": self.to_string()
+        }))
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+impl From<reqwest::Error> for StorageError {
+    fn from(error: reqwest::Error) -> Self {
+        StorageError::Http(error)
+    }
+}
+
+/// Where media files live. Handlers only see this trait; the implementation is picked
+/// in configuration (`STORAGE_BACKEND`).
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Moves a finished local file into storage under `key`.
+    async fn put(&self, key: &str, file: &Path) -> Result<(), StorageError>;
+
+    /// Reads the object, or only `range` of it (byte offsets, end exclusive).
+    async fn get_range(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StorageError>;
+
+    /// Removes the object. Removing a key that does not exist is not an error.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Every object whose key starts with `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError>;
+
+    /// `None` when there is no object under `key`.
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError>;
+}
+
+pub fn from_config(config: &StorageConfig) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    match config.backend {
+        StorageKind::Local => Ok(Arc::new(LocalStorage::new(&config.dir))),
+        StorageKind::S3 => Ok(Arc::new(S3Storage::new(config)?)),
+    }
+}
+
+/// Last path segment of the name sent by the client, for display only; never used to
+/// build a path.
+pub fn original_filename(raw: &str) -> String {
+    let name = raw
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_ORIGINAL_FILENAME_LEN)
+        .collect::<String>();
+    if name.is_empty() || name == "." || name == ".." {
+        "unnamed".to_string()
+    } else {
+        name
+    }
+}
+
+/// New key `{kind}/{aa}/{bb}/{uuid}.{ext}`, the extension following the sniffed type.
+/// The two shard levels come from the UUID, so no directory grows past a few thousand
+/// entries.
+pub fn new_storage_key(kind: &str, media_type: MediaType) -> String {
+    let id = Uuid::new_v4().simple().to_string();
+    format!("{}/{}/{}/{}.{}", kind, &id[..2], &id[2..4], id, media_type.extension())
+}
+
+/// Whether `key` has the shape produced by [`new_storage_key`] for `kind`. Keys sent
+/// back by clients are checked with this before they reach the database.
+pub fn is_storage_key(kind: &str, key: &str) -> bool {
+    let Some(rest) = key.strip_prefix(kind).and_then(|rest| rest.strip_prefix('/')) else {
+        return false;
+    };
+    let parts: Vec<&str> = rest.split('/').collect();
+    let [first, second, name] = parts.as_slice() else {
+        return false;
+    };
+    let (id, extension) = match name.split_once('.') {
+        Some((id, extension)) => (id, Some(extension)),
+        None => (*name, None),
+    };
+
+    let is_hex = |value: &str| value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'));
+    id.len() == 32
+        && is_hex(id)
+        && *first == &id[..2]
+        && *second == &id[2..4]
+        && extension.is_none_or(|extension| {
+            !extension.is_empty()
+                && extension.len() <= MAX_EXTENSION_LEN
+                && extension.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+fn temp_path(tmp_dir: &Path) -> PathBuf {
+    tmp_dir.join(format!("upload-{}", Uuid::new_v4().simple()))
+}
+
+// Arquivo temporário apagado no drop, inclusive quando o upload é recusado no meio
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // O backend local já moveu o arquivo; nos outros casos o temporário ainda existe
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// An upload received into a temporary file, with the key it will be stored under.
+/// The temporary file is removed when this is dropped, so an upload that is never
+/// stored leaves nothing behind.
+pub struct StagedFile {
+    pub storage_key: String,
+    pub filename: String,
+    /// Hex SHA-256 of the contents, as stored.
+    pub sha256: String,
+    /// Size as stored, after any metadata was removed.
+    pub size: u64,
+    /// Read from images before their metadata was removed.
+    pub metadata: ImageMetadata,
+    /// Read from the container of MP4 and QuickTime videos.
+    pub video: Option<VideoInfo>,
+    temp: TempFile,
+}
+
+impl StagedFile {
+    /// Hands the file to the backend under `storage_key`.
+    pub async fn store(&self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        storage.put(&self.storage_key, &self.temp.0).await
+    }
+}
+
+/// Largest upload accepted for files of `class`.
+pub fn max_bytes(config: &StorageConfig, class: MediaClass) -> u64 {
+    match class {
+        MediaClass::Image => config.max_image_bytes,
+        MediaClass::Video => config.max_video_bytes,
+        MediaClass::Document => config.max_document_bytes,
+    }
+}
+
+// Decide o tipo pelos primeiros bytes e devolve também o limite de tamanho dele
+fn accept_type(config: &StorageConfig, kind: &str, head: &[u8]) -> Result<(MediaType, u64), StorageError> {
+    let allowed = allowed_types(kind);
+    match sniff(head) {
+        Some(media_type) if allowed.contains(&media_type) => Ok((media_type, max_bytes(config, media_type.class()))),
+        _ => Err(StorageError::UnsupportedType(
+            allowed.iter().map(|media_type| media_type.mime()).collect::<Vec<_>>().join(", "),
+        )),
+    }
+}
+
+/// Streams a multipart field to a temporary file. The type is sniffed from the first
+/// bytes and checked against what `kind` accepts, and the size limit for that type is
+/// enforced while the rest arrives. Images then have their metadata removed and MP4 and
+/// QuickTime videos have their container read.
+pub async fn stage_field(config: &StorageConfig, kind: &str, field: &mut Field) -> Result<StagedFile, StorageError> {
+    let filename = original_filename(field.content_disposition().get_filename().unwrap_or_default());
+
+    tokio::fs::create_dir_all(&config.tmp_dir).await?;
+    let temp = TempFile(temp_path(&config.tmp_dir));
+    let file = tokio::fs::OpenOptions::new().write(true).create_new(true).open(&temp.0).await?;
+    let mut file = tokio::io::BufWriter::new(file);
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let mut accepted = None;
+    let mut size = 0u64;
+    let mut hasher = Sha256::new();
+
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|error| StorageError::Multipart(error.to_string()))?;
+        size += data.len() as u64;
+        hasher.update(&data);
+
+        match accepted {
+            Some((_, limit)) => {
+                if size > limit {
+                    return Err(StorageError::TooLarge(limit));
+                }
+                file.write_all(&data).await?;
+            }
+            None => {
+                head.extend_from_slice(&data);
+                if head.len() >= SNIFF_LEN {
+                    let (media_type, limit) = accept_type(config, kind, &head)?;
+                    if size > limit {
+                        return Err(StorageError::TooLarge(limit));
+                    }
+                    file.write_all(&head).await?;
+                    accepted = Some((media_type, limit));
+                }
+            }
+        }
+    }
+
+    // Arquivos menores que SNIFF_LEN só são examinados aqui
+    let media_type = match accepted {
+        Some((media_type, _)) => media_type,
+        None => {
+            let (media_type, limit) = accept_type(config, kind, &head)?;
+            if size > limit {
+                return Err(StorageError::TooLarge(limit));
+            }
+            file.write_all(&head).await?;
+            media_type
+        }
+    };
+    file.flush().await?;
+
+    let (sha256, metadata) = if media_type.class() == MediaClass::Image {
+        scrub_image(&temp.0, media_type).await?
+    } else {
+        (hex::encode(hasher.finalize()), ImageMetadata::default())
+    };
+    let video = probe_video(&temp.0, media_type).await?;
+    let size = tokio::fs::metadata(&temp.0).await?.len();
+
+    Ok(StagedFile {
+        storage_key: new_storage_key(kind, media_type),
+        filename,
+        sha256,
+        size,
+        metadata,
+        video,
+        temp,
+    })
+}
+
+// Tira localização e demais metadados da imagem antes de ela chegar ao storage; o hash
+// é do arquivo já limpo, que é o que fica guardado
+async fn scrub_image(path: &Path, media_type: MediaType) -> Result<(String, ImageMetadata), StorageError> {
+    let contents = tokio::fs::read(path).await?;
+    let (contents, metadata) = strip_metadata(media_type, &contents)?;
+    tokio::fs::write(path, &contents).await?;
+    Ok((hex::encode(Sha256::digest(&contents)), metadata))
+}
+
+// Lê o contêiner de MP4 e MOV; arquivos truncados ou que não são contêineres válidos
+// são recusados aqui, antes de virar registro
+async fn probe_video(path: &Path, media_type: MediaType) -> Result<Option<VideoInfo>, StorageError> {
+    if !matches!(media_type, MediaType::Mp4 | MediaType::Mov) {
+        return Ok(None);
+    }
+    let path = path.to_path_buf();
+    let probed = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)?;
+        let size = file.metadata()?.len();
+        probe_mp4(&mut std::io::BufReader::new(file), size)
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))?;
+
+    match probed {
+        Ok(info) => Ok(Some(info)),
+        Err(Mp4Error::Io(error)) => Err(StorageError::Io(error)),
+        Err(error) => Err(StorageError::Video(error.to_string())),
+    }
+}
+
+// SHA-256 em hexadecimal de tudo o que o stream produzir
+async fn hash_stream(mut stream: impl Stream<Item = io::Result<Bytes>> + Unpin) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Reads an object back from storage and returns the hex SHA-256 of its contents.
+pub async fn hash_object(storage: &dyn StorageBackend, key: &str) -> Result<String, StorageError> {
+    let body = storage.get_range(key, None).await?;
+    Ok(hash_stream(body.stream).await?)
+}
+
+/// Reads a whole object into memory, refusing objects over `limit` bytes.
+pub async fn read_object(storage: &dyn StorageBackend, key: &str, limit: u64) -> Result<Vec<u8>, StorageError> {
+    let mut body = storage.get_range(key, None).await?;
+    if body.length > limit {
+        return Err(StorageError::TooLarge(limit));
+    }
+    let mut contents = Vec::with_capacity(body.length as usize);
+    while let Some(chunk) = body.stream.next().await {
+        contents.extend_from_slice(&chunk?);
+    }
+    Ok(contents)
+}
+
+/// Stores contents produced by the server itself (not an upload) under `key`.
+pub async fn put_bytes(
+    storage: &dyn StorageBackend,
+    config: &StorageConfig,
+    key: &str,
+    contents: &[u8],
+) -> Result<(), StorageError> {
+    tokio::fs::create_dir_all(&config.tmp_dir).await?;
+    let temp = TempFile(temp_path(&config.tmp_dir));
+    tokio::fs::write(&temp.0, contents).await?;
+    storage.put(key, &temp.0).await
+}
+
+/// Takes over a file that was received in full by other means (a resumable upload),
+/// with the same type and size checks and metadata removal as [`stage_field`]. A refused file is removed.
+pub async fn stage_file(config: &StorageConfig, kind: &str, path: PathBuf, filename: &str) -> Result<StagedFile, StorageError> {
+    let temp = TempFile(path);
+    let mut file = tokio::fs::File::open(&temp.0).await?;
+    let size = file.metadata().await?.len();
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    (&mut file).take(SNIFF_LEN as u64).read_to_end(&mut head).await?;
+    let (media_type, limit) = accept_type(config, kind, &head)?;
+    if size > limit {
+        return Err(StorageError::TooLarge(limit));
+    }
+
+    let (sha256, metadata) = if media_type.class() == MediaClass::Image {
+        drop(file);
+        scrub_image(&temp.0, media_type).await?
+    } else {
+        file.rewind().await?;
+        (hash_stream(ReaderStream::new(file)).await?, ImageMetadata::default())
+    };
+    let video = probe_video(&temp.0, media_type).await?;
+    let size = tokio::fs::metadata(&temp.0).await?.len();
+
+    Ok(StagedFile {
+        storage_key: new_storage_key(kind, media_type),
+        filename: original_filename(filename),
+        sha256,
+        size,
+        metadata,
+        video,
+        temp,
+    })
+}
+
+/// Stores every file in a multipart form under new keys. All files are received and
+/// checked, against their type and the installation quota, before the first one is
+/// stored, so a refused file leaves none behind. Documents are encrypted first, like
+/// those sent to `/documents`. Once stored, the files are recorded as standalone uploads,
+/// with their envelopes, and the installation quota is checked again under its lock; if
+/// that fails, they are removed again.
+pub async fn save_fields(
+    storage: &dyn StorageBackend,
+    db: &PgPool,
+    config: &StorageConfig,
+    kind: &str,
+    mut payload: Multipart,
+) -> Result<Vec<StoredFile>, StorageError> {
+    let mut staged = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|error| StorageError::Multipart(error.to_string()))?;
+        staged.push(stage_field(config, kind, &mut field).await?);
+    }
+    // Envios avulsos não são de um aluno: só a cota da instalação vale
+    check_quota(db, config, None, staged.iter().map(|file| file.size).sum()).await?;
+
+    // Só o arquivo cifrado chega ao storage; sha256 e bytes seguem os do conteúdo em claro
+    let mut envelopes = Vec::new();
+    for file in &staged {
+        envelopes.push(if kind == DOCUMENTS { file.encrypt(&config.document_keys).await? } else { None });
+    }
+
+    let mut files: Vec<StoredFile> = Vec::new();
+    for file in &staged {
+        if let Err(error) = file.store(storage).await {
+            // Desfaz os que já foram gravados
+            for stored in &files {
+                let _ = storage.delete(&stored.storage_key).await;
+            }
+            return Err(error);
+        }
+        files.push(StoredFile {
+            storage_key: file.storage_key.clone(),
+            filename: file.filename.clone(),
+            sha256: file.sha256.clone(),
+        });
+    }
+
+    // Como em commit_within_quota, o lock só é pego depois de gravar
+    let keys: Vec<String> = staged.iter().map(|file| file.storage_key.clone()).collect();
+    let sizes: Vec<i64> = staged.iter().map(|file| file.size as i64).collect();
+    let key_ids: Vec<Option<&str>> = envelopes.iter().map(|envelope| envelope.as_ref().map(|envelope| envelope.key_id.as_str())).collect();
+    let wrapped_keys: Vec<Option<&[u8]>> =
+        envelopes.iter().map(|envelope| envelope.as_ref().map(|envelope| envelope.wrapped_key.as_slice())).collect();
+    let committed = match db.begin().await.map_err(StorageError::Database) {
+        Ok(mut tx) => {
+            let recorded = sqlx::query(
+                "INSERT INTO standalone_uploads (storage_key, bytes, encryption_key_id, encrypted_data_key)
+                 SELECT * FROM UNNEST($1::text[], $2::bigint[], $3::text[], $4::bytea[])",
+            )
+            .bind(&keys)
+            .bind(&sizes)
+            .bind(&key_ids)
+            .bind(&wrapped_keys)
+            .execute(&mut tx)
+            .await
+            .map_err(StorageError::Database);
+            match recorded {
+                Ok(_) => match quota::check_total_locked(&mut tx, config).await {
+                    Ok(()) => tx.commit().await.map_err(StorageError::Database),
+                    Err(error) => Err(error),
+                },
+                Err(error) => Err(error),
+            }
+        }
+        Err(error) => Err(error),
+    };
+    if let Err(error) = committed {
+        for key in &keys {
+            if let Err(delete_error) = storage.delete(key).await {
+                eprintln!("Failed to remove {} after rollback: {}", key, delete_error);
+            }
+        }
+        return Err(error);
+    }
+    Ok(files)
+}
+
+/// Text fields of a multipart form plus the one file it carries, already staged.
+pub struct UploadForm {
+    fields: HashMap<String, String>,
+    pub file: Option<StagedFile>,
+}
+
+impl UploadForm {
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+/// Reads a form whose file arrives in `file_field`. Every other field is read as text,
+/// up to [`MAX_TEXT_FIELD_BYTES`]; a second file is refused. When `student_id` comes
+/// before the file and the request declared its `content_length`, the student's quota is
+/// checked before the file is received.
+pub async fn read_upload_form(
+    mut payload: Multipart,
+    db: &PgPool,
+    config: &StorageConfig,
+    kind: &str,
+    file_field: &str,
+    content_length: Option<u64>,
+) -> Result<UploadForm, StorageError> {
+    let mut form = UploadForm {
+        fields: HashMap::new(),
+        file: None,
+    };
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|error| StorageError::Multipart(error.to_string()))?;
+        let name = field.name().to_string();
+
+        if name == file_field {
+            if form.file.is_some() {
+                return Err(StorageError::Multipart(format!("only one '{}' field is accepted", file_field)));
+            }
+            let student_id = form.text("student_id").and_then(|id| Uuid::parse_str(id.trim()).ok());
+            if let (Some(student_id), Some(length)) = (student_id, content_length) {
+                check_quota(db, config, Some(student_id), length).await?;
+            }
+            form.file = Some(stage_field(config, kind, &mut field).await?);
+            continue;
+        }
+
+        let mut value = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let data = chunk.map_err(|error| StorageError::Multipart(error.to_string()))?;
+            if value.len() + data.len() > MAX_TEXT_FIELD_BYTES {
+                return Err(StorageError::Multipart(format!("field '{}' is too long", name)));
+            }
+            value.extend_from_slice(&data);
+        }
+        let value = String::from_utf8(value)
+            .map_err(|_| StorageError::Multipart(format!("field '{}' is not valid UTF-8", name)))?;
+        form.fields.insert(name, value);
+    }
+
+    Ok(form)
+}
+
+/// Stores `file` and then commits `tx`, which must already hold the student's record
+/// pointing at it, if with that record counted the student and the installation are still
+/// within their quotas. The quota locks are taken after the file is stored, so they are
+/// held only until the commit. On any failure the transaction is rolled back and the
+/// stored object is removed again.
+pub async fn commit_within_quota(
+    mut tx: Transaction<'_, Postgres>,
+    file: &StagedFile,
+    storage: &dyn StorageBackend,
+    config: &StorageConfig,
+    student_id: Uuid,
+) -> Result<(), StorageError> {
+    file.store(storage).await?;
+
+    // Sem commit, o drop da transação desfaz o INSERT
+    let committed = match quota::check_quota_locked(&mut tx, config, student_id).await {
+        Ok(()) => tx.commit().await.map_err(StorageError::Database),
+        Err(error) => Err(error),
+    };
+    if let Err(error) = committed {
+        if let Err(delete_error) = storage.delete(&file.storage_key).await {
+            eprintln!("Failed to remove {} after rollback: {}", file.storage_key, delete_error);
+        }
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Whether `key` was issued for `kind` and its object is in storage. Checked before a
+/// key sent back by a client is attached to a record.
+pub async fn is_uploaded(storage: &dyn StorageBackend, kind: &str, key: &str) -> bool {
+    is_storage_key(kind, key) && matches!(storage.stat(key).await, Ok(Some(_)))
+}
+
+/// Whether an insert failed because the key already belongs to another record.
+pub fn is_key_taken(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(error) => error.code().as_deref() == Some("23505"),
+        _ => false,
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use chrono::{DateTime, Utc};
+
+// Segundos entre 1904-01-01 (época do MP4/QuickTime) e 1970-01-01
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+// O moov é lido inteiro para a memória; nenhum vídeo de verdade chega perto disso
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Container metadata of an MP4 or QuickTime file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VideoInfo {
+    pub duration_seconds: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    /// Sample entry of the first video track, e.g. `avc1` or `hvc1`.
+    pub video_codec: Option<String>,
+    /// Sample entry of the first audio track, e.g. `mp4a`.
+    pub audio_codec: Option<String>,
+    /// Average over the whole file, in bits per second.
+    pub bitrate: Option<u64>,
+    /// When the recording was made, as written by the camera.
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum Mp4Error {
+    /// A box runs past the end of the file.
+    Truncated,
+    Invalid(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for Mp4Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mp4Error::Truncated => write!(f, "the file is truncated"),
+            Mp4Error::Invalid(reason) => write!(f, "{}", reason),
+            Mp4Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for Mp4Error {
+    fn from(error: io::Error) -> Self {
+        Mp4Error::Io(error)
+    }
+}
+
+// Leitura big-endian sobre fatias, sem pânico em dados curtos
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+// Tipo de um box e o conteúdo depois do cabeçalho
+type Mp4Box<'a> = ([u8; 4], &'a [u8]);
+
+/// The boxes directly inside `data`. A box that claims more bytes than its parent holds
+/// makes the file invalid.
+fn boxes(data: &[u8]) -> Result<Vec<Mp4Box<'_>>, Mp4Error> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let size = be_u32(data, pos).ok_or(Mp4Error::Truncated)? as u64;
+        let kind: [u8; 4] = data.get(pos + 4..pos + 8).ok_or(Mp4Error::Truncated)?.try_into().unwrap();
+        let (size, header) = match size {
+            0 => ((data.len() - pos) as u64, 8),
+            1 => (be_u64(data, pos + 8).ok_or(Mp4Error::Truncated)?, 16),
+            size => (size, 8),
+        };
+        if size < header as u64 {
+            return Err(Mp4Error::Invalid("invalid box size"));
+        }
+        let end = pos as u64 + size;
+        if end > data.len() as u64 {
+            return Err(Mp4Error::Truncated);
+        }
+        found.push((kind, &data[pos + header..end as usize]));
+        pos = end as usize;
+    }
+    Ok(found)
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<Option<&'a [u8]>, Mp4Error> {
+    Ok(boxes(data)?.into_iter().find(|(found, _)| found == kind).map(|(_, body)| body))
+}
+
+// mvhd e mdhd têm o mesmo começo: versão, datas, escala de tempo e duração
+struct MediaHeader {
+    created: u64,
+    timescale: u32,
+    duration: u64,
+}
+
+fn media_header(body: &[u8]) -> Option<MediaHeader> {
+    match body.first()? {
+        0 => Some(MediaHeader {
+            created: be_u32(body, 4)? as u64,
+            timescale: be_u32(body, 12)?,
+            duration: be_u32(body, 16)? as u64,
+        }),
+        1 => Some(MediaHeader {
+            created: be_u64(body, 4)?,
+            timescale: be_u32(body, 20)?,
+            duration: be_u64(body, 24)?,
+        }),
+        _ => None,
+    }
+}
+
+fn seconds(duration: u64, timescale: u32) -> Option<f64> {
+    (timescale > 0 && duration > 0).then(|| duration as f64 / timescale as f64)
+}
+
+fn fourcc(kind: &[u8]) -> String {
+    String::from_utf8_lossy(kind).trim_end().to_string()
+}
+
+struct Track<'a> {
+    handler: [u8; 4],
+    header: Option<MediaHeader>,
+    sample_entry: Option<Mp4Box<'a>>,
+    sample_count: u64,
+}
+
+fn track(trak: &[u8]) -> Result<Option<Track<'_>>, Mp4Error> {
+    let Some(mdia) = child(trak, b"mdia")? else {
+        return Ok(None);
+    };
+    let Some(handler) = child(mdia, b"hdlr")?.and_then(|hdlr| hdlr.get(8..12)) else {
+        return Ok(None);
+    };
+    let header = child(mdia, b"mdhd")?.and_then(media_header);
+
+    let stbl = match child(mdia, b"minf")? {
+        Some(minf) => child(minf, b"stbl")?,
+        None => None,
+    };
+    let (mut sample_entry, mut sample_count) = (None, 0);
+    if let Some(stbl) = stbl {
+        // stsd: cabeçalho de full box + contagem, depois as sample entries
+        if let Some(stsd) = child(stbl, b"stsd")? {
+            sample_entry = boxes(stsd.get(8..).ok_or(Mp4Error::Truncated)?)?.into_iter().next();
+        }
+        if let Some(stts) = child(stbl, b"stts")? {
+            let entries = be_u32(stts, 4).ok_or(Mp4Error::Truncated)? as usize;
+            for index in 0..entries {
+                sample_count += be_u32(stts, 8 + index * 8).ok_or(Mp4Error::Truncated)? as u64;
+            }
+        }
+    }
+
+    Ok(Some(Track {
+        handler: handler.try_into().unwrap(),
+        header,
+        sample_entry,
+        sample_count,
+    }))
+}
+
+fn read_moov(moov: &[u8], info: &mut VideoInfo) -> Result<(), Mp4Error> {
+    let header = child(moov, b"mvhd")?
+        .and_then(media_header)
+        .ok_or(Mp4Error::Invalid("the movie header is missing"))?;
+    info.duration_seconds = seconds(header.duration, header.timescale);
+    info.recorded_at = (header.created > 0)
+        .then(|| DateTime::from_timestamp(header.created as i64 - MP4_EPOCH_OFFSET, 0))
+        .flatten();
+
+    let mut has_video = false;
+    for (kind, trak) in boxes(moov)? {
+        if &kind != b"trak" {
+            continue;
+        }
+        let Some(track) = track(trak)? else {
+            continue;
+        };
+        match &track.handler {
+            b"vide" if !has_video => {
+                has_video = true;
+                if let Some((codec, entry)) = track.sample_entry {
+                    info.video_codec = Some(fourcc(&codec));
+                    // VisualSampleEntry: largura e altura depois de 24 bytes fixos
+                    info.width = be_u16(entry, 24).map(u32::from).filter(|&width| width > 0);
+                    info.height = be_u16(entry, 26).map(u32::from).filter(|&height| height > 0);
+                }
+                info.frame_rate = track
+                    .header
+                    .and_then(|header| seconds(header.duration, header.timescale))
+                    .filter(|_| track.sample_count > 0)
+                    .map(|duration| track.sample_count as f64 / duration);
+            }
+            b"soun" if info.audio_codec.is_none() => {
+                info.audio_codec = track.sample_entry.map(|(codec, _)| fourcc(&codec));
+            }
+            _ => {}
+        }
+    }
+
+    if !has_video {
+        return Err(Mp4Error::Invalid("the file has no video track"));
+    }
+    Ok(())
+}
+
+/// Walks the top-level boxes of an MP4/QuickTime file of `size` bytes and reads its
+/// movie header and tracks. Only the `moov` box is loaded into memory; the media data is
+/// skipped. Files with a box running past the end, or without a video track, are
+/// refused.
+pub fn probe_mp4<R: Read + Seek>(reader: &mut R, size: u64) -> Result<VideoInfo, Mp4Error> {
+    let mut info = VideoInfo::default();
+    let mut moov_found = false;
+    let mut pos = 0u64;
+
+    while pos < size {
+        if size - pos < 8 {
+            return Err(Mp4Error::Truncated);
+        }
+        reader.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        reader.read_exact(&mut header[..8])?;
+        let (box_size, header_len) = match be_u32(&header, 0).unwrap() {
+            // Tamanho 0: o box vai até o fim do arquivo
+            0 => (size - pos, 8),
+            1 => {
+                if size - pos < 16 {
+                    return Err(Mp4Error::Truncated);
+                }
+                reader.read_exact(&mut header[8..16])?;
+                (be_u64(&header, 8).unwrap(), 16)
+            }
+            box_size => (box_size as u64, 8),
+        };
+        if box_size < header_len {
+            return Err(Mp4Error::Invalid("invalid box size"));
+        }
+        if box_size > size - pos {
+            return Err(Mp4Error::Truncated);
+        }
+
+        if &header[4..8] == b"moov" {
+            if moov_found {
+                return Err(Mp4Error::Invalid("the file has more than one movie box"));
+            }
+            let body_len = box_size - header_len;
+            if body_len > MAX_MOOV_BYTES {
+                return Err(Mp4Error::Invalid("the movie box is too large"));
+            }
+            let mut moov = vec![0u8; body_len as usize];
+            reader.read_exact(&mut moov)?;
+            read_moov(&moov, &mut info)?;
+            moov_found = true;
+        }
+        pos += box_size;
+    }
+
+    if !moov_found {
+        return Err(Mp4Error::Invalid("the movie box is missing"));
+    }
+    info.bitrate = info
+        .duration_seconds
+        .map(|duration| (size as f64 * 8.0 / duration).round() as u64);
+    Ok(info)
+}
@@ -0,0 +1,206 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use actix_web::{http::header::CONTENT_LENGTH, HttpRequest};
+use serde::Serialize;
+use sqlx::{Executor, PgConnection, PgPool, Postgres};
+use uuid::Uuid;
+
+use super::StorageError;
+use crate::config::StorageConfig;
+
+// Arquivos por aluno e tipo; $1 restringe a um aluno. Só conta o que está no storage: os
+// registros antigos, sem storage_key, ficam de fora. Envios avulsos contam até um registro
+// assumir a chave, quando passam a contar na tabela dele
+const USAGE_QUERY: &str = r#"
+    SELECT student_id, 'photos' AS media_type, COUNT(*) AS files, COALESCE(SUM(bytes), 0)::BIGINT AS bytes
+    FROM photos WHERE storage_key IS NOT NULL AND ($1::uuid IS NULL OR student_id = $1) GROUP BY student_id
+    UNION ALL
+    SELECT p.student_id, 'photo_variants', COUNT(*), COALESCE(SUM(v.bytes), 0)::BIGINT
+    FROM photo_variants v JOIN photos p ON p.id = v.photo_id WHERE $1::uuid IS NULL OR p.student_id = $1 GROUP BY p.student_id
+    UNION ALL
+    SELECT student_id, 'videos', COUNT(*), COALESCE(SUM(bytes), 0)::BIGINT
+    FROM videos WHERE storage_key IS NOT NULL AND ($1::uuid IS NULL OR student_id = $1) GROUP BY student_id
+    UNION ALL
+    SELECT student_id, 'documents', COUNT(*), COALESCE(SUM(bytes), 0)::BIGINT
+    FROM documents WHERE storage_key IS NOT NULL AND ($1::uuid IS NULL OR student_id = $1) GROUP BY student_id
+    UNION ALL
+    SELECT NULL, 'files', COUNT(*), COALESCE(SUM(bytes), 0)::BIGINT
+    FROM file_metadata WHERE storage_key IS NOT NULL AND $1::uuid IS NULL HAVING COUNT(*) > 0
+    UNION ALL
+    SELECT NULL, 'uploads', COUNT(*), COALESCE(SUM(bytes), 0)::BIGINT
+    FROM standalone_uploads u WHERE $1::uuid IS NULL
+        AND NOT EXISTS (SELECT 1 FROM photos WHERE storage_key = u.storage_key)
+        AND NOT EXISTS (SELECT 1 FROM videos WHERE storage_key = u.storage_key)
+        AND NOT EXISTS (SELECT 1 FROM documents WHERE storage_key = u.storage_key)
+        AND NOT EXISTS (SELECT 1 FROM file_metadata WHERE storage_key = u.storage_key)
+    HAVING COUNT(*) > 0
+"#;
+
+// Variantes são geradas depois do commit e refeitas quando somem: aparecem no relatório,
+// mas não contam em nenhuma cota
+const VARIANTS: &str = "photo_variants";
+
+/// Files of one media type and the bytes they take.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MediaUsage {
+    pub files: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StudentUsage {
+    pub student_id: Uuid,
+    pub name: Option<String>,
+    pub bytes: i64,
+    /// `None` when students have no quota.
+    pub quota_bytes: Option<u64>,
+    pub by_type: BTreeMap<String, MediaUsage>,
+}
+
+/// Storage used by the installation, by media type and by student. Files not tied to a
+/// student (`files`, and `uploads` no record points at yet) only count towards the total.
+/// Photo variants are listed in `by_type` but left out of every `bytes`, which is what
+/// the quotas are checked against.
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub bytes: i64,
+    pub quota_bytes: Option<u64>,
+    pub by_type: BTreeMap<String, MediaUsage>,
+    pub students: Vec<StudentUsage>,
+}
+
+fn quota(bytes: u64) -> Option<u64> {
+    (bytes > 0).then_some(bytes)
+}
+
+// Chave do lock de transação que enfileira as conferências da cota da instalação
+const TOTAL_QUOTA_LOCK: i64 = 0x0051_554f_5441;
+
+async fn usage_rows<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    student_id: Option<Uuid>,
+) -> Result<Vec<(Option<Uuid>, String, i64, i64)>, sqlx::Error> {
+    sqlx::query_as(USAGE_QUERY).bind(student_id).fetch_all(db).await
+}
+
+async fn used_bytes<'e>(db: impl Executor<'e, Database = Postgres>, student_id: Option<Uuid>) -> Result<u64, sqlx::Error> {
+    let rows = usage_rows(db, student_id).await?;
+    let bytes = rows.iter().filter(|(_, media_type, _, _)| media_type != VARIANTS).map(|(_, _, _, bytes)| *bytes);
+    Ok(bytes.sum::<i64>().max(0) as u64)
+}
+
+/// Builds the usage report, students with the most bytes first.
+pub async fn usage_report(db: &PgPool, config: &StorageConfig) -> Result<UsageReport, sqlx::Error> {
+    let rows = usage_rows(db, None).await?;
+    let names: BTreeMap<Uuid, String> = sqlx::query_as::<_, (Uuid, String)>("SELECT id, name FROM students")
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut report = UsageReport {
+        bytes: 0,
+        quota_bytes: quota(config.total_quota_bytes),
+        by_type: BTreeMap::new(),
+        students: Vec::new(),
+    };
+    let mut students: BTreeMap<Uuid, StudentUsage> = BTreeMap::new();
+    for (student_id, media_type, files, bytes) in rows {
+        let quota_bytes = if media_type == VARIANTS { 0 } else { bytes };
+        report.bytes += quota_bytes;
+        let total = report.by_type.entry(media_type.clone()).or_default();
+        total.files += files;
+        total.bytes += bytes;
+
+        let Some(student_id) = student_id else {
+            continue;
+        };
+        let student = students.entry(student_id).or_insert_with(|| StudentUsage {
+            student_id,
+            name: names.get(&student_id).cloned(),
+            bytes: 0,
+            quota_bytes: quota(config.student_quota_bytes),
+            by_type: BTreeMap::new(),
+        });
+        student.bytes += quota_bytes;
+        student.by_type.insert(media_type, MediaUsage { files, bytes });
+    }
+
+    report.students = students.into_values().collect();
+    report.students.sort_by_key(|student| Reverse(student.bytes));
+    Ok(report)
+}
+
+/// Declared size of the request body, when the client sent one.
+pub fn content_length(req: &HttpRequest) -> Option<u64> {
+    req.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Refuses `incoming` more bytes when they would take the installation over
+/// `STORAGE_QUOTA_TOTAL_MB`, and, for a student's upload, the student over
+/// `STORAGE_QUOTA_STUDENT_MB`. Called with the `Content-Length` before the body is read,
+/// to refuse early, and again with the actual size; for a student's upload the check that
+/// counts is [`check_quota_locked`], at commit.
+pub async fn check_quota(
+    db: &PgPool,
+    config: &StorageConfig,
+    student_id: Option<Uuid>,
+    incoming: u64,
+) -> Result<(), StorageError> {
+    if let Some(limit) = quota(config.total_quota_bytes) {
+        let used = used_bytes(db, None).await.map_err(StorageError::Database)?;
+        if used.saturating_add(incoming) > limit {
+            return Err(StorageError::StorageFull(limit));
+        }
+    }
+    if let (Some(limit), Some(student_id)) = (quota(config.student_quota_bytes), student_id) {
+        let used = used_bytes(db, Some(student_id)).await.map_err(StorageError::Database)?;
+        if used.saturating_add(incoming) > limit {
+            return Err(StorageError::OverQuota(limit));
+        }
+    }
+    Ok(())
+}
+
+/// Final check for a student's upload, run in the transaction that inserted its record,
+/// so the usage summed here already includes it. Concurrent uploads for the same student
+/// wait on the student's row, and, with an installation quota, on a transaction lock,
+/// until this transaction ends; the second one then sees the first one's bytes.
+pub async fn check_quota_locked(
+    conn: &mut PgConnection,
+    config: &StorageConfig,
+    student_id: Uuid,
+) -> Result<(), StorageError> {
+    // NO KEY UPDATE e não FOR UPDATE: o INSERT da mídia já segura um KEY SHARE na linha do
+    // aluno (chave estrangeira), e FOR UPDATE esperaria por ele, travando dois envios um no outro
+    if let Some(limit) = quota(config.student_quota_bytes) {
+        sqlx::query("SELECT id FROM students WHERE id = $1 FOR NO KEY UPDATE")
+            .bind(student_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(StorageError::Database)?;
+        if used_bytes(&mut *conn, Some(student_id)).await.map_err(StorageError::Database)? > limit {
+            return Err(StorageError::OverQuota(limit));
+        }
+    }
+    check_total_locked(conn, config).await
+}
+
+/// Final check for files stored through `/upload`, run in the transaction that recorded
+/// them as standalone uploads: with an installation quota, checks the total under the
+/// same transaction lock as [`check_quota_locked`], so concurrent uploads see each other's
+/// bytes.
+pub async fn check_total_locked(conn: &mut PgConnection, config: &StorageConfig) -> Result<(), StorageError> {
+    if let Some(limit) = quota(config.total_quota_bytes) {
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(TOTAL_QUOTA_LOCK)
+            .execute(&mut *conn)
+            .await
+            .map_err(StorageError::Database)?;
+        if used_bytes(&mut *conn, None).await.map_err(StorageError::Database)? > limit {
+            return Err(StorageError::StorageFull(limit));
+        }
+    }
+    Ok(())
+}
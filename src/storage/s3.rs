@@ -0,0 +1,390 @@
+use std::ops::Range;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, LAST_MODIFIED, RANGE},
+    Client, Method, Response, StatusCode, Url,
+};
+use sha2::{Digest, Sha256};
+use tokio_util::io::ReaderStream;
+
+use super::{ObjectBody, ObjectInfo, StorageBackend, StorageError};
+use crate::config::StorageConfig;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const EMPTY_PAYLOAD_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+// Corpo do PUT vai em streaming, sem hash; a assinatura cobre o resto da requisição
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// AWS Signature Version 4 for the `s3` service, enough for the handful of object
+/// operations the backend uses.
+pub struct SigV4 {
+    access_key: String,
+    secret_key: String,
+    region: String,
+}
+
+impl SigV4 {
+    pub fn new(access_key: &str, secret_key: &str, region: &str) -> SigV4 {
+        SigV4 {
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            region: region.to_string(),
+        }
+    }
+
+    /// `Authorization` header for a request. `path` is the already encoded URI path and
+    /// `headers` every header to sign, which must include `host`, `x-amz-date` and
+    /// `x-amz-content-sha256`.
+    pub fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        payload_hash: &str,
+        amz_date: &str,
+    ) -> String {
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut headers: Vec<(String, String)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        let signature = hex::encode(hmac(&key, &string_to_sign));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        )
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encoding as SigV4 defines it: everything but unreserved characters, and `/`
+/// too unless it separates path segments.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            b'/' if !encode_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Objects in a bucket of any S3-compatible service (AWS, MinIO, ...).
+pub struct S3Storage {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    path_style: bool,
+    signer: SigV4,
+}
+
+impl S3Storage {
+    pub fn new(config: &StorageConfig) -> Result<S3Storage, StorageError> {
+        let endpoint = Url::parse(&config.s3_endpoint)
+            .map_err(|error| StorageError::Backend(format!("invalid S3_ENDPOINT: {}", error)))?;
+        Ok(S3Storage {
+            client: Client::new(),
+            endpoint,
+            bucket: config.s3_bucket.clone(),
+            path_style: config.s3_path_style,
+            signer: SigV4::new(&config.s3_access_key, &config.s3_secret_key, &config.s3_region),
+        })
+    }
+
+    // MinIO usa o bucket no caminho; a AWS prefere no nome do host
+    fn url(&self, key: &str) -> Result<(Url, String), StorageError> {
+        let mut url = self.endpoint.clone();
+        let path = if self.path_style && key.is_empty() {
+            format!("/{}", self.bucket)
+        } else if self.path_style {
+            format!("/{}/{}", self.bucket, uri_encode(key, false))
+        } else {
+            let host = format!("{}.{}", self.bucket, self.endpoint.host_str().unwrap_or_default());
+            url.set_host(Some(&host))
+                .map_err(|error| StorageError::Backend(format!("invalid bucket host: {}", error)))?;
+            format!("/{}", uri_encode(key, false))
+        };
+        url.set_path(&path);
+        Ok((url, path))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        mut headers: HeaderMap,
+        payload_hash: &str,
+        body: Option<reqwest::Body>,
+    ) -> Result<Response, StorageError> {
+        let (mut url, path) = self.url(key)?;
+        if !query.is_empty() {
+            // Mesma codificação usada na assinatura
+            let query = query
+                .iter()
+                .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+                .collect::<Vec<_>>()
+                .join("&");
+            url.set_query(Some(&query));
+        }
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.signer.authorization(
+            method.as_str(),
+            &path,
+            query,
+            &[("host", &host), ("x-amz-content-sha256", payload_hash), ("x-amz-date", &amz_date)],
+            payload_hash,
+            &amz_date,
+        );
+
+        for (name, value) in [
+            ("x-amz-content-sha256", payload_hash),
+            ("x-amz-date", &amz_date),
+            ("authorization", &authorization),
+        ] {
+            let value = HeaderValue::from_str(value)
+                .map_err(|error| StorageError::Backend(format!("invalid {} header: {}", name, error)))?;
+            headers.insert(name, value);
+        }
+
+        let mut request = self.client.request(method, url).headers(headers);
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        Ok(request.send().await?)
+    }
+
+    async fn error(key: &str, response: Response) -> StorageError {
+        if response.status() == StatusCode::NOT_FOUND {
+            return StorageError::NotFound(key.to_string());
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        StorageError::Backend(format!("{} for {}: {}", status, key, body.trim()))
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+fn last_modified(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let value = headers.get(LAST_MODIFIED)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Text of every `<tag>` element in `xml`, unescaped. The listing responses are flat
+/// enough that this is all the parsing they need.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|rest| rest.split_once(close.as_str()))
+        .map(|(value, _)| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+        .collect()
+}
+
+/// Objects in a `ListObjectsV2` response, and the token for the next page if any.
+pub fn parse_list_response(xml: &str) -> (Vec<ObjectInfo>, Option<String>) {
+    let objects = xml_values(xml, "Contents")
+        .iter()
+        .filter_map(|contents| {
+            let key = xml_values(contents, "Key").into_iter().next()?;
+            let size = xml_values(contents, "Size").first()?.parse().ok()?;
+            let last_modified = xml_values(contents, "LastModified")
+                .first()
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                .map(|date| date.with_timezone(&Utc));
+            Some(ObjectInfo { key, size, last_modified })
+        })
+        .collect();
+
+    let truncated = xml_values(xml, "IsTruncated").first().is_some_and(|value| value == "true");
+    let next = if truncated {
+        xml_values(xml, "NextContinuationToken").into_iter().next()
+    } else {
+        None
+    };
+    (objects, next)
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn put(&self, key: &str, file: &Path) -> Result<(), StorageError> {
+        let file = tokio::fs::File::open(file).await?;
+        let length = file.metadata().await?.len();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+        let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
+
+        let response = self
+            .send(Method::PUT, key, &[], headers, UNSIGNED_PAYLOAD, Some(body))
+            .await?;
+        if !response.status().is_success() {
+            return Err(S3Storage::error(key, response).await);
+        }
+        Ok(())
+    }
+
+    async fn get_range(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StorageError> {
+        let mut headers = HeaderMap::new();
+        if let Some(range) = &range {
+            if range.end <= range.start {
+                // Intervalo vazio: S3 não aceita "bytes=10-9", só precisamos do tamanho
+                let size = self.stat(key).await?.ok_or_else(|| StorageError::NotFound(key.to_string()))?.size;
+                return Ok(ObjectBody {
+                    size,
+                    length: 0,
+                    stream: futures_util::stream::empty().boxed(),
+                });
+            }
+            let value = format!("bytes={}-{}", range.start, range.end - 1);
+            headers.insert(RANGE, HeaderValue::from_str(&value).expect("range header is ASCII"));
+        }
+
+        let response = self
+            .send(Method::GET, key, &[], headers, EMPTY_PAYLOAD_SHA256, None)
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(S3Storage::error(key, response).await);
+        }
+
+        let length = header_u64(response.headers(), CONTENT_LENGTH).unwrap_or_default();
+        // Em 206 o tamanho total vem depois da barra em Content-Range: "bytes 0-9/1234"
+        let size = match status {
+            StatusCode::PARTIAL_CONTENT => response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit_once('/'))
+                .and_then(|(_, total)| total.parse().ok())
+                .unwrap_or(length),
+            _ => length,
+        };
+
+        let stream = response
+            .bytes_stream()
+            .map_err(std::io::Error::other)
+            .boxed();
+        Ok(ObjectBody { size, length, stream })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let response = self
+            .send(Method::DELETE, key, &[], HeaderMap::new(), EMPTY_PAYLOAD_SHA256, None)
+            .await?;
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(S3Storage::error(key, response).await);
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            // A listagem é sobre o bucket, não sobre uma chave
+            let response = self
+                .send(Method::GET, "", &query, HeaderMap::new(), EMPTY_PAYLOAD_SHA256, None)
+                .await?;
+            if !response.status().is_success() {
+                return Err(S3Storage::error(prefix, response).await);
+            }
+
+            let (page, next) = parse_list_response(&response.text().await?);
+            objects.extend(page);
+            match next {
+                Some(next) => token = Some(next),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError> {
+        let response = self
+            .send(Method::HEAD, key, &[], HeaderMap::new(), EMPTY_PAYLOAD_SHA256, None)
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(ObjectInfo {
+                key: key.to_string(),
+                size: header_u64(response.headers(), CONTENT_LENGTH).unwrap_or_default(),
+                last_modified: last_modified(response.headers()),
+            })),
+            _ => Err(S3Storage::error(key, response).await),
+        }
+    }
+}
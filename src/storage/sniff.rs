@@ -0,0 +1,102 @@
+/// Bytes read from the start of an upload before its type is decided.
+pub const SNIFF_LEN: usize = 64;
+
+/// File formats accepted for upload, recognised by their leading bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+    Jpeg,
+    Png,
+    Webp,
+    Mp4,
+    Mov,
+    Webm,
+    Pdf,
+}
+
+/// Broad family of a [`MediaType`]; each one has its own size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaClass {
+    Image,
+    Video,
+    Document,
+}
+
+impl MediaType {
+    pub fn mime(self) -> &'static str {
+        match self {
+            MediaType::Jpeg => "image/jpeg",
+            MediaType::Png => "image/png",
+            MediaType::Webp => "image/webp",
+            MediaType::Mp4 => "video/mp4",
+            MediaType::Mov => "video/quicktime",
+            MediaType::Webm => "video/webm",
+            MediaType::Pdf => "application/pdf",
+        }
+    }
+
+    /// Extension given to the storage key, so the key alone tells how to serve it.
+    pub fn extension(self) -> &'static str {
+        match self {
+            MediaType::Jpeg => "jpg",
+            MediaType::Png => "png",
+            MediaType::Webp => "webp",
+            MediaType::Mp4 => "mp4",
+            MediaType::Mov => "mov",
+            MediaType::Webm => "webm",
+            MediaType::Pdf => "pdf",
+        }
+    }
+
+    pub fn class(self) -> MediaClass {
+        match self {
+            MediaType::Jpeg | MediaType::Png | MediaType::Webp => MediaClass::Image,
+            MediaType::Mp4 | MediaType::Mov | MediaType::Webm => MediaClass::Video,
+            MediaType::Pdf => MediaClass::Document,
+        }
+    }
+}
+
+/// Recognises the format from the first bytes of a file (up to [`SNIFF_LEN`]). The name
+/// and `Content-Type` sent by the client are never consulted.
+pub fn sniff(head: &[u8]) -> Option<MediaType> {
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(MediaType::Jpeg);
+    }
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(MediaType::Png);
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some(MediaType::Webp);
+    }
+    if head.starts_with(b"%PDF-") {
+        return Some(MediaType::Pdf);
+    }
+    // ISO base media: caixa "ftyp" logo no início; a marca "qt  " é QuickTime
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(if &head[8..12] == b"qt  " { MediaType::Mov } else { MediaType::Mp4 });
+    }
+    // Matroska e WebM começam com o cabeçalho EBML; só o DocType distingue os dois
+    if head.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) && head.windows(4).any(|window| window == b"webm") {
+        return Some(MediaType::Webm);
+    }
+    None
+}
+
+/// Formats each kind of upload accepts.
+pub fn allowed_types(kind: &str) -> &'static [MediaType] {
+    match kind {
+        super::PHOTOS => &[MediaType::Jpeg, MediaType::Png, MediaType::Webp],
+        super::VIDEOS => &[MediaType::Mp4, MediaType::Mov, MediaType::Webm],
+        super::DOCUMENTS => &[MediaType::Pdf, MediaType::Jpeg, MediaType::Png, MediaType::Webp],
+        // file_metadata só registra fotos e vídeos
+        super::FILES => &[
+            MediaType::Jpeg,
+            MediaType::Png,
+            MediaType::Webp,
+            MediaType::Mp4,
+            MediaType::Mov,
+            MediaType::Webm,
+        ],
+        _ => &[],
+    }
+}
@@ -0,0 +1,42 @@
+use uuid::Uuid;
+
+use crate::auth::{
+    api_keys::{display_prefix, generate_api_key},
+    permissions::{Permission, Role},
+    AuthenticatedUser, Credential,
+};
+
+#[test]
+fn test_scopes_round_trip_through_permission_names() {
+    for permission in Permission::ALL {
+        assert_eq!(permission.as_str().parse::<Permission>(), Ok(permission));
+    }
+    assert!("media:delete".parse::<Permission>().is_err());
+}
+
+#[test]
+fn test_api_key_caller_is_limited_to_its_scopes() {
+    // Arrange
+    let caller = AuthenticatedUser {
+        id: Uuid::new_v4(),
+        role: Role::Integration,
+        credential: Credential::ApiKey { scopes: vec![Permission::MediaWrite] },
+    };
+
+    // Act / Assert
+    assert!(caller.can(Permission::MediaWrite));
+    assert!(!caller.can(Permission::MediaRead));
+    assert!(!caller.can(Permission::UsersManage));
+    assert_eq!(caller.session_id(), None);
+}
+
+#[test]
+fn test_generated_key_shape() {
+    // Act
+    let key = generate_api_key();
+
+    // Assert
+    assert!(key.starts_with("ak_"));
+    assert_eq!(key.len(), 3 + 64);
+    assert_eq!(display_prefix(&key), key[..11]);
+}
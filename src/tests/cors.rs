@@ -0,0 +1,58 @@
+use actix_web::{
+    http::{header::HeaderValue, Method},
+    test::TestRequest,
+};
+
+use crate::{config::CorsConfig, cors::rejection_reason};
+
+fn policy() -> CorsConfig {
+    CorsConfig {
+        allowed_origins: vec!["https://portal.example.com".to_string()],
+        allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+        allowed_headers: vec!["authorization".to_string(), "content-type".to_string()],
+        exposed_headers: vec![],
+        allow_credentials: false,
+        max_age_seconds: 60,
+    }
+}
+
+fn preflight(method: &str, headers: &str) -> TestRequest {
+    TestRequest::default()
+        .method(Method::OPTIONS)
+        .insert_header(("Access-Control-Request-Method", method))
+        .insert_header(("Access-Control-Request-Headers", headers))
+}
+
+#[test]
+fn test_allowed_preflight_has_no_rejection() {
+    // Arrange
+    let req = preflight("POST", "Authorization, Content-Type").to_srv_request();
+    let origin = HeaderValue::from_static("https://portal.example.com");
+
+    // Act / Assert
+    assert_eq!(rejection_reason(&policy(), &origin, req.head()), None);
+}
+
+#[test]
+fn test_rejects_unknown_origin_method_and_header() {
+    // Arrange
+    let origin = HeaderValue::from_static("https://portal.example.com");
+    let other_origin = HeaderValue::from_static("https://evil.example.com");
+    let get = TestRequest::default().to_srv_request();
+    let delete = preflight("DELETE", "").to_srv_request();
+    let custom_header = preflight("GET", "authorization, x-debug").to_srv_request();
+
+    // Act / Assert
+    assert_eq!(
+        rejection_reason(&policy(), &other_origin, get.head()),
+        Some("origin not allowed".to_string())
+    );
+    assert_eq!(
+        rejection_reason(&policy(), &origin, delete.head()),
+        Some("method DELETE not allowed".to_string())
+    );
+    assert_eq!(
+        rejection_reason(&policy(), &origin, custom_header.head()),
+        Some("headers not allowed: x-debug".to_string())
+    );
+}
@@ -0,0 +1,118 @@
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+use crate::config::MasterKey;
+use crate::storage::{
+    encrypt_to, hash_document, new_storage_key, open_document, plaintext_len, unwrap_key, wrap_key, LocalStorage, MediaType,
+    StorageBackend, DOCUMENTS,
+};
+
+fn master_key(id: &str, byte: u8) -> MasterKey {
+    MasterKey {
+        id: id.to_string(),
+        key: [byte; 32],
+    }
+}
+
+fn encrypted(data_key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    encrypt_to(data_key, plaintext, &mut output).unwrap();
+    output
+}
+
+#[test]
+fn test_data_key_opens_with_the_master_key_that_wrapped_it() {
+    // Arrange
+    let old = master_key("2024", 1);
+    let data_key = [7u8; 32];
+    let envelope = wrap_key(&old, &data_key);
+    let rotated = [master_key("2025", 2), master_key("2024", 1)];
+
+    // Act
+    let opened = unwrap_key(&rotated, &envelope).unwrap();
+    let without_old = unwrap_key(&rotated[..1], &envelope);
+    let mut relabelled = envelope.clone();
+    relabelled.key_id = "2025".to_string();
+    let under_other_id = unwrap_key(&rotated, &relabelled);
+
+    // Assert
+    assert_eq!(envelope.key_id, "2024");
+    assert_eq!(opened, data_key);
+    assert!(without_old.is_err());
+    assert!(under_other_id.is_err());
+}
+
+#[test]
+fn test_plaintext_size_is_recovered_from_the_encrypted_size() {
+    // Arrange
+    let data_key = [3u8; 32];
+
+    for size in [0, 1, 64 * 1024 - 1, 64 * 1024, 64 * 1024 + 1, 200_000] {
+        // Act
+        let output = encrypted(&data_key, &vec![b'x'; size]);
+
+        // Assert
+        assert_eq!(plaintext_len(output.len() as u64), Some(size as u64), "size {}", size);
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_document_reads_back_in_plaintext() {
+    // Arrange
+    let root = std::env::temp_dir().join(format!("encryption-test-{}", uuid::Uuid::new_v4()));
+    let storage = LocalStorage::new(&root);
+    tokio::fs::create_dir_all(&root).await.unwrap();
+    let master = [master_key("atual", 9)];
+    let data_key = [5u8; 32];
+    let envelope = wrap_key(&master[0], &data_key);
+    let plaintext: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+    let source = root.join("upload.tmp");
+    tokio::fs::write(&source, encrypted(&data_key, &plaintext)).await.unwrap();
+    let key = new_storage_key(DOCUMENTS, MediaType::Pdf);
+    storage.put(&key, &source).await.unwrap();
+
+    // Act
+    let object = open_document(&storage, &master, &key, Some(&envelope)).await.unwrap();
+    let size = object.size;
+    let chunks: Vec<_> = object.stream.try_collect().await.unwrap();
+    let sha256 = hash_document(&storage, &master, &key, Some(&envelope)).await.unwrap();
+
+    // Assert
+    assert_eq!(size, plaintext.len() as u64);
+    assert_eq!(chunks.concat(), plaintext);
+    assert_eq!(sha256, hex::encode(Sha256::digest(&plaintext)));
+
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_truncated_or_altered_document_does_not_decrypt() {
+    // Arrange
+    let root = std::env::temp_dir().join(format!("encryption-test-{}", uuid::Uuid::new_v4()));
+    let storage = LocalStorage::new(&root);
+    tokio::fs::create_dir_all(&root).await.unwrap();
+    let master = [master_key("atual", 9)];
+    let data_key = [5u8; 32];
+    let envelope = wrap_key(&master[0], &data_key);
+    let output = encrypted(&data_key, &vec![b'a'; 100_000]);
+    // Sem o último bloco, o que sobra termina num bloco completo que não está marcado como último
+    let truncated = output[..11 + 64 * 1024 + 16].to_vec();
+    let mut altered = output.clone();
+    altered[20] ^= 1;
+
+    let mut results = Vec::new();
+    for contents in [truncated, altered] {
+        let source = root.join("upload.tmp");
+        tokio::fs::write(&source, contents).await.unwrap();
+        let key = new_storage_key(DOCUMENTS, MediaType::Pdf);
+        storage.put(&key, &source).await.unwrap();
+
+        // Act
+        results.push(hash_document(&storage, &master, &key, Some(&envelope)).await);
+    }
+
+    // Assert
+    assert!(results.iter().all(Result::is_err));
+
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+}
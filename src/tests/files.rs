@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use crate::services::files::{legacy_path, parse_range, RangeRequest};
+
+#[test]
+fn test_single_byte_ranges_are_resolved() {
+    // Act / Assert
+    assert_eq!(parse_range("bytes=0-99", 1000), RangeRequest::Partial(0..100));
+    assert_eq!(parse_range("bytes=500-", 1000), RangeRequest::Partial(500..1000));
+    assert_eq!(parse_range("bytes=-200", 1000), RangeRequest::Partial(800..1000));
+    assert_eq!(parse_range("bytes=900-5000", 1000), RangeRequest::Partial(900..1000));
+    assert_eq!(parse_range("bytes=-5000", 1000), RangeRequest::Partial(0..1000));
+}
+
+#[test]
+fn test_ranges_past_the_end_are_unsatisfiable() {
+    // Act / Assert
+    assert_eq!(parse_range("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+}
+
+#[test]
+fn test_unsupported_ranges_fall_back_to_the_whole_file() {
+    // Act / Assert
+    assert_eq!(parse_range("bytes=0-1,5-6", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("bytes=50-10", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("bytes=abc-", 1000), RangeRequest::Full);
+}
+
+#[test]
+fn test_legacy_files_are_plain_names_in_the_legacy_directory() {
+    // Act / Assert
+    assert_eq!(legacy_path("./uploads", "report.pdf"), Some(PathBuf::from("./uploads/report.pdf")));
+    assert_eq!(legacy_path("./uploads", "photos/ab/cd/6f1c.jpg"), None);
+    assert_eq!(legacy_path("./uploads", "../secret.txt"), None);
+    assert_eq!(legacy_path("./uploads", "/etc/passwd"), None);
+    assert_eq!(legacy_path("./uploads", ""), None);
+}
@@ -0,0 +1,65 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+use crate::images::{fit_within, render_variants, variant_key, VariantFormat, VariantSize};
+use crate::storage::{is_storage_key, sniff, MediaType, PHOTOS};
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_variants_keep_aspect_ratio_and_never_upscale() {
+    // Act / Assert
+    assert_eq!(fit_within(4000, 3000, 1024), (1024, 768));
+    assert_eq!(fit_within(3000, 4000, 320), (240, 320));
+    assert_eq!(fit_within(5000, 10, 320), (320, 1));
+    assert_eq!(fit_within(800, 600, 1024), (800, 600));
+}
+
+#[test]
+fn test_variant_keys_sit_next_to_the_original() {
+    // Arrange
+    let original = "photos/ab/cd/abcd0123456789abcdef0123456789ab.jpg";
+
+    // Act
+    let key = variant_key(original, VariantSize::Thumbnail, VariantFormat::Webp);
+
+    // Assert
+    assert_eq!(key, "photos/ab/cd/abcd0123456789abcdef0123456789ab/thumbnail.webp");
+    assert!(!is_storage_key(PHOTOS, &key));
+}
+
+#[test]
+fn test_every_size_is_rendered_in_every_format() {
+    // Act
+    let variants = render_variants(&png(1500, 500), None).unwrap();
+
+    // Assert
+    assert_eq!(variants.len(), 6);
+    for variant in &variants {
+        let expected = match variant.size {
+            VariantSize::Large => (1500, 500),
+            VariantSize::Medium => (1024, 341),
+            VariantSize::Thumbnail => (320, 107),
+        };
+        assert_eq!((variant.width, variant.height), expected);
+        let media_type = if variant.format == VariantFormat::Webp { MediaType::Webp } else { MediaType::Jpeg };
+        assert_eq!(sniff(&variant.bytes), Some(media_type));
+    }
+}
+
+#[test]
+fn test_undecodable_photo_is_refused() {
+    // Arrange
+    let mut truncated = vec![0xFF, 0xD8, 0xFF, 0xE0];
+    truncated.extend_from_slice(&[0; 100]);
+
+    // Act / Assert
+    assert!(render_variants(&truncated, None).is_err());
+}
@@ -0,0 +1,48 @@
+use crate::mailer::{Email, FileMailer, Mailer};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_file_mailer_writes_message_to_maildir() {
+    // Arrange
+    let dir = std::env::temp_dir().join(format!("maildir-{}", Uuid::new_v4()));
+    let mailer = FileMailer::new(&dir, "no-reply@example.com".parse().unwrap());
+
+    // Act
+    mailer
+        .send(Email {
+            to: "coach@example.com".to_string(),
+            subject: "You have been invited".to_string(),
+            body: "Create your account here".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // Assert
+    let delivered: Vec<_> = std::fs::read_dir(dir.join("new")).unwrap().collect();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(std::fs::read_dir(dir.join("tmp")).unwrap().count(), 0);
+
+    let message = std::fs::read_to_string(delivered[0].as_ref().unwrap().path()).unwrap();
+    assert!(message.contains("To: coach@example.com"));
+    assert!(message.contains("Subject: You have been invited"));
+    assert!(message.contains("Create your account here"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_file_mailer_rejects_invalid_recipient() {
+    let dir = std::env::temp_dir().join(format!("maildir-{}", Uuid::new_v4()));
+    let mailer = FileMailer::new(&dir, "no-reply@example.com".parse().unwrap());
+
+    let result = mailer
+        .send(Email {
+            to: "not an address".to_string(),
+            subject: "Hello".to_string(),
+            body: "Hello".to_string(),
+        })
+        .await;
+
+    assert!(result.is_err());
+    assert!(!dir.join("new").exists());
+}
@@ -0,0 +1,130 @@
+use std::io::Cursor;
+
+use chrono::NaiveDate;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, RgbImage};
+
+use crate::storage::{strip_metadata, MediaType};
+
+const TAKEN_AT: &[u8] = b"2024:05:18 09:30:00\0";
+const LATITUDE: &[u8] = b"23S\0";
+
+// EXIF big-endian com orientação 6, DateTimeOriginal e um IFD de GPS
+fn exif_block() -> Vec<u8> {
+    fn entry(out: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32) {
+        out.extend_from_slice(&tag.to_be_bytes());
+        out.extend_from_slice(&kind.to_be_bytes());
+        out.extend_from_slice(&count.to_be_bytes());
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut tiff = b"MM\0*".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    // IFD0 (8..50): Orientation, ExifIFD -> 50, GPSInfo -> 88
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    entry(&mut tiff, 0x0112, 3, 1, 6 << 16);
+    entry(&mut tiff, 0x8769, 4, 1, 50);
+    entry(&mut tiff, 0x8825, 4, 1, 88);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    // Exif IFD (50..68): DateTimeOriginal -> 68
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    entry(&mut tiff, 0x9003, 2, 20, 68);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(TAKEN_AT);
+    // GPS IFD (88..): GPSLatitude guardada como texto só para ser achada no teste
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    entry(&mut tiff, 0x0002, 2, 4, u32::from_be_bytes(LATITUDE.try_into().unwrap()));
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+fn jpeg_with_exif() -> Vec<u8> {
+    let mut plain = Vec::new();
+    RgbImage::new(16, 8)
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut plain, 80))
+        .unwrap();
+
+    let mut app1 = b"Exif\0\0".to_vec();
+    app1.extend_from_slice(&exif_block());
+    let mut xmp = b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta>GPSLatitude</x:xmpmeta>".to_vec();
+    xmp.splice(0..0, ((xmp.len() + 2) as u16).to_be_bytes());
+
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+    jpeg.extend_from_slice(&app1);
+    jpeg.extend_from_slice(&[0xFF, 0xE1]);
+    jpeg.extend_from_slice(&xmp);
+    jpeg.extend_from_slice(&plain[2..]);
+    // Dados de fabricante depois do fim da imagem
+    jpeg.extend_from_slice(b"trailer with GPSLatitude");
+    jpeg
+}
+
+#[test]
+fn test_jpeg_metadata_is_read_and_removed() {
+    // Arrange
+    let original = jpeg_with_exif();
+
+    // Act
+    let (cleaned, metadata) = strip_metadata(MediaType::Jpeg, &original).unwrap();
+
+    // Assert
+    let taken_at = NaiveDate::from_ymd_opt(2024, 5, 18).unwrap().and_hms_opt(9, 30, 0).unwrap();
+    assert_eq!(metadata.taken_at, Some(taken_at));
+    assert_eq!(metadata.orientation, Some(6));
+    assert!(!contains(&cleaned, LATITUDE));
+    assert!(!contains(&cleaned, b"GPSLatitude"));
+    assert!(!contains(&cleaned, TAKEN_AT));
+    assert!(cleaned.ends_with(&[0xFF, 0xD9]));
+    assert!(image::load_from_memory(&cleaned).is_ok());
+
+    // A orientação continua no arquivo, sozinha
+    let (_, kept) = strip_metadata(MediaType::Jpeg, &cleaned).unwrap();
+    assert_eq!(kept.orientation, Some(6));
+    assert_eq!(kept.taken_at, None);
+}
+
+#[test]
+fn test_png_text_and_exif_chunks_are_removed() {
+    // Arrange
+    let mut plain = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+        .write_to(&mut Cursor::new(&mut plain), ImageFormat::Png)
+        .unwrap();
+    let exif = exif_block();
+    let mut chunks = Vec::new();
+    for (kind, data) in [(&b"eXIf"[..], &exif[..]), (b"tEXt", b"Comment\0GPSLatitude")] {
+        chunks.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunks.extend_from_slice(kind);
+        chunks.extend_from_slice(data);
+        chunks.extend_from_slice(&[0; 4]);
+    }
+    // Logo depois do IHDR (8 de assinatura + 25 do chunk)
+    let mut original = plain.clone();
+    original.splice(33..33, chunks);
+
+    // Act
+    let (cleaned, metadata) = strip_metadata(MediaType::Png, &original).unwrap();
+
+    // Assert
+    assert_eq!(metadata.orientation, Some(6));
+    assert!(metadata.taken_at.is_some());
+    assert!(!contains(&cleaned, b"GPSLatitude"));
+    assert!(!contains(&cleaned, LATITUDE));
+    assert!(image::load_from_memory(&cleaned).is_ok());
+}
+
+#[test]
+fn test_image_that_cannot_be_followed_is_refused() {
+    // Arrange
+    let mut truncated = jpeg_with_exif();
+    truncated.truncate(200);
+
+    // Act / Assert
+    assert!(strip_metadata(MediaType::Jpeg, &truncated).is_err());
+    assert!(strip_metadata(MediaType::Jpeg, &[0xFF, 0xD8, 0xFF, 0x00, 0x00]).is_err());
+    assert!(strip_metadata(MediaType::Png, b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR").is_err());
+}
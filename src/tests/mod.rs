@@ -0,0 +1,16 @@
+// documents.rs e tasks.rs são rascunhos antigos que não compilam; ficam de fora
+mod api_keys;
+mod cors;
+mod encryption;
+mod files;
+mod images;
+mod mailer;
+mod metadata;
+mod mp4;
+mod rate_limit;
+mod reconcile;
+mod signed_urls;
+mod storage;
+mod totp;
+mod video_uploads;
+mod zip;
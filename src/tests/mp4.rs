@@ -0,0 +1,105 @@
+use std::io::Cursor;
+
+use chrono::{TimeZone, Utc};
+
+use crate::storage::{probe_mp4, Mp4Error, VideoInfo};
+
+// 2024-05-18 09:30:00 UTC em segundos desde 1904
+const CREATED: u32 = 3_798_869_400;
+
+fn bx(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn concat(parts: &[Vec<u8>]) -> Vec<u8> {
+    parts.concat()
+}
+
+// mvhd e mdhd versão 0: flags, criação, modificação, escala de tempo, duração
+fn header(kind: &[u8; 4], timescale: u32, duration: u32, padding: usize) -> Vec<u8> {
+    let mut body = vec![0; 4];
+    for value in [CREATED, CREATED, timescale, duration] {
+        body.extend_from_slice(&value.to_be_bytes());
+    }
+    body.extend(vec![0; padding]);
+    bx(kind, &body)
+}
+
+fn track(handler: &[u8; 4], entry: Vec<u8>, samples: u32) -> Vec<u8> {
+    let mut hdlr = vec![0; 8];
+    hdlr.extend_from_slice(handler);
+    hdlr.extend_from_slice(&[0; 13]);
+
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend(entry);
+    let mut stts = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stts.extend_from_slice(&samples.to_be_bytes());
+    stts.extend_from_slice(&512u32.to_be_bytes());
+
+    let stbl = bx(b"stbl", &concat(&[bx(b"stsd", &stsd), bx(b"stts", &stts)]));
+    let mdia = concat(&[header(b"mdhd", 12800, 128_000, 4), bx(b"hdlr", &hdlr), bx(b"minf", &stbl)]);
+    bx(b"trak", &bx(b"mdia", &mdia))
+}
+
+fn video_entry(width: u16, height: u16) -> Vec<u8> {
+    let mut body = vec![0; 24];
+    body.extend_from_slice(&width.to_be_bytes());
+    body.extend_from_slice(&height.to_be_bytes());
+    body.extend(vec![0; 50]);
+    bx(b"avc1", &body)
+}
+
+fn movie(tracks: &[Vec<u8>]) -> Vec<u8> {
+    let mut moov = header(b"mvhd", 1000, 10_000, 80);
+    for track in tracks {
+        moov.extend_from_slice(track);
+    }
+    concat(&[bx(b"ftyp", b"isom\0\0\x02\0isomiso2avc1mp41"), bx(b"moov", &moov), bx(b"mdat", &[7; 1000])])
+}
+
+fn probe(file: &[u8]) -> Result<VideoInfo, Mp4Error> {
+    probe_mp4(&mut Cursor::new(file), file.len() as u64)
+}
+
+#[test]
+fn test_container_metadata_is_read() {
+    // Arrange
+    let file = movie(&[track(b"vide", video_entry(1920, 1080), 250), track(b"soun", bx(b"mp4a", &[0; 28]), 430)]);
+
+    // Act
+    let info = probe(&file).unwrap();
+
+    // Assert
+    assert_eq!(info.duration_seconds, Some(10.0));
+    assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+    assert_eq!(info.frame_rate, Some(25.0));
+    assert_eq!(info.video_codec.as_deref(), Some("avc1"));
+    assert_eq!(info.audio_codec.as_deref(), Some("mp4a"));
+    assert_eq!(info.bitrate, Some((file.len() as f64 * 0.8).round() as u64));
+    assert_eq!(info.recorded_at, Some(Utc.with_ymd_and_hms(2024, 5, 18, 9, 30, 0).unwrap()));
+}
+
+#[test]
+fn test_truncated_file_is_refused() {
+    // Arrange
+    let mut file = movie(&[track(b"vide", video_entry(640, 360), 250)]);
+    file.truncate(file.len() - 10);
+
+    // Act / Assert
+    assert!(matches!(probe(&file), Err(Mp4Error::Truncated)));
+    assert!(matches!(probe(&file[..file.len() - 995]), Err(Mp4Error::Truncated)));
+}
+
+#[test]
+fn test_files_without_a_movie_or_video_track_are_refused() {
+    // Arrange
+    let no_movie = concat(&[bx(b"ftyp", b"isom\0\0\x02\0"), bx(b"mdat", &[0; 100])]);
+    let audio_only = movie(&[track(b"soun", bx(b"mp4a", &[0; 28]), 430)]);
+
+    // Act / Assert
+    assert!(matches!(probe(&no_movie), Err(Mp4Error::Invalid(_))));
+    assert!(matches!(probe(&audio_only), Err(Mp4Error::Invalid(_))));
+}
@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant};
+
+use crate::{config::BucketConfig, rate_limit::{RateLimitError, RateLimiter}};
+
+const LIMIT: BucketConfig = BucketConfig { per_minute: 60, burst: 3 };
+
+#[test]
+fn test_bucket_allows_burst_then_rejects() {
+    // Arrange
+    let limiter = RateLimiter::new();
+    let now = Instant::now();
+
+    // Act
+    let results: Vec<_> = (0..4).map(|_| limiter.check_at("ip:1", LIMIT, now)).collect();
+
+    // Assert
+    assert!(results[..3].iter().all(Result::is_ok));
+    assert_eq!(results[3], Err(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_bucket_refills_over_time_and_keys_are_independent() {
+    // Arrange
+    let limiter = RateLimiter::new();
+    let now = Instant::now();
+    for _ in 0..3 {
+        limiter.check_at("user:a", LIMIT, now).unwrap();
+    }
+
+    // Act / Assert
+    assert!(limiter.check_at("user:a", LIMIT, now).is_err());
+    assert!(limiter.check_at("user:b", LIMIT, now).is_ok());
+    assert!(limiter.check_at("user:a", LIMIT, now + Duration::from_secs(1)).is_ok());
+    assert!(limiter.check_at("user:a", LIMIT, now + Duration::from_secs(1)).is_err());
+}
+
+#[test]
+fn test_retry_after_rounds_up() {
+    // Arrange
+    let error = RateLimitError { retry_after: Duration::from_millis(1500) };
+
+    // Act / Assert
+    assert_eq!(error.retry_after_secs(), 2);
+}
@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+
+use crate::reconcile::find_orphans;
+use crate::storage::ObjectInfo;
+
+fn object(key: &str, age_hours: i64) -> ObjectInfo {
+    ObjectInfo {
+        key: key.to_string(),
+        size: 10,
+        last_modified: Some(Utc::now() - Duration::hours(age_hours)),
+    }
+}
+
+#[test]
+fn test_only_old_unreferenced_objects_are_orphans() {
+    // Arrange
+    let referenced: HashSet<String> = ["photos/ab/cd/abcd.jpg".to_string(), "Fatura antiga.pdf".to_string()].into();
+    let objects = vec![
+        object("photos/ab/cd/abcd.jpg", 100),
+        object("Fatura antiga.pdf", 100),
+        object("Fatura Claro.pdf", 100),
+        object("documents/12/34/1234.pdf", 1),
+    ];
+
+    // Act
+    let orphans = find_orphans(objects, &referenced, Utc::now() - Duration::hours(24));
+
+    // Assert
+    let keys: Vec<&str> = orphans.iter().map(|object| object.key.as_str()).collect();
+    assert_eq!(keys, ["Fatura Claro.pdf"]);
+}
+
+#[test]
+fn test_hidden_and_undated_objects_are_never_orphans() {
+    // Arrange
+    let mut undated = object("videos/ab/cd/abcd.mp4", 100);
+    undated.last_modified = None;
+    let objects = vec![object(".tmp/resumable/abcd", 100), object("files/.partial", 100), undated];
+
+    // Act
+    let orphans = find_orphans(objects, &HashSet::new(), Utc::now());
+
+    // Assert
+    assert!(orphans.is_empty());
+}
@@ -0,0 +1,46 @@
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::signed_urls::UrlSigner;
+
+#[test]
+fn test_signature_verifies_until_expiry() {
+    // Arrange
+    let signer = UrlSigner::new("secret", Duration::minutes(15));
+    let id = Uuid::new_v4();
+    let signature = signer.sign(id, 1_000);
+
+    // Act / Assert
+    assert!(signer.verify(id, 1_000, &signature, 999));
+    assert!(signer.verify(id, 1_000, &signature, 1_000));
+    assert!(!signer.verify(id, 1_000, &signature, 1_001));
+}
+
+#[test]
+fn test_signature_is_bound_to_id_expiry_and_key() {
+    // Arrange
+    let signer = UrlSigner::new("secret", Duration::minutes(15));
+    let other_key = UrlSigner::new("other", Duration::minutes(15));
+    let id = Uuid::new_v4();
+    let signature = signer.sign(id, 1_000);
+
+    // Act / Assert
+    assert!(!signer.verify(Uuid::new_v4(), 1_000, &signature, 0));
+    assert!(!signer.verify(id, 2_000, &signature, 0));
+    assert!(!other_key.verify(id, 1_000, &signature, 0));
+    assert!(!signer.verify(id, 1_000, "not-hex", 0));
+}
+
+#[test]
+fn test_signed_path_points_to_files_endpoint() {
+    // Arrange
+    let signer = UrlSigner::new("secret", Duration::minutes(15));
+    let id = Uuid::new_v4();
+
+    // Act
+    let path = signer.signed_path(id);
+
+    // Assert
+    assert!(path.starts_with(&format!("/api/files/{}?exp=", id)));
+    assert!(path.contains("&sig="));
+}
@@ -0,0 +1,51 @@
+use crate::auth::totp::{code_at, generate_recovery_codes, generate_secret, matching_step};
+
+// Segredo e instantes do apêndice B da RFC 6238 (SHA-1); os códigos são os 6 últimos dígitos
+const RFC_SECRET: &[u8] = b"12345678901234567890";
+const RFC_SECRET_BASE32: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+#[test]
+fn test_code_at_matches_rfc_6238_vectors() {
+    // Arrange
+    let vectors = [
+        (59, 287082),
+        (1111111109, 81804),
+        (1111111111, 50471),
+        (1234567890, 5924),
+        (2000000000, 279037),
+    ];
+
+    for (unix_time, expected) in vectors {
+        // Act
+        let code = code_at(RFC_SECRET, unix_time / 30);
+
+        // Assert
+        assert_eq!(code, expected, "unix time {}", unix_time);
+    }
+}
+
+#[test]
+fn test_matching_step_accepts_adjacent_steps_only() {
+    // Arrange
+    let unix_time = 1234567890;
+    let step = unix_time / 30;
+
+    // Act / Assert
+    assert_eq!(matching_step(RFC_SECRET_BASE32, "005924", unix_time), Some(step));
+    assert_eq!(matching_step(RFC_SECRET_BASE32, "005924", unix_time + 30), Some(step));
+    assert_eq!(matching_step(RFC_SECRET_BASE32, "005924", unix_time + 90), None);
+    assert_eq!(matching_step(RFC_SECRET_BASE32, "5924", unix_time), None);
+    assert_eq!(matching_step(RFC_SECRET_BASE32, "abcdef", unix_time), None);
+}
+
+#[test]
+fn test_generated_secret_and_recovery_codes() {
+    // Act
+    let secret = generate_secret();
+    let codes = generate_recovery_codes();
+
+    // Assert
+    assert_eq!(secret.len(), 32);
+    assert_eq!(codes.len(), 10);
+    assert!(codes.iter().all(|code| code.len() == 11 && code.as_bytes()[5] == b'-'));
+}
@@ -0,0 +1,20 @@
+use crate::services::video_uploads::parse_metadata;
+
+#[test]
+fn test_upload_metadata_is_decoded() {
+    // Act
+    let metadata = parse_metadata("filename am9nby5tcDQ=, student_id MTIzNA==,is_confidential").unwrap();
+
+    // Assert
+    assert_eq!(metadata["filename"], "jogo.mp4");
+    assert_eq!(metadata["student_id"], "1234");
+    assert_eq!(metadata["is_confidential"], "");
+    assert!(parse_metadata("").unwrap().is_empty());
+}
+
+#[test]
+fn test_invalid_upload_metadata_is_rejected() {
+    // Act / Assert
+    assert!(parse_metadata("filename not-base64!").is_none());
+    assert!(parse_metadata("filename //79").is_none());
+}
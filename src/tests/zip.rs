@@ -0,0 +1,96 @@
+use chrono::{TimeZone, Utc};
+
+use crate::zip::{Crc32, ZipWriter};
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[test]
+fn test_crc32_of_pieces_matches_the_reference_value() {
+    // Arrange
+    let mut crc = Crc32::new();
+
+    // Act
+    crc.update(b"12345");
+    crc.update(b"6789");
+
+    // Assert
+    assert_eq!(crc.finish(), 0xCBF4_3926);
+}
+
+#[test]
+fn test_archive_ends_with_a_directory_pointing_at_its_entries() {
+    // Arrange
+    let modified = Utc.with_ymd_and_hms(2025, 1, 27, 15, 41, 20).unwrap();
+    let mut zip = ZipWriter::default();
+    let mut archive = Vec::new();
+
+    // Act
+    for (name, contents) in [("photos/a.jpg", &b"first"[..]), ("manifest.json", &b"{}"[..])] {
+        archive.extend(zip.start_file(name, contents.len() as u64, modified));
+        archive.extend_from_slice(contents);
+        let mut crc = Crc32::new();
+        crc.update(contents);
+        archive.extend(zip.end_file(crc.finish(), contents.len() as u64).unwrap());
+    }
+    let data_size = archive.len();
+    archive.extend(zip.finish());
+
+    // Assert
+    assert_eq!(u32_at(&archive, 0), 0x0403_4b50);
+    let end = archive.len() - 22;
+    assert_eq!(u32_at(&archive, end), 0x0605_4b50);
+    assert_eq!(u16_at(&archive, end + 10), 2);
+    assert_eq!(u32_at(&archive, end + 16) as usize, data_size);
+    assert_eq!(u32_at(&archive, end + 12) as usize, end - data_size);
+
+    let second = u32_at(&archive, data_size + 46 + "photos/a.jpg".len() + 42) as usize;
+    assert_eq!(u32_at(&archive, second), 0x0403_4b50);
+    assert_eq!(&archive[second + 30..second + 30 + "manifest.json".len()], b"manifest.json");
+}
+
+#[test]
+fn test_entries_over_4_gib_get_zip64_records() {
+    // Arrange
+    let size = 5 * 1024 * 1024 * 1024u64;
+    let mut zip = ZipWriter::default();
+
+    // Act
+    let header = zip.start_file("videos/long.mp4", size, Utc::now());
+    let descriptor = zip.end_file(0x1234_5678, size).unwrap();
+    let directory_offset = header.len() as u64 + size + descriptor.len() as u64;
+    let end = zip.finish();
+
+    // Assert
+    assert_eq!(u16_at(&header, 4), 45);
+    assert_eq!(u32_at(&header, 22), 0xFFFF_FFFF);
+    assert_eq!(descriptor.len(), 24);
+    assert_eq!(u64_at(&descriptor, 8), size);
+
+    let zip64_end = end.len() - 22 - 20 - 56;
+    assert_eq!(u32_at(&end, zip64_end), 0x0606_4b50);
+    assert_eq!(u64_at(&end, zip64_end + 48), directory_offset);
+    assert_eq!(u32_at(&end, end.len() - 22 + 16), 0xFFFF_FFFF);
+}
+
+#[test]
+fn test_file_larger_than_announced_is_refused() {
+    // Arrange
+    let mut zip = ZipWriter::default();
+    zip.start_file("documents/a.pdf", 10, Utc::now());
+
+    // Act
+    let result = zip.end_file(0, 5 * 1024 * 1024 * 1024);
+
+    // Assert
+    assert!(result.is_err());
+}
@@ -0,0 +1,242 @@
+use std::io;
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+// Campos de 32 bits saturados indicam que o valor está no extra ZIP64
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+const ZIP64_ENTRY_LIMIT: usize = 0xFFFF;
+// 2.0 para arquivos comuns, 4.5 quando há extensões ZIP64
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// Bit 3: CRC e tamanhos vêm depois dos dados; bit 11: nomes em UTF-8
+const FLAGS: u16 = 0x0808;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE) of data fed in pieces, as ZIP entries need it.
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 = CRC_TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+struct Entry {
+    name: String,
+    dos_time: u16,
+    dos_date: u16,
+    offset: u64,
+    crc: u32,
+    size: u64,
+    zip64: bool,
+}
+
+/// Writes a ZIP archive front to back without seeking, so it can be streamed while the
+/// files are read. Entries are stored uncompressed; their CRC and size follow the data in
+/// a descriptor. ZIP64 records are added where sizes, offsets or the entry count need
+/// them. The caller sends the bytes each call returns, in order, with each file's
+/// contents between [`ZipWriter::start_file`] and [`ZipWriter::end_file`].
+#[derive(Default)]
+pub struct ZipWriter {
+    offset: u64,
+    entries: Vec<Entry>,
+}
+
+fn dos_datetime(modified: DateTime<Utc>) -> (u16, u16) {
+    // O formato do DOS começa em 1980
+    if modified.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (modified.hour() << 11) | (modified.minute() << 5) | (modified.second() / 2);
+    let date = (((modified.year() - 1980).min(127) as u32) << 9) | (modified.month() << 5) | modified.day();
+    (time as u16, date as u16)
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn saturated(value: u64) -> u32 {
+    value.min(ZIP64_LIMIT) as u32
+}
+
+impl ZipWriter {
+    /// Local header of a file expected to hold `size` bytes; its contents come next.
+    pub fn start_file(&mut self, name: &str, size: u64, modified: DateTime<Utc>) -> Vec<u8> {
+        let (dos_time, dos_date) = dos_datetime(modified);
+        let zip64 = size >= ZIP64_LIMIT;
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        put_u32(&mut header, 0x0403_4b50);
+        put_u16(&mut header, if zip64 { VERSION_ZIP64 } else { VERSION });
+        put_u16(&mut header, FLAGS);
+        put_u16(&mut header, 0); // sem compressão
+        put_u16(&mut header, dos_time);
+        put_u16(&mut header, dos_date);
+        put_u32(&mut header, 0);
+        // Com o bit 3, os tamanhos reais ficam no descritor
+        put_u32(&mut header, if zip64 { ZIP64_LIMIT as u32 } else { 0 });
+        put_u32(&mut header, if zip64 { ZIP64_LIMIT as u32 } else { 0 });
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, if zip64 { 20 } else { 0 });
+        header.extend_from_slice(name.as_bytes());
+        if zip64 {
+            put_u16(&mut header, 0x0001);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, 0);
+            put_u64(&mut header, 0);
+        }
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            dos_time,
+            dos_date,
+            offset: self.offset,
+            crc: 0,
+            size: 0,
+            zip64,
+        });
+        self.offset += header.len() as u64;
+        header
+    }
+
+    /// Data descriptor closing the current file, once `written` bytes with checksum `crc`
+    /// were sent. Fails when a file announced under 4 GiB turned out larger.
+    pub fn end_file(&mut self, crc: u32, written: u64) -> io::Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .last_mut()
+            .ok_or_else(|| io::Error::other("no file was started"))?;
+        if !entry.zip64 && written >= ZIP64_LIMIT {
+            return Err(io::Error::other(format!("{} is larger than announced", entry.name)));
+        }
+        entry.crc = crc;
+        entry.size = written;
+
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, 0x0807_4b50);
+        put_u32(&mut descriptor, crc);
+        if entry.zip64 {
+            put_u64(&mut descriptor, written);
+            put_u64(&mut descriptor, written);
+        } else {
+            put_u32(&mut descriptor, written as u32);
+            put_u32(&mut descriptor, written as u32);
+        }
+        self.offset += written + descriptor.len() as u64;
+        Ok(descriptor)
+    }
+
+    /// Central directory and end records; nothing may follow.
+    pub fn finish(self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let directory_offset = self.offset;
+
+        for entry in &self.entries {
+            let mut extra = Vec::new();
+            if entry.size >= ZIP64_LIMIT {
+                put_u64(&mut extra, entry.size);
+                put_u64(&mut extra, entry.size);
+            }
+            if entry.offset >= ZIP64_LIMIT {
+                put_u64(&mut extra, entry.offset);
+            }
+            let zip64 = entry.zip64 || !extra.is_empty();
+            let version = if zip64 { VERSION_ZIP64 } else { VERSION };
+
+            put_u32(&mut out, 0x0201_4b50);
+            put_u16(&mut out, version);
+            put_u16(&mut out, version);
+            put_u16(&mut out, FLAGS);
+            put_u16(&mut out, 0);
+            put_u16(&mut out, entry.dos_time);
+            put_u16(&mut out, entry.dos_date);
+            put_u32(&mut out, entry.crc);
+            put_u32(&mut out, saturated(entry.size));
+            put_u32(&mut out, saturated(entry.size));
+            put_u16(&mut out, entry.name.len() as u16);
+            put_u16(&mut out, if extra.is_empty() { 0 } else { extra.len() as u16 + 4 });
+            put_u16(&mut out, 0); // comentário
+            put_u16(&mut out, 0); // disco
+            put_u16(&mut out, 0); // atributos internos
+            put_u32(&mut out, 0); // atributos externos
+            put_u32(&mut out, saturated(entry.offset));
+            out.extend_from_slice(entry.name.as_bytes());
+            if !extra.is_empty() {
+                put_u16(&mut out, 0x0001);
+                put_u16(&mut out, extra.len() as u16);
+                out.extend_from_slice(&extra);
+            }
+        }
+
+        let directory_size = out.len() as u64;
+        let count = self.entries.len();
+        if count >= ZIP64_ENTRY_LIMIT || directory_offset >= ZIP64_LIMIT || directory_size >= ZIP64_LIMIT {
+            let record_offset = directory_offset + directory_size;
+            put_u32(&mut out, 0x0606_4b50);
+            put_u64(&mut out, 44);
+            put_u16(&mut out, VERSION_ZIP64);
+            put_u16(&mut out, VERSION_ZIP64);
+            put_u32(&mut out, 0);
+            put_u32(&mut out, 0);
+            put_u64(&mut out, count as u64);
+            put_u64(&mut out, count as u64);
+            put_u64(&mut out, directory_size);
+            put_u64(&mut out, directory_offset);
+
+            put_u32(&mut out, 0x0706_4b50);
+            put_u32(&mut out, 0);
+            put_u64(&mut out, record_offset);
+            put_u32(&mut out, 1);
+        }
+
+        put_u32(&mut out, 0x0605_4b50);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, count.min(ZIP64_ENTRY_LIMIT) as u16);
+        put_u16(&mut out, count.min(ZIP64_ENTRY_LIMIT) as u16);
+        put_u32(&mut out, saturated(directory_size));
+        put_u32(&mut out, saturated(directory_offset));
+        put_u16(&mut out, 0);
+        out
+    }
+}